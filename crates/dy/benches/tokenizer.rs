@@ -0,0 +1,70 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use dy::{lexer::tokenize_into_lines, spec::ValidDYSpec};
+use plx_like::EXO_SPEC;
+
+/// A spec similar to the PLX exo spec, just to have realistic keys
+mod plx_like {
+    use dy::spec::{DYSpec, KeySpec, ValueType};
+
+    const SEE: &KeySpec = &KeySpec {
+        id: "see",
+        desc: "bench",
+        subkeys: &[],
+        vt: ValueType::Multiline,
+        required: true,
+        ..KeySpec::DEFAULT
+    };
+    const TYPE: &KeySpec = &KeySpec {
+        id: "type",
+        desc: "bench",
+        subkeys: &[],
+        vt: ValueType::SingleLine,
+        ..KeySpec::DEFAULT
+    };
+    const CHECK: &KeySpec = &KeySpec {
+        id: "check",
+        desc: "bench",
+        subkeys: &[SEE, TYPE],
+        vt: ValueType::SingleLine,
+        required: true,
+        ..KeySpec::DEFAULT
+    };
+    const EXO: &KeySpec = &KeySpec {
+        id: "exo",
+        desc: "bench",
+        subkeys: &[CHECK],
+        vt: ValueType::Multiline,
+        max_occurs: Some(1),
+        required: true,
+        ..KeySpec::DEFAULT
+    };
+    pub const EXO_SPEC: &DYSpec = &[EXO];
+}
+
+/// Generate a big fence-free and comment-free exo file
+fn generate_exo(checks: usize) -> String {
+    let mut content = String::from("exo A big exo\nwith a long instruction\n\n");
+    for i in 0..checks {
+        content.push_str(&format!(
+            "check number {i}\nsee What is your name ?\ntype John\nsee Hello John\n\n"
+        ));
+    }
+    content
+}
+
+fn bench_tokenizer(c: &mut Criterion) {
+    let spec = ValidDYSpec::new(EXO_SPEC).unwrap();
+    let fast_content = generate_exo(10_000);
+    // A single comment anywhere forces the general path
+    let general_content = format!("// a comment\n{fast_content}");
+
+    c.bench_function("tokenize fast path", |b| {
+        b.iter(|| tokenize_into_lines(&spec, &fast_content))
+    });
+    c.bench_function("tokenize general path", |b| {
+        b.iter(|| tokenize_into_lines(&spec, &general_content))
+    });
+}
+
+criterion_group!(benches, bench_tokenizer);
+criterion_main!(benches);
@@ -12,24 +12,27 @@ pub mod tests {
         desc: "test",
         subkeys: &[],
         vt: ValueType::Multiline,
-        once: true,
+        max_occurs: Some(1),
         required: true,
+        ..KeySpec::DEFAULT
     };
     pub const CODE_SPEC: &KeySpec = &KeySpec {
         id: "code",
         desc: "test",
         subkeys: &[],
         vt: ValueType::SingleLine,
-        once: true,
+        max_occurs: Some(1),
         required: true,
+        ..KeySpec::DEFAULT
     };
     pub const COURSE_SPEC: &KeySpec = &KeySpec {
         id: "course",
         desc: "test",
         subkeys: &[CODE_SPEC, GOAL_SPEC],
         vt: ValueType::SingleLine,
-        once: true,
+        max_occurs: Some(1),
         required: true,
+        ..KeySpec::DEFAULT
     };
     pub const TESTING_COURSE_SPEC: &DYSpec = &[COURSE_SPEC];
 
@@ -38,16 +41,15 @@ pub mod tests {
         desc: "test",
         subkeys: &[],
         vt: ValueType::Multiline,
-        once: false,
-        required: false,
+        ..KeySpec::DEFAULT
     };
     pub const SKILL_SPEC: &KeySpec = &KeySpec {
         id: "skill",
         desc: "test",
         subkeys: &[SUBSKILL_SPEC],
         vt: ValueType::Multiline,
-        once: false,
         required: true,
+        ..KeySpec::DEFAULT
     };
     pub const TESTING_SKILLS_SPEC: &DYSpec = &[SKILL_SPEC];
 
@@ -56,48 +58,48 @@ pub mod tests {
         desc: "test",
         subkeys: &[],
         vt: ValueType::SingleLine,
-        once: true,
-        required: false,
+        max_occurs: Some(1),
+        ..KeySpec::DEFAULT
     };
     pub const SEE_SPEC: &KeySpec = &KeySpec {
         id: "see",
         desc: "test",
         subkeys: &[],
         vt: ValueType::Multiline,
-        once: false,
         required: true,
+        ..KeySpec::DEFAULT
     };
     pub const TYPE_SPEC: &KeySpec = &KeySpec {
         id: "type",
         desc: "test",
         subkeys: &[],
         vt: ValueType::SingleLine,
-        once: false,
-        required: false,
+        ..KeySpec::DEFAULT
     };
     pub const EXIT_SPEC: &KeySpec = &KeySpec {
         id: "exit",
         desc: "test",
         subkeys: &[],
         vt: ValueType::SingleLine,
-        once: true,
-        required: false,
+        max_occurs: Some(1),
+        ..KeySpec::DEFAULT
     };
     pub const CHECK_SPEC: &KeySpec = &KeySpec {
         id: "check",
         desc: "test",
         subkeys: &[ARGS_SPEC, SEE_SPEC, TYPE_SPEC, EXIT_SPEC],
         vt: ValueType::SingleLine,
-        once: false,
         required: true,
+        ..KeySpec::DEFAULT
     };
     pub const EXO_SPEC: &KeySpec = &KeySpec {
         id: "exo",
         desc: "test",
         subkeys: &[CHECK_SPEC],
         vt: ValueType::Multiline,
-        once: true, // for now, only one exo per file
+        max_occurs: Some(1), // for now, only one exo per file
         required: true,
+        ..KeySpec::DEFAULT
     };
     pub const TESTING_EXOS_SPEC: &DYSpec = &[EXO_SPEC];
 }
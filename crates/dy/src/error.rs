@@ -2,7 +2,9 @@ use lsp_types::Range;
 use serde::{Deserialize, Serialize};
 use serde_with::SerializeDisplay;
 
-use crate::lexer::COMMENT_PREFIX;
+use crate::lexer::BLOCK_COMMENT_END;
+use crate::parser::NiceRange;
+use std::fmt::Debug;
 
 #[derive(
     Deserialize, Debug, thiserror::Error, Clone, Eq, PartialEq, PartialOrd, Ord, SerializeDisplay,
@@ -11,23 +13,162 @@ pub enum ParseErrorType {
     // Blocks tree building errors
     #[error("The '{0}' key can be only used under a `{1}`")]
     WrongKeyPosition(String, String),
+    /// A WrongKeyPosition with ParseOptions::recover_orphans, the key is kept in a synthetic parent
+    #[error("The '{0}' key can be only used under a `{1}`, it is kept as if it was under one")]
+    RecoveredOrphanKey(String, String),
     #[error("The '{0}' key can only be used once {level}", level = if *.1 == 0 {"in the document root"} else {"at this level"})]
     DuplicatedKey(String, u8),
+    #[error("The {1} subblock(s) of this duplicated '{0}' key are ignored with it")]
+    DroppedSubblocks(String, usize),
+    #[error(
+        "The '{0}' key is merged with a previous one that already has a value, this value is ignored"
+    )]
+    ConflictingMergedValue(String),
     #[error("Invalid multiline content found after the '{0}' key which is single line")]
     InvalidMultilineContent(String),
+    /// The comment prefix of the spec is given for the hint
     #[error(
-        "This content is not associated to any valid key.\nHint: maybe this should be a comment starting with {} or it needs a valid key as a prefix?",
-        COMMENT_PREFIX
+        "This content is not associated to any valid key.\nHint: maybe this should be a comment starting with {0} or it needs a valid key as a prefix?"
     )]
-    ContentOutOfKey,
+    ContentOutOfKey(String),
     #[error("Missing required key '{0}'")]
     MissingRequiredKey(String),
     #[error("Missing a value for the required key '{0}'")]
     MissingRequiredValue(String),
+    #[error("The '{0}' key can only be used {1} times at this level")]
+    TooManyOccurrences(String, u32),
+    #[error("The '{0}' key must be used at least {1} times here")]
+    TooFewOccurrences(String, u32),
+    #[error("The list value of the '{0}' key contains an empty entry")]
+    EmptyListEntry(String),
+    #[error(
+        "Couldn't parse '{0}' as a duration, it must be a number followed by ms, s or m, like 500ms, 2s or 1m"
+    )]
+    InvalidDuration(String),
+    #[error("Couldn't parse '{0}' as an integer, it must be a signed 32 bits integer like 2 or -1")]
+    InvalidInteger(String),
+    #[error("Couldn't parse '{0}' as a boolean, it must be true or false")]
+    InvalidBool(String),
+    #[error("The value of the '{0}' key doesn't match the pattern {1}")]
+    PatternMismatch(String, String),
+    /// The allowed values are boxed to keep ParseError small
+    #[error("The value '{1}' is not valid for the '{0}' key, it must be one of: {allowed}", allowed = .2.join(", "))]
+    InvalidEnumValue(String, String, Box<Vec<String>>),
+    #[error(
+        "This block comment is never closed, all the following lines are ignored.\nHint: add a line with {} to close it",
+        BLOCK_COMMENT_END
+    )]
+    UnterminatedBlockComment,
+    #[error("The blocks are nested deeper than {0} levels, the deeper lines are ignored")]
+    MaxDepthExceeded(u8),
 
     /// An error generated by the FromDYBlock::from_block_with_validation()
     #[error("{0}")]
     ValidationError(String),
+
+    // Optional lints, enabled via ParseOptions
+    #[error("This line contains an odd number of backticks, an inline code span is not terminated")]
+    UnbalancedInlineCode,
+    #[error("This looks like the '{0}' key being typed, it is not complete yet")]
+    PartialKey(String),
+    #[error("The value starts with the '{0}' key again, the key was probably typed twice")]
+    RepeatedKeyInValue(String),
+    #[error(
+        "This line starts like the '{0}' key but is part of the value above.\nHint: write the key in lowercase at the start of the line to start a new block"
+    )]
+    PossibleMissingKey(String),
+    #[error(
+        "This line starts with the '{0}' key but it cannot be used here, it was probably meant to be part of the previous value.\nHint: escape the key with a backslash like \\{0} to keep the line in the value"
+    )]
+    AmbiguousKeyLikeValue(String),
+    #[error("This line is longer than the maximum of {0} characters")]
+    LineTooLong(u32),
+    // Warnings of specs
+    #[error(
+        "This check expects the program to fail but doesn't assert any output after the last `type`, so the error message is not verified.\nHint: add a `see` with the expected error message at the end"
+    )]
+    WeakErrorCheck,
+}
+
+/// The severity of a ParseErrorType, warnings are mostly generated by optional lints
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl ParseErrorType {
+    /// The id of the key concerned by this error, if any
+    pub fn key_id(&self) -> Option<&str> {
+        match self {
+            ParseErrorType::WrongKeyPosition(id, _)
+            | ParseErrorType::RecoveredOrphanKey(id, _)
+            | ParseErrorType::DuplicatedKey(id, _)
+            | ParseErrorType::DroppedSubblocks(id, _)
+            | ParseErrorType::ConflictingMergedValue(id)
+            | ParseErrorType::InvalidMultilineContent(id)
+            | ParseErrorType::MissingRequiredKey(id)
+            | ParseErrorType::MissingRequiredValue(id)
+            | ParseErrorType::TooManyOccurrences(id, _)
+            | ParseErrorType::TooFewOccurrences(id, _)
+            | ParseErrorType::EmptyListEntry(id)
+            | ParseErrorType::PatternMismatch(id, _)
+            | ParseErrorType::InvalidEnumValue(id, ..)
+            | ParseErrorType::PartialKey(id)
+            | ParseErrorType::RepeatedKeyInValue(id)
+            | ParseErrorType::PossibleMissingKey(id)
+            | ParseErrorType::AmbiguousKeyLikeValue(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// A short name of the error kind, in kebab case, used in `dy:allow(...)` directives
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseErrorType::WrongKeyPosition(..) => "wrong-key-position",
+            ParseErrorType::RecoveredOrphanKey(..) => "recovered-orphan-key",
+            ParseErrorType::DuplicatedKey(..) => "duplicated-key",
+            ParseErrorType::DroppedSubblocks(..) => "dropped-subblocks",
+            ParseErrorType::ConflictingMergedValue(_) => "conflicting-merged-value",
+            ParseErrorType::InvalidMultilineContent(_) => "invalid-multiline-content",
+            ParseErrorType::ContentOutOfKey(_) => "content-out-of-key",
+            ParseErrorType::MissingRequiredKey(_) => "missing-required-key",
+            ParseErrorType::MissingRequiredValue(_) => "missing-required-value",
+            ParseErrorType::TooManyOccurrences(..) => "too-many-occurrences",
+            ParseErrorType::TooFewOccurrences(..) => "too-few-occurrences",
+            ParseErrorType::EmptyListEntry(_) => "empty-list-entry",
+            ParseErrorType::InvalidDuration(_) => "invalid-duration",
+            ParseErrorType::InvalidInteger(_) => "invalid-integer",
+            ParseErrorType::InvalidBool(_) => "invalid-bool",
+            ParseErrorType::PatternMismatch(..) => "pattern-mismatch",
+            ParseErrorType::InvalidEnumValue(..) => "invalid-enum-value",
+            ParseErrorType::UnterminatedBlockComment => "unterminated-block-comment",
+            ParseErrorType::MaxDepthExceeded(_) => "max-depth-exceeded",
+            ParseErrorType::ValidationError(_) => "validation-error",
+            ParseErrorType::UnbalancedInlineCode => "unbalanced-inline-code",
+            ParseErrorType::PartialKey(_) => "partial-key",
+            ParseErrorType::RepeatedKeyInValue(_) => "repeated-key-in-value",
+            ParseErrorType::PossibleMissingKey(_) => "possible-missing-key",
+            ParseErrorType::AmbiguousKeyLikeValue(_) => "ambiguous-key-like-value",
+            ParseErrorType::LineTooLong(_) => "line-too-long",
+            ParseErrorType::WeakErrorCheck => "weak-error-check",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            ParseErrorType::UnbalancedInlineCode
+            | ParseErrorType::PartialKey(_)
+            | ParseErrorType::RepeatedKeyInValue(_)
+            | ParseErrorType::PossibleMissingKey(_)
+            | ParseErrorType::AmbiguousKeyLikeValue(_)
+            | ParseErrorType::DroppedSubblocks(..)
+            | ParseErrorType::RecoveredOrphanKey(..)
+            | ParseErrorType::LineTooLong(_)
+            | ParseErrorType::WeakErrorCheck => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
 }
 
 /// Implement ordering to sort errors by range start position.
@@ -38,11 +179,17 @@ impl Ord for ParseError {
             self.range.start.line,
             self.range.start.character,
             &self.error, // include the error variant to make sure
+            &self.path,
+            &self.some_file,
+            self.severity,
         )
             .cmp(&(
                 other.range.start.line,
                 other.range.start.character,
                 &other.error,
+                &other.path,
+                &other.some_file,
+                other.severity,
             ))
     }
 }
@@ -53,9 +200,84 @@ impl PartialOrd for ParseError {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
 #[typeshare::typeshare]
 pub struct ParseError {
     pub range: Range,
     pub error: ParseErrorType,
+    /// The entities containing the error from the outermost one, like `skill 'Classes'`, so errors
+    /// of nested entities can be attributed. It is filled by the FromDYBlock implementations that
+    /// want it, it's empty otherwise.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<String>,
+    /// The file containing the error, when errors of several files are aggregated by
+    /// ParseResult::merge_all(). It's None otherwise, the file is given by the ParseResult.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub some_file: Option<String>,
+    /// The severity of this error when it differs from the one of its type, like for the errors
+    /// reported as warnings with ParseOptions::preserve_all, see ParseError::severity()
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+}
+
+impl ParseError {
+    /// An error on the given range, without path nor file
+    pub fn new(range: Range, error: ParseErrorType) -> Self {
+        ParseError {
+            range,
+            error,
+            path: vec![],
+            some_file: None,
+            severity: None,
+        }
+    }
+
+    /// The severity of this error, the one of its type unless it was changed
+    pub fn severity(&self) -> Severity {
+        self.severity.unwrap_or_else(|| self.error.severity())
+    }
+
+    /// Add the given entity at the start of the path, when returning the error to the parent entity
+    pub fn prefix_path(&mut self, entity: String) {
+        self.path.insert(0, entity);
+    }
+}
+
+// Implement Debug so we can have a shorter display of Range, like Block
+impl Debug for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("ParseError");
+        debug
+            .field("range", &NiceRange(&self.range))
+            .field("error", &self.error);
+        if !self.path.is_empty() {
+            debug.field("path", &self.path);
+        }
+        if let Some(file) = &self.some_file {
+            debug.field("some_file", file);
+        }
+        if let Some(severity) = &self.severity {
+            debug.field("severity", severity);
+        }
+        debug.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{ParseError, ParseErrorType};
+    use crate::range_on_line_part;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_error_debug_is_compact() {
+        let error = ParseError::new(
+            range_on_line_part(3, 5, 11),
+            ParseErrorType::DuplicatedKey("code".to_string(), 1),
+        );
+        assert_eq!(
+            format!("{error:?}"),
+            "ParseError { range: 3:5-3:11, error: DuplicatedKey(\"code\", 1) }"
+        );
+    }
 }
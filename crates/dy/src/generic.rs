@@ -0,0 +1,115 @@
+/// Generic parsing without a dedicated Rust type, for tools handling several kinds of DY files
+use std::path::Path;
+
+use crate::{
+    FromDYBlock, ParseResult, error::ParseError, parse_with_spec, parser::Block, spec::ValidDYSpec,
+};
+use serde::Serialize;
+
+/// A generic value extracted from a block, with the values of its subblocks as children
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+pub struct DyValue {
+    /// The id of the key of the block
+    pub key: String,
+    /// The joined and trimmed text of the block
+    pub value: String,
+    pub children: Vec<DyValue>,
+}
+
+impl<'a> FromDYBlock<'a> for DyValue {
+    fn from_block_with_validation(block: &Block<'a>) -> (Vec<ParseError>, Self) {
+        (
+            Vec::new(),
+            DyValue {
+                key: block.key.id.to_string(),
+                value: block.get_joined_text(),
+                children: block
+                    .subblocks
+                    .iter()
+                    .map(|b| DyValue::from_block_with_validation(b).1)
+                    .collect(),
+            },
+        )
+    }
+}
+
+pub type GenericParseResult = ParseResult<DyValue>;
+
+/// A registry of specs, each one associated to the file name it must be used for
+#[derive(Debug, Default)]
+pub struct SpecRegistry<'a> {
+    specs: Vec<(&'a str, ValidDYSpec<'a>)>,
+}
+
+impl<'a> SpecRegistry<'a> {
+    pub fn new() -> Self {
+        SpecRegistry { specs: Vec::new() }
+    }
+
+    /// Register the spec to use for files with the given name (not a path), like `course.dy`
+    pub fn register(&mut self, file_name: &'a str, spec: ValidDYSpec<'a>) -> &mut Self {
+        self.specs.push((file_name, spec));
+        self
+    }
+
+    /// Get the spec registered for the file name of the given path
+    pub fn spec_for(&self, path: &str) -> Option<&ValidDYSpec<'a>> {
+        let file_name = Path::new(path).file_name()?.to_str()?;
+        self.specs
+            .iter()
+            .find(|(name, _)| *name == file_name)
+            .map(|(_, spec)| spec)
+    }
+
+    /// Parse the given content with the spec registered for the file name of the given path.
+    /// An error is returned if no spec is registered for this file name.
+    pub fn parse_auto(&self, path: &str, content: &str) -> Result<GenericParseResult, String> {
+        let spec = self
+            .spec_for(path)
+            .ok_or_else(|| format!("No spec is registered for the file '{path}'"))?;
+        Ok(parse_with_spec(spec, &Some(path.to_string()), content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::tests::{TESTING_COURSE_SPEC, TESTING_SKILLS_SPEC};
+    use crate::generic::{DyValue, SpecRegistry};
+    use crate::spec::ValidDYSpec;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_can_parse_generic_values_by_file_name() {
+        let mut registry = SpecRegistry::new();
+        registry
+            .register("course.dy", ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap())
+            .register("skills.dy", ValidDYSpec::new(TESTING_SKILLS_SPEC).unwrap());
+        let result = registry
+            .parse_auto("prg1/course.dy", "course PRG1\ncode PRG1\ngoal Learn C++")
+            .unwrap();
+        assert_eq!(result.errors, vec![]);
+        assert_eq!(
+            result.items,
+            vec![DyValue {
+                key: "course".to_string(),
+                value: "PRG1".to_string(),
+                children: vec![
+                    DyValue {
+                        key: "code".to_string(),
+                        value: "PRG1".to_string(),
+                        children: vec![]
+                    },
+                    DyValue {
+                        key: "goal".to_string(),
+                        value: "Learn C++".to_string(),
+                        children: vec![]
+                    }
+                ]
+            }]
+        );
+        assert_eq!(
+            registry.parse_auto("prg1/other.dy", "course PRG1"),
+            Err("No spec is registered for the file 'prg1/other.dy'".to_string())
+        );
+    }
+}
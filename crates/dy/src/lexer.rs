@@ -1,8 +1,27 @@
 /// The parser is responsible of the syntax analysis by cutting the content into lines, and lines into parts
-use crate::spec::{KeySpec, ValidDYSpec, all_valid_keys};
+use crate::range_on_lines;
+use crate::spec::{KeySpec, ValidDYSpec, ValueType, all_valid_keys};
+use lsp_types::Range;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
 
 pub const COMMENT_PREFIX: &str = "//";
+/// Added after the comment prefix, it makes a comment documenting the whole document, like
+/// `//!`, it is kept in ParseResult::doc_comments instead of being dropped like other comments
+pub const DOC_COMMENT_MARKER: &str = "!";
+/// A block comment starts and ends with these markers on their own lines, it can be nested
+pub const BLOCK_COMMENT_START: &str = "/*";
+pub const BLOCK_COMMENT_END: &str = "*/";
+/// Put before the comment prefix or a key at the start of a line, like `\//` or `\exo`, to keep
+/// the line as content, the escape itself is removed
+pub const COMMENT_ESCAPE: char = '\\';
+/// Written after the comment prefix, like `// dy:allow(duplicated-key)`, to ignore the errors with
+/// the given codes (see ParseErrorType::code()) on the next line
+pub const ALLOW_DIRECTIVE: &str = "dy:allow";
+/// Start of a shebang line like `#!/usr/bin/env dy`, only allowed as the first line
+pub const SHEBANG_PREFIX: &str = "#!";
 const MARKDOWN_CODE_SNIPPETS_SEPARATORS: &[&str; 2] = &["```", "~~~"];
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -30,12 +49,35 @@ pub enum LinePart<'a> {
 }
 
 impl<'a> Line<'a> {
+    /// The length in bytes of the key as written at the start of the line, it can be one of its
+    /// aliases, or 0 if this line has no key
+    pub(crate) fn key_len(&self) -> usize {
+        match self.lt {
+            LineType::WithKey(key_spec) => {
+                let first_word = self.slice.split(' ').next().unwrap_or(self.slice);
+                // A custom matcher might accept more than the key in the first word
+                if key_spec.names().any(|name| name.len() == first_word.len()) {
+                    first_word.len()
+                } else {
+                    key_spec.id.len()
+                }
+            }
+            _ => 0,
+        }
+    }
+
     pub(crate) fn tokenize_parts(&self) -> Vec<LinePart<'a>> {
         match self.lt {
             LineType::WithKey(key_spec) => {
+                let value = &self.slice[self.key_len()..];
+                let value = if key_spec.vt == ValueType::Raw {
+                    value.strip_prefix(' ').unwrap_or(value)
+                } else {
+                    value.trim()
+                };
                 vec![
-                    LinePart::Key(&self.slice[..key_spec.id.len()]),
-                    LinePart::Value(self.slice[key_spec.id.len()..].trim()),
+                    LinePart::Key(&self.slice[..self.key_len()]),
+                    LinePart::Value(value),
                 ]
             }
             _ => vec![LinePart::Value(self.slice)],
@@ -43,47 +85,158 @@ impl<'a> Line<'a> {
     }
 }
 
+/// Decide if a line starts with one of the given keys (all the keys of the spec) and which one.
+/// The key must be written with as many bytes as its id or one of its aliases, the rest of the line
+/// is its value. It can be a closure capturing its state, like a compiled regex.
+#[derive(Clone)]
+pub struct KeyMatcher(Arc<KeyMatcherFn>);
+
+type KeyMatcherFn =
+    dyn for<'k> Fn(&str, &[&'k KeySpec<'k>]) -> Option<&'k KeySpec<'k>> + Send + Sync;
+
+impl KeyMatcher {
+    pub fn new(
+        matcher: impl for<'k> Fn(&str, &[&'k KeySpec<'k>]) -> Option<&'k KeySpec<'k>>
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        KeyMatcher(Arc::new(matcher))
+    }
+
+    fn find_key<'k>(&self, line: &str, keys: &[&'k KeySpec<'k>]) -> Option<&'k KeySpec<'k>> {
+        (self.0)(line, keys)
+    }
+}
+
+impl Debug for KeyMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("KeyMatcher")
+    }
+}
+
 /// Take all the lines of `content`, take a flat list of all valid keys in `spec`
 /// and categorize lines between comments, starting with a key and put all the others in the `unknown` category.
 /// A WithKey Line is not verified to be at a valid position !
 pub fn tokenize_into_lines<'a>(spec: &'a ValidDYSpec, content: &'a str) -> Vec<Line<'a>> {
+    // Fast path: most files are only made of keys and values, when there is no comment, no code
+    // snippet and no escape in the whole content, we can skip these checks on every line
+    let may_contain_comments_or_fences = content.contains(spec.comment_prefix())
+        || content.contains(BLOCK_COMMENT_START)
+        || content.contains(COMMENT_ESCAPE)
+        || MARKDOWN_CODE_SNIPPETS_SEPARATORS
+            .iter()
+            .any(|separator| content.contains(separator));
+    tokenize_into_lines_with(spec, content, may_contain_comments_or_fences, None)
+}
+
+/// Same as tokenize_into_lines() but the keys are detected by the given `matcher` instead of the
+/// exact key followed by a space, to support other conventions like keys in uppercase. Comments
+/// and code snippets are still detected before calling the matcher.
+pub fn tokenize_into_lines_with_matcher<'a>(
+    spec: &'a ValidDYSpec,
+    content: &'a str,
+    matcher: &KeyMatcher,
+) -> Vec<Line<'a>> {
+    tokenize_into_lines_with(spec, content, true, Some(matcher))
+}
+
+fn tokenize_into_lines_with<'a>(
+    spec: &'a ValidDYSpec,
+    content: &'a str,
+    check_comments_and_fences: bool,
+    matcher: Option<&KeyMatcher>,
+) -> Vec<Line<'a>> {
     let mut lines = Vec::new();
 
     let all_keys = all_valid_keys(spec.get());
     // For faster access to the correct key, we group them by length so when extracting the first
-    // word, we can only look at keys with the same length
-    let mut all_keys_grouped_by_len: HashMap<usize, Vec<&KeySpec>> = HashMap::new();
+    // word, we can only look at keys with the same length. Each alias is grouped with its own length.
+    let mut all_keys_grouped_by_len: HashMap<usize, Vec<(&str, &KeySpec)>> = HashMap::new();
     all_keys.iter().for_each(|k| {
-        all_keys_grouped_by_len
-            .entry(k.id.len())
-            .or_default()
-            .push(k);
+        for name in k.names() {
+            all_keys_grouped_by_len
+                .entry(name.len())
+                .or_default()
+                .push((name, k));
+        }
     });
 
     let mut inside_a_markdown_code_snippet = false;
+    // The number of block comments opened and not closed yet
+    let mut block_comment_depth = 0;
+    // Set after a key with a raw body, the next lines are not tokenized anymore
+    let mut inside_a_raw_body = false;
 
-    for (index, line_text) in content.lines().enumerate() {
+    for (index, mut line_text) in content.lines().enumerate() {
         let mut lt = LineType::Unknown;
+        // lines() already removes the \r of \r\n, but not on a last line without \n
+        line_text = line_text.strip_suffix('\r').unwrap_or(line_text);
 
-        for code_separator in MARKDOWN_CODE_SNIPPETS_SEPARATORS {
-            if line_text.starts_with(code_separator) {
-                inside_a_markdown_code_snippet = !inside_a_markdown_code_snippet;
-            }
+        if inside_a_raw_body {
+            lines.push(Line {
+                index,
+                slice: line_text,
+                lt,
+            });
+            continue;
+        }
+
+        if is_shebang(index, line_text) {
+            // Ignored like a comment, the line is still there so the next line indexes don't change
+            lines.push(Line {
+                index,
+                slice: line_text,
+                lt: LineType::Comment,
+            });
+            continue;
+        }
+
+        if check_comments_and_fences && block_comment_depth == 0 && is_code_fence(line_text) {
+            inside_a_markdown_code_snippet = !inside_a_markdown_code_snippet;
         }
 
         if inside_a_markdown_code_snippet {
             // just keep it as Unknown, we skill all lines inside markdown code snippets
-        } else if line_text.starts_with(COMMENT_PREFIX) {
+        } else if check_comments_and_fences && line_text.trim_end() == BLOCK_COMMENT_START {
+            block_comment_depth += 1;
+            lt = LineType::Comment;
+        } else if block_comment_depth > 0 {
+            if line_text.trim_end() == BLOCK_COMMENT_END {
+                block_comment_depth -= 1;
+            }
+            lt = LineType::Comment;
+        } else if check_comments_and_fences && line_text.starts_with(spec.comment_prefix()) {
             lt = LineType::Comment;
+        } else if check_comments_and_fences
+            && line_text.strip_prefix(COMMENT_ESCAPE).is_some_and(|rest| {
+                rest.starts_with(spec.comment_prefix())
+                    || match matcher {
+                        Some(matcher) => matcher.find_key(rest, &all_keys).is_some(),
+                        None => all_keys
+                            .iter()
+                            .flat_map(|key| key.names())
+                            .any(|name| line_starts_with_key(rest, name)),
+                    }
+            })
+        {
+            // An escaped comment or key is content without the escape, it cannot start with a key
+            line_text = &line_text[COMMENT_ESCAPE.len_utf8()..];
+        } else if let Some(matcher) = matcher {
+            if let Some(key) = matcher.find_key(line_text, &all_keys) {
+                lt = LineType::WithKey(key);
+                inside_a_raw_body = key.raw_body;
+            }
         } else {
             // Extract the first word before the first space, if there is no space, the first word is the entire line
             let first_word = line_text.split(" ").next().unwrap_or(line_text);
 
             // If there is a key with the same length as the first word, that is equal
             if let Some(possible_keys) = all_keys_grouped_by_len.get(&first_word.len()) {
-                for key in possible_keys {
-                    if line_starts_with_key(line_text, key.id) {
+                for (name, key) in possible_keys {
+                    if line_starts_with_key(line_text, name) {
                         lt = LineType::WithKey(key);
+                        inside_a_raw_body = key.raw_body;
                         break;
                     }
                 }
@@ -101,6 +254,191 @@ pub fn tokenize_into_lines<'a>(spec: &'a ValidDYSpec, content: &'a str) -> Vec<L
     lines
 }
 
+/// Whether the line at the given index is a shebang, the first line of an executable file
+pub fn is_shebang(index: usize, line: &str) -> bool {
+    index == 0 && line.starts_with(SHEBANG_PREFIX)
+}
+
+/// Remove the shebang line of the content if there is one, without its new line. The returned
+/// content starts on the second line, the line indexes in it are shifted by one.
+pub fn strip_shebang(content: &str) -> &str {
+    if is_shebang(0, content) {
+        content.split_once('\n').map(|(_, rest)| rest).unwrap_or("")
+    } else {
+        content
+    }
+}
+
+/// Find the key that the given word is a strict prefix of, like `cod` for `code`, to consider it as
+/// a key being typed. The aliases of the keys are considered too. This is meant for completion and
+/// lenient diagnostics, `None` is returned if the word is empty, already a complete key or if it's
+/// the prefix of several keys.
+pub fn is_partial_key<'a>(spec: &'a ValidDYSpec, word: &str) -> Option<&'a KeySpec<'a>> {
+    let keys = all_valid_keys(spec.get());
+    if keys.iter().any(|key| key.names().any(|name| name == word)) {
+        return None;
+    }
+    let mut candidates = keys.into_iter().filter(|key| is_partial_key_of(key, word));
+    match (candidates.next(), candidates.next()) {
+        (Some(key), None) => Some(key),
+        _ => None,
+    }
+}
+
+/// Whether the given word is a strict prefix of the id or of an alias of this key
+pub(crate) fn is_partial_key_of(key: &KeySpec, word: &str) -> bool {
+    !word.is_empty()
+        && key
+            .names()
+            .any(|name| name.len() > word.len() && name.starts_with(word))
+}
+
+/// Extract the text of the `//!` comments (with the given comment prefix), without the prefix and
+/// the following space. Comments inside block comments are ignored as they are commented out.
+pub(crate) fn extract_doc_comments(lines: &[Line], comment_prefix: &str) -> Vec<String> {
+    let doc_comment_prefix = format!("{comment_prefix}{DOC_COMMENT_MARKER}");
+    let mut block_comment_depth = 0;
+    let mut doc_comments = Vec::new();
+    for line in lines
+        .iter()
+        .filter(|line| line.lt == LineType::Comment && !is_shebang(line.index, line.slice))
+    {
+        match line.slice.trim_end() {
+            BLOCK_COMMENT_START => block_comment_depth += 1,
+            BLOCK_COMMENT_END if block_comment_depth > 0 => block_comment_depth -= 1,
+            text if block_comment_depth == 0 => {
+                if let Some(doc) = text.strip_prefix(doc_comment_prefix.as_str()) {
+                    doc_comments.push(doc.strip_prefix(' ').unwrap_or(doc).to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    doc_comments
+}
+
+/// Extract the `dy:allow(...)` directives of the comments (with the given comment prefix), as the
+/// index of the line they apply to (the next one) with the allowed error codes. Directives inside
+/// block comments are ignored as they are commented out.
+pub(crate) fn extract_allow_directives<'a>(
+    lines: &[Line<'a>],
+    comment_prefix: &str,
+) -> Vec<(usize, Vec<&'a str>)> {
+    let mut block_comment_depth = 0;
+    let mut directives = Vec::new();
+    for line in lines.iter().filter(|line| line.lt == LineType::Comment) {
+        match line.slice.trim_end() {
+            BLOCK_COMMENT_START => block_comment_depth += 1,
+            BLOCK_COMMENT_END if block_comment_depth > 0 => block_comment_depth -= 1,
+            text if block_comment_depth == 0 => {
+                let codes = text
+                    .strip_prefix(comment_prefix)
+                    .map(str::trim_start)
+                    .and_then(|rest| rest.strip_prefix(ALLOW_DIRECTIVE))
+                    .and_then(|rest| rest.strip_prefix('('))
+                    .and_then(|rest| rest.strip_suffix(')'));
+                if let Some(codes) = codes {
+                    directives.push((line.index + 1, codes.split(',').map(str::trim).collect()));
+                }
+            }
+            _ => {}
+        }
+    }
+    directives
+}
+
+/// Find the start of the first block comment that is never closed, all the following lines are
+/// comments, so this is probably a mistake
+pub(crate) fn find_unterminated_block_comment(lines: &[Line]) -> Option<usize> {
+    let mut opened: Vec<usize> = Vec::new();
+    for line in lines.iter().filter(|line| line.lt == LineType::Comment) {
+        match line.slice.trim_end() {
+            BLOCK_COMMENT_START => opened.push(line.index),
+            BLOCK_COMMENT_END => {
+                opened.pop();
+            }
+            _ => {}
+        }
+    }
+    opened.first().copied()
+}
+
+/// Whether the given line opens or closes a markdown code snippet, the fence can be indented like
+/// in a list item and followed by a language, like `  ```rust`
+pub(crate) fn is_code_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    MARKDOWN_CODE_SNIPPETS_SEPARATORS
+        .iter()
+        .any(|separator| line.starts_with(separator))
+}
+
+/// The language given after an opening code fence, if any
+fn code_fence_lang(line: &str) -> Option<String> {
+    // Note: all separators have the same length
+    let lang = line.trim_start()[MARKDOWN_CODE_SNIPPETS_SEPARATORS[0].len()..].trim();
+    (!lang.is_empty()).then(|| lang.to_string())
+}
+
+/// Extract the markdown code snippets of the given text with the language given after the opening
+/// fence, if any. Fences are detected like in tokenize_into_lines(), a snippet that is not closed
+/// goes until the end of the text.
+pub fn extract_code_blocks(text: &str) -> Vec<(Option<String>, String)> {
+    let mut code_blocks = Vec::new();
+    let mut current: Option<(Option<String>, Vec<&str>)> = None;
+    for line in text.lines() {
+        if is_code_fence(line) {
+            match current.take() {
+                Some((lang, lines)) => code_blocks.push((lang, lines.join("\n"))),
+                None => current = Some((code_fence_lang(line), Vec::new())),
+            }
+        } else if let Some((_, lines)) = current.as_mut() {
+            lines.push(line);
+        }
+    }
+    if let Some((lang, lines)) = current {
+        code_blocks.push((lang, lines.join("\n")));
+    }
+    code_blocks
+}
+
+/// A markdown code snippet found in a content, see code_fences()
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CodeFence {
+    /// The language given after the opening fence, like `rust` for ```` ```rust ````
+    pub lang: Option<String>,
+    /// From the start of the opening fence line to the end of the closing fence line
+    pub range: Range,
+}
+
+/// Get the markdown code snippets of the given content with their range, like to offer a copy of
+/// the starter code of an exo in an editor. Fences are detected like in tokenize_into_lines(), a
+/// snippet that is not closed goes until the end of the content.
+pub fn code_fences(content: &str) -> Vec<CodeFence> {
+    let mut fences = Vec::new();
+    let mut opened: Option<(usize, Option<String>)> = None;
+    let mut last_line = (0, 0);
+    for (index, line) in content.lines().enumerate() {
+        last_line = (index, line.chars().count());
+        if !is_code_fence(line) {
+            continue;
+        }
+        match opened.take() {
+            Some((start, lang)) => fences.push(CodeFence {
+                lang,
+                range: range_on_lines(start as u32, index as u32, last_line.1 as u32),
+            }),
+            None => opened = Some((index, code_fence_lang(line))),
+        }
+    }
+    if let Some((start, lang)) = opened {
+        fences.push(CodeFence {
+            lang,
+            range: range_on_lines(start as u32, last_line.0 as u32, last_line.1 as u32),
+        });
+    }
+    fences
+}
+
 /// Make sure the given line starts with a prefix and is followed by nothing or a space or a \n
 #[inline(always)]
 fn line_starts_with_key(line: &str, prefix: &str) -> bool {
@@ -122,13 +460,69 @@ fn line_starts_with_key(line: &str, prefix: &str) -> bool {
 mod tests {
     use crate::{
         common::tests::{
-            CODE_SPEC, COURSE_SPEC, EXO_SPEC, GOAL_SPEC, TESTING_COURSE_SPEC, TESTING_EXOS_SPEC,
+            CHECK_SPEC, CODE_SPEC, COURSE_SPEC, EXO_SPEC, GOAL_SPEC, SEE_SPEC, TESTING_COURSE_SPEC,
+            TESTING_EXOS_SPEC,
+        },
+        lexer::{
+            CodeFence, KeyMatcher, Line, LinePart, LineType, code_fences, extract_allow_directives,
+            extract_code_blocks, find_unterminated_block_comment, is_partial_key, is_shebang,
+            line_starts_with_key, strip_shebang, tokenize_into_lines, tokenize_into_lines_with,
+            tokenize_into_lines_with_matcher,
         },
-        lexer::{Line, LinePart, LineType, line_starts_with_key, tokenize_into_lines},
-        spec::ValidDYSpec,
+        range_on_lines,
+        spec::{KeySpec, ValidDYSpec},
     };
     use pretty_assertions::assert_eq;
 
+    /// Only recognize keys written in uppercase, like `COURSE`
+    fn uppercase_matcher<'k>(line: &str, keys: &[&'k KeySpec<'k>]) -> Option<&'k KeySpec<'k>> {
+        let first_word = line.split(' ').next().unwrap_or(line);
+        keys.iter()
+            .find(|key| first_word == key.id.to_uppercase())
+            .copied()
+    }
+
+    #[test]
+    #[ntest::timeout(50)]
+    fn test_can_tokenize_with_a_custom_matcher() {
+        let text = "COURSE Programmation 1
+code PRG1
+CODE PRG1
+// GOAL commented
+GOAL Learn C++";
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        assert_eq!(
+            tokenize_into_lines_with_matcher(&binding, text, &KeyMatcher::new(uppercase_matcher)),
+            vec![
+                Line {
+                    index: 0,
+                    slice: "COURSE Programmation 1",
+                    lt: LineType::WithKey(COURSE_SPEC)
+                },
+                Line {
+                    index: 1,
+                    slice: "code PRG1",
+                    lt: LineType::Unknown
+                },
+                Line {
+                    index: 2,
+                    slice: "CODE PRG1",
+                    lt: LineType::WithKey(CODE_SPEC)
+                },
+                Line {
+                    index: 3,
+                    slice: "// GOAL commented",
+                    lt: LineType::Comment
+                },
+                Line {
+                    index: 4,
+                    slice: "GOAL Learn C++",
+                    lt: LineType::WithKey(GOAL_SPEC)
+                },
+            ]
+        );
+    }
+
     #[test]
     #[ntest::timeout(50)]
     fn test_line_into_parts() {
@@ -276,6 +670,29 @@ blabla";
         assert!(&lines.iter().all(|l| l.lt == LineType::Unknown));
     }
 
+    #[test]
+    fn test_keys_inside_indented_code_fences_are_ignored() {
+        let text = "exo hey
+some instruction
+  ```rust
+see inside the code
+  ```
+check ok
+see after the code";
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let lines = tokenize_into_lines(&binding, text);
+        assert_eq!(lines[3].lt, LineType::Unknown);
+        assert_eq!(lines[5].lt, LineType::WithKey(CHECK_SPEC));
+        assert_eq!(lines[6].lt, LineType::WithKey(SEE_SPEC));
+        assert_eq!(
+            code_fences(text),
+            vec![CodeFence {
+                lang: Some("rust".to_string()),
+                range: range_on_lines(2, 4, 5),
+            }]
+        );
+    }
+
     #[test]
     #[ntest::timeout(50)]
     fn test_can_tokenize_and_ignore_anything_inside_code_blocks() {
@@ -438,4 +855,166 @@ color:blue; // included
             ]
         );
     }
+
+    #[test]
+    #[ntest::timeout(50)]
+    fn test_can_tokenize_nested_block_comments() {
+        let text = "course Programmation 1
+/*
+code PRG1
+/*
+goal nested
+*/
+still commented
+*/
+goal Learn
+/*
+code never closed";
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let lines = tokenize_into_lines(&binding, text);
+        let types: Vec<&LineType> = lines.iter().map(|line| &line.lt).collect();
+        assert_eq!(
+            types,
+            vec![
+                &LineType::WithKey(COURSE_SPEC),
+                &LineType::Comment,
+                &LineType::Comment,
+                &LineType::Comment,
+                &LineType::Comment,
+                &LineType::Comment,
+                &LineType::Comment,
+                &LineType::Comment,
+                &LineType::WithKey(GOAL_SPEC),
+                &LineType::Comment,
+                &LineType::Comment,
+            ]
+        );
+        assert_eq!(find_unterminated_block_comment(&lines), Some(9));
+        assert_eq!(find_unterminated_block_comment(&lines[..9]), None);
+    }
+
+    #[test]
+    fn test_can_get_code_fences_with_their_range() {
+        let text = "exo Hello
+Complete this code
+```c
+int main() {
+}
+```
+and then this one
+~~~ rust
+fn main() {}
+~~~";
+        assert_eq!(
+            code_fences(text),
+            vec![
+                CodeFence {
+                    lang: Some("c".to_string()),
+                    range: range_on_lines(2, 5, 3),
+                },
+                CodeFence {
+                    lang: Some("rust".to_string()),
+                    range: range_on_lines(7, 9, 3),
+                },
+            ]
+        );
+        // Not closed
+        assert_eq!(
+            code_fences(
+                "```
+some code"
+            ),
+            vec![CodeFence {
+                lang: None,
+                range: range_on_lines(0, 1, 9),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_can_extract_code_blocks() {
+        let text =
+            "Some prose\n```\nplain\n```\nmore prose\n~~~ c\nint a;\nint b;\n~~~\n```rust\nfn main";
+        assert_eq!(
+            extract_code_blocks(text),
+            vec![
+                (None, "plain".to_string()),
+                (Some("c".to_string()), "int a;\nint b;".to_string()),
+                (Some("rust".to_string()), "fn main".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    #[ntest::timeout(50)]
+    fn test_fast_path_gives_same_lines_as_general_path() {
+        let text = "exo hey there
+some instruction
+
+check validate it
+args John
+see Hello John
+type Doe
+exit 0";
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        assert_eq!(
+            tokenize_into_lines_with(&binding, text, false, None),
+            tokenize_into_lines_with(&binding, text, true, None)
+        );
+    }
+
+    #[test]
+    fn test_can_find_partial_key() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        assert_eq!(is_partial_key(&binding, "cod").map(|k| k.id), Some("code"));
+        assert_eq!(is_partial_key(&binding, "g").map(|k| k.id), Some("goal"));
+        // Ambiguous between course and code
+        assert_eq!(is_partial_key(&binding, "co"), None);
+        // Already a complete key or not a prefix at all
+        assert_eq!(is_partial_key(&binding, "code"), None);
+        assert_eq!(is_partial_key(&binding, "codePRG1"), None);
+        assert_eq!(is_partial_key(&binding, ""), None);
+    }
+
+    #[test]
+    fn test_shebang_is_a_comment_only_on_the_first_line() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let text = "#!/usr/bin/env dy\ncourse PRG1\n#!/usr/bin/env dy";
+        let lines = tokenize_into_lines(&binding, text);
+        assert_eq!(
+            lines.iter().map(|l| (l.index, &l.lt)).collect::<Vec<_>>(),
+            vec![
+                (0, &LineType::Comment),
+                (1, &LineType::WithKey(COURSE_SPEC)),
+                (2, &LineType::Unknown),
+            ]
+        );
+        assert!(is_shebang(0, "#!/bin/dy"));
+        assert!(!is_shebang(1, "#!/bin/dy"));
+        assert!(!is_shebang(0, "# not a shebang"));
+        assert_eq!(strip_shebang(text), "course PRG1\n#!/usr/bin/env dy");
+        assert_eq!(strip_shebang("#!/usr/bin/env dy"), "");
+        assert_eq!(strip_shebang("course PRG1"), "course PRG1");
+    }
+
+    #[test]
+    fn test_can_extract_allow_directives() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let text = "// dy:allow( duplicated-key , partial-key )
+course PRG1
+//dy:allow(content-out-of-key)
+/*
+// dy:allow(duplicated-key)
+*/
+// dy:allow incomplete
+";
+        let lines = tokenize_into_lines(&binding, text);
+        assert_eq!(
+            extract_allow_directives(&lines, "//"),
+            vec![
+                (1, vec!["duplicated-key", "partial-key"]),
+                (3, vec!["content-out-of-key"])
+            ]
+        );
+    }
 }
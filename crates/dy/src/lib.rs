@@ -1,19 +1,35 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::Display;
+use std::hash::Hash;
 
 use colored::Colorize;
-use error::ParseError;
-use lexer::tokenize_into_lines;
+use error::{ParseError, ParseErrorType, Severity};
+use lexer::{
+    BLOCK_COMMENT_START, KeyMatcher, Line, LinePart, LineType, extract_allow_directives,
+    extract_doc_comments, find_unterminated_block_comment, tokenize_into_lines,
+    tokenize_into_lines_with_matcher,
+};
+use lint::{
+    lint_ambiguous_key_like_value, lint_line_too_long, lint_partial_key_at_eof,
+    lint_possible_missing_key, lint_repeated_key_in_value, lint_unbalanced_inline_code,
+};
 use lsp_types::{Position, Range};
-use parser::{Block, build_blocks_tree};
+use parser::{
+    Block, PRESERVED_LINES_KEY, TextTransformer, build_blocks_tree, build_blocks_tree_with_options,
+};
 use serde::Serialize;
 use spec::ValidDYSpec;
 
 pub mod error;
+pub mod generic;
 pub mod lexer;
 pub mod parser;
+#[cfg(feature = "miette")]
+pub mod report;
 pub mod spec;
 
 mod common;
+mod lint;
 
 // DY files must be stored inside something.dy
 pub const FILE_EXTENSION: &str = "dy";
@@ -27,10 +43,117 @@ pub struct ParseResult<T> {
     pub some_file_path: Option<String>,
     /// If the `errors` vec is not empty, it will also includes the file content so the error can be displayed
     pub some_file_content: Option<String>,
+    /// The text of the `//!` comments documenting the file, in the order of the document
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub doc_comments: Vec<String>,
 }
 
 impl<T> Display for ParseResult<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with(f, None, false)
+    }
+}
+
+/// Display a ParseResult with more options than the default Display: hints taken from the
+/// description of keys in the spec and 1-based positions
+pub struct ParseResultDisplay<'r, T> {
+    result: &'r ParseResult<T>,
+    spec: Option<&'r ValidDYSpec<'r>>,
+    one_based: bool,
+}
+
+impl<'r, T> ParseResultDisplay<'r, T> {
+    /// Show lines and characters starting at 1 like in editors, instead of 0 like in LSP ranges
+    pub fn one_based(mut self) -> Self {
+        self.one_based = true;
+        self
+    }
+}
+
+impl<'r, T> Display for ParseResultDisplay<'r, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.result.fmt_with(f, self.spec, self.one_based)
+    }
+}
+
+impl<T> ParseResult<T> {
+    /// Get a displayable version of this result that also includes a hint with the description of
+    /// the key concerned by each error. The spec must be the one used to produce this result.
+    pub fn display_with_spec<'r>(&'r self, spec: &'r ValidDYSpec<'r>) -> ParseResultDisplay<'r, T> {
+        ParseResultDisplay {
+            result: self,
+            spec: Some(spec),
+            one_based: false,
+        }
+    }
+
+    /// Get a displayable version of this result with 1-based positions for humans, the ranges
+    /// themselves stay 0-based
+    pub fn display_one_based(&self) -> ParseResultDisplay<'_, T> {
+        ParseResultDisplay {
+            result: self,
+            spec: None,
+            one_based: true,
+        }
+    }
+
+    /// Count the errors and the warnings, the result is ok if there is no error, warnings are accepted
+    pub fn summary(&self) -> (usize, usize, bool) {
+        let warnings = self
+            .errors
+            .iter()
+            .filter(|e| e.severity() == Severity::Warning)
+            .count();
+        let errors = self.errors.len() - warnings;
+        (errors, warnings, errors == 0)
+    }
+
+    /// Aggregate the results of several files into a single one, the file path of each result is
+    /// kept in the `some_file` of its errors so they can be filtered with errors_for_file(). The
+    /// merged result has no file path and no content.
+    pub fn merge_all(results: Vec<ParseResult<T>>) -> ParseResult<T> {
+        let mut merged = ParseResult {
+            items: Vec::new(),
+            errors: Vec::new(),
+            some_file_path: None,
+            some_file_content: None,
+            doc_comments: Vec::new(),
+        };
+        for result in results {
+            merged.items.extend(result.items);
+            merged
+                .errors
+                .extend(result.errors.into_iter().map(|mut error| {
+                    if error.some_file.is_none() {
+                        error.some_file = result.some_file_path.clone();
+                    }
+                    error
+                }));
+            merged.doc_comments.extend(result.doc_comments);
+        }
+        merged
+    }
+
+    /// The errors of the given file, after a merge_all()
+    pub fn errors_for_file(&self, path: &str) -> Vec<&ParseError> {
+        self.errors
+            .iter()
+            .filter(|error| error.some_file.as_deref() == Some(path))
+            .collect()
+    }
+
+    /// The exit code a CLI tool should return for this result, 0 if ok and 1 if there are errors
+    pub fn to_exit_code(&self) -> i32 {
+        let (_, _, ok) = self.summary();
+        if ok { 0 } else { 1 }
+    }
+
+    fn fmt_with(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        spec: Option<&ValidDYSpec>,
+        one_based: bool,
+    ) -> std::fmt::Result {
         if self.errors.is_empty() {
             write!(
                 f,
@@ -65,17 +188,34 @@ impl<T> Display for ParseResult<T> {
 
             for error in self.errors.iter() {
                 let range = error.range;
-                let position = match &self.some_file_path {
-                    Some(file) => format!("{file}:{}:{}", range.start.line, range.start.character),
-                    None => format!("line {}, char {}", range.start.line, range.start.character),
+                let shift = if one_based { 1 } else { 0 };
+                let (line, character) = (range.start.line + shift, range.start.character + shift);
+                // The error can be in another file than the parsed one, like an included file
+                let some_file = error.some_file.as_ref().or(self.some_file_path.as_ref());
+                let position = match some_file {
+                    Some(file) => format!("{file}:{line}:{character}"),
+                    None => format!("line {line}, char {character}"),
+                };
+                let label = match error.severity() {
+                    Severity::Error => "Error",
+                    Severity::Warning => "Warning",
                 };
-                let _ = write!(f, "{}", format!("\nError at {position}\n").cyan().bold());
+                let context = if error.path.is_empty() {
+                    String::default()
+                } else {
+                    format!(" in {}", error.path.join(" > "))
+                };
+                let _ = write!(
+                    f,
+                    "{}",
+                    format!("\n{label} at {position}{context}\n").cyan().bold()
+                );
 
-                let context_line = match &self.some_file_content {
-                    Some(content) => content.lines().collect::<Vec<_>>()
+                let context_line = match (&self.some_file_content, &error.some_file) {
+                    (Some(content), None) => content.lines().collect::<Vec<_>>()
                         [error.range.start.line as usize..range.end.line as usize + 1]
                         .join("\n"),
-                    None => String::default(),
+                    _ => String::default(),
                 };
                 let _ = writeln!(f, "{context_line}");
                 let underlined_chars_count = range.end.character - range.start.character;
@@ -92,12 +232,84 @@ impl<T> Display for ParseResult<T> {
                     repeated_markers.red()
                 );
                 let _ = writeln!(f, "{}", format!(" {}", error.error).red().bold());
+                if let Some(key_spec) = spec
+                    .zip(error.error.key_id())
+                    .and_then(|(spec, id)| spec.find_key(id))
+                {
+                    let _ = writeln!(f, "Hint: '{}' - {}", key_spec.id, key_spec.desc);
+                }
             }
             Ok(())
         }
     }
 }
 
+/// The differences between two ParseResult, typically two parses of an evolving content.
+/// Items are matched via a key given by the caller, changed items are given in their new version.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ResultDiff<'r, T> {
+    pub added_items: Vec<&'r T>,
+    pub removed_items: Vec<&'r T>,
+    pub changed_items: Vec<&'r T>,
+    pub added_errors: Vec<&'r ParseError>,
+    pub removed_errors: Vec<&'r ParseError>,
+}
+
+impl<'r, T> ResultDiff<'r, T> {
+    pub fn is_empty(&self) -> bool {
+        self.added_items.is_empty()
+            && self.removed_items.is_empty()
+            && self.changed_items.is_empty()
+            && self.added_errors.is_empty()
+            && self.removed_errors.is_empty()
+    }
+}
+
+impl<T: PartialEq> ParseResult<T> {
+    /// Compute the differences from `self` (the old result) to `other` (the new result).
+    /// The `key` function identifies an item across both results, as T equality is too strict to
+    /// find the same item after an edit. Items with the same key but not equal are changed items.
+    pub fn diff<'r, K: Eq + Hash>(
+        &'r self,
+        other: &'r Self,
+        key: impl Fn(&T) -> K,
+    ) -> ResultDiff<'r, T> {
+        let old_items: HashMap<K, &T> = self.items.iter().map(|i| (key(i), i)).collect();
+        let new_keys: HashSet<K> = other.items.iter().map(&key).collect();
+
+        let mut diff = ResultDiff {
+            added_items: Vec::new(),
+            removed_items: Vec::new(),
+            changed_items: Vec::new(),
+            added_errors: Vec::new(),
+            removed_errors: Vec::new(),
+        };
+        for item in other.items.iter() {
+            match old_items.get(&key(item)) {
+                Some(old_item) if *old_item != item => diff.changed_items.push(item),
+                Some(_) => {}
+                None => diff.added_items.push(item),
+            }
+        }
+        diff.removed_items = self
+            .items
+            .iter()
+            .filter(|i| !new_keys.contains(&key(i)))
+            .collect();
+        diff.added_errors = other
+            .errors
+            .iter()
+            .filter(|e| !self.errors.contains(e))
+            .collect();
+        diff.removed_errors = self
+            .errors
+            .iter()
+            .filter(|e| !other.errors.contains(e))
+            .collect();
+        diff
+    }
+}
+
 /// Make sure we can create this type from a Block and validate it's content once created
 pub trait FromDYBlock<'a> {
     /// Get a block representing the same object as Self but in a blocks tree
@@ -105,10 +317,59 @@ pub trait FromDYBlock<'a> {
     fn from_block_with_validation(block: &Block<'a>) -> (Vec<ParseError>, Self);
 }
 
+/// Options to enable optional behaviors during parsing, everything is disabled by default
+#[derive(Debug, Default, Clone)]
+pub struct ParseOptions {
+    /// Generate UnbalancedInlineCode warnings on lines of multiline values with an odd number of backticks
+    pub lint_unbalanced_inline_code: bool,
+    /// Generate a PartialKey warning when the last line is the start of a key, like `cod` for `code`
+    pub lint_partial_key_at_eof: bool,
+    /// Generate RepeatedKeyInValue warnings on lines like `code code PRG1`, this has false
+    /// positives when the value really starts with the key, like `see see you later`
+    pub lint_repeated_key_in_value: bool,
+    /// Generate PossibleMissingKey warnings when the last line of a multiline value starts like a
+    /// key that could follow, like ` check foo` at the end of an instruction
+    pub lint_possible_missing_key: bool,
+    /// Generate AmbiguousKeyLikeValue warnings on misplaced keys directly following a line of a
+    /// multiline value, like `type anything` inside an exo instruction, as they were probably
+    /// meant as content. They can be escaped like `\type anything`.
+    pub lint_ambiguous_key_like_value: bool,
+    /// Wrap a key found without its parent (like a `check` without `exo`) in a synthetic parent
+    /// block instead of dropping it, see Block::is_synthetic(). A RecoveredOrphanKey warning is
+    /// generated instead of the WrongKeyPosition error, this is meant to keep the content for
+    /// tooling like outlines and completion. Orphans followed by a block of their parent key are
+    /// moved into it instead.
+    pub recover_orphans: bool,
+    /// Post-process the joined value of every block before FromDYBlock runs, like to expand
+    /// `${code}`-style references. It is applied lazily when the text is accessed, ranges and
+    /// errors are not affected as they refer to the text as written.
+    pub transformer: Option<TextTransformer>,
+    /// Keep every line of the document in the `raw` lines of the blocks, with the lines before the
+    /// first block in a synthetic block with the parser::PRESERVED_LINES_KEY. Duplicated blocks are
+    /// kept too and not merged. This allows an editor to write the document back exactly, see
+    /// parser::blocks_to_raw_text(). As nothing is dropped, the errors of the blocks tree are
+    /// reported as warnings.
+    pub preserve_all: bool,
+    /// Detect keys with this matcher instead of the exact key followed by a space, see
+    /// lexer::tokenize_into_lines_with_matcher()
+    pub key_matcher: Option<KeyMatcher>,
+    /// Consider the last top-level block as still being typed: its MissingRequiredKey and
+    /// MissingRequiredValue errors (including in its subblocks) are not generated, see
+    /// Block::incomplete and parse_incremental()
+    pub incomplete_last_block: bool,
+    /// Report a single ContentOutOfKey error spanning each region of consecutive out of key lines,
+    /// instead of one error per line, like for a multi-line preamble before the first key
+    pub coalesce_content_out_of_key: bool,
+    /// Generate LineTooLong warnings on lines with more characters than this maximum
+    pub lint_max_line_length: Option<u32>,
+}
+
 /// Given a ValidDYSpec and a content, generate a ParseResult with all the items of type T that
 /// have been extracted. This T needs to implement the mapping from a given Block and validation
 /// after the mapping, via the FromDYBlock trait.
 /// The ParseResult.some_file_content is filled with an owned copy of the content only if they are some errors
+/// An empty content (or only made of spaces and comments) has no item, the only errors are the
+/// MissingRequiredKey of the required top-level keys.
 pub fn parse_with_spec<'a, T>(
     spec: &'a ValidDYSpec,
     some_file: &Option<String>,
@@ -117,8 +378,114 @@ pub fn parse_with_spec<'a, T>(
 where
     T: FromDYBlock<'a>,
 {
+    parse_with_options(spec, some_file, content, &ParseOptions::default())
+}
+
+/// Parse a content that might still be typed, like in an editor sending each change. When
+/// `is_complete` is false, the last top-level block is considered incomplete and the missing
+/// required keys and values are only reported in the previous blocks.
+pub fn parse_incremental<'a, T>(
+    spec: &'a ValidDYSpec,
+    content: &'a str,
+    is_complete: bool,
+) -> ParseResult<T>
+where
+    T: FromDYBlock<'a>,
+{
+    let options = ParseOptions {
+        incomplete_last_block: !is_complete,
+        ..Default::default()
+    };
+    parse_with_options(spec, &None, content, &options)
+}
+
+/// Check if the given content is valid for the spec, without creating the items. Only the errors
+/// of the structure are checked (including the missing required keys), the validations done by
+/// FromDYBlock are not run. Warnings and errors allowed by a directive don't make the content
+/// invalid.
+pub fn is_valid(spec: &ValidDYSpec, content: &str) -> bool {
     let lines = tokenize_into_lines(spec, content);
-    let (blocks, mut errors) = build_blocks_tree(spec, lines);
+    let allow_directives = extract_allow_directives(&lines, spec.comment_prefix());
+    let unterminated_comment_error = unterminated_block_comment_error(&lines);
+    let (_, mut errors) = build_blocks_tree(spec, lines);
+    errors.extend(unterminated_comment_error);
+    drop_allowed_errors(&mut errors, &allow_directives);
+    errors
+        .iter()
+        .all(|error| error.severity() == Severity::Warning)
+}
+
+/// The error of a block comment that is never closed, if any
+fn unterminated_block_comment_error(lines: &[Line]) -> Option<ParseError> {
+    find_unterminated_block_comment(lines).map(|line_index| {
+        ParseError::new(
+            range_on_line_with_length(line_index as u32, BLOCK_COMMENT_START.len() as u32),
+            ParseErrorType::UnterminatedBlockComment,
+        )
+    })
+}
+
+/// Drop the errors allowed by a directive on the line above their start
+fn drop_allowed_errors(errors: &mut Vec<ParseError>, allow_directives: &[(usize, Vec<&str>)]) {
+    errors.retain(|error| {
+        !allow_directives.iter().any(|(line, codes)| {
+            *line == error.range.start.line as usize && codes.contains(&error.error.code())
+        })
+    });
+}
+
+/// Get the ids of all the distinct keys used in the content, like to know which features a file
+/// is using. Keys are taken from the lines starting with a key, even if they are at an invalid
+/// position, the lines in comments and code snippets are ignored.
+pub fn used_keys<'a>(spec: &'a ValidDYSpec, content: &'a str) -> BTreeSet<&'a str> {
+    tokenize_into_lines(spec, content)
+        .iter()
+        .filter_map(|line| match line.lt {
+            LineType::WithKey(key) => Some(key.id),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Same as parse_with_spec() but with the given options
+pub fn parse_with_options<'a, T>(
+    spec: &'a ValidDYSpec,
+    some_file: &Option<String>,
+    content: &'a str,
+    options: &ParseOptions,
+) -> ParseResult<T>
+where
+    T: FromDYBlock<'a>,
+{
+    let lines = match &options.key_matcher {
+        Some(matcher) => tokenize_into_lines_with_matcher(spec, content, matcher),
+        None => tokenize_into_lines(spec, content),
+    };
+    let doc_comments = extract_doc_comments(&lines, spec.comment_prefix());
+    let allow_directives = extract_allow_directives(&lines, spec.comment_prefix());
+    let mut lint_errors: Vec<ParseError> = unterminated_block_comment_error(&lines)
+        .into_iter()
+        .collect();
+    if options.lint_unbalanced_inline_code {
+        lint_errors.extend(lint_unbalanced_inline_code(&lines));
+    }
+    if options.lint_partial_key_at_eof {
+        lint_errors.extend(lint_partial_key_at_eof(spec, &lines));
+    }
+    if options.lint_repeated_key_in_value {
+        lint_errors.extend(lint_repeated_key_in_value(&lines));
+    }
+    if options.lint_possible_missing_key {
+        lint_errors.extend(lint_possible_missing_key(spec, &lines));
+    }
+    if options.lint_ambiguous_key_like_value {
+        lint_errors.extend(lint_ambiguous_key_like_value(spec, &lines));
+    }
+    if let Some(max) = options.lint_max_line_length {
+        lint_errors.extend(lint_line_too_long(&lines, max));
+    }
+    let (blocks, mut errors) = build_blocks_tree_with_options(spec, content, lines, options);
+    errors.extend(lint_errors);
 
     let mut items: Vec<T> = Vec::with_capacity(blocks.len());
 
@@ -126,27 +493,93 @@ where
     // because they are the only one we know they implement this method, due to the T: FromDYBlock
     // Subblocks are managed by this method
     for block in blocks {
+        if std::ptr::eq(block.key, &PRESERVED_LINES_KEY) {
+            continue;
+        }
         let (new_errors, entity) = T::from_block_with_validation(&block);
         errors.extend(new_errors);
         items.push(entity);
     }
 
+    drop_allowed_errors(&mut errors, &allow_directives);
+
     let some_file_content = if errors.is_empty() {
         None
     } else {
         Some(content.to_string())
     };
 
+    // Make sure no range goes out of the content, an editor could reject the diagnostic
+    if !errors.is_empty() {
+        let line_lengths = get_line_lengths(content);
+        for error in errors.iter_mut() {
+            error.range = clamp_range_to_lines(error.range, &line_lengths);
+        }
+    }
+
     // We have to sort the errors to make them appear in the same order as their position in the document
     // defined by the first char of the range. This is required because the generation order might be different.
     errors.sort();
+    if options.coalesce_content_out_of_key {
+        errors = coalesce_content_out_of_key(errors);
+    }
 
     ParseResult {
         items,
         some_file_path: some_file.clone(),
         errors,
         some_file_content,
+        doc_comments,
+    }
+}
+
+/// Quickly extract the header of each top-level block, without building the blocks tree: the
+/// key id, the first line of its value and the range of the key line. This is useful to list
+/// entities of big files, no validation is done so errors are not reported.
+pub fn parse_headers(spec: &ValidDYSpec, content: &str) -> Vec<(String, String, Range)> {
+    tokenize_into_lines(spec, content)
+        .iter()
+        .filter_map(|line| match line.lt {
+            LineType::WithKey(key_spec) if spec.get().iter().any(|k| k.id == key_spec.id) => {
+                let value = line
+                    .tokenize_parts()
+                    .into_iter()
+                    .find_map(|part| match part {
+                        LinePart::Value(value) => Some(value.to_string()),
+                        LinePart::Key(_) => None,
+                    })
+                    .unwrap_or_default();
+                Some((
+                    key_spec.id.to_string(),
+                    value,
+                    range_on_line_with_length(line.index as u32, line.slice.chars().count() as u32),
+                ))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Merge the ContentOutOfKey errors of consecutive lines into one error from the start of the
+/// first line to the end of the last one. The errors must be sorted.
+fn coalesce_content_out_of_key(errors: Vec<ParseError>) -> Vec<ParseError> {
+    let mut coalesced: Vec<ParseError> = Vec::with_capacity(errors.len());
+    let mut last_region: Option<usize> = None;
+    for error in errors {
+        if !matches!(error.error, ParseErrorType::ContentOutOfKey(_)) {
+            coalesced.push(error);
+            continue;
+        }
+        if let Some(index) = last_region
+            && coalesced[index].range.end.line + 1 == error.range.start.line
+        {
+            coalesced[index].range.end = error.range.end;
+            continue;
+        }
+        last_region = Some(coalesced.len());
+        coalesced.push(error);
     }
+    coalesced
 }
 
 // Helpers functions
@@ -172,6 +605,59 @@ pub fn range_on_lines(line: u32, line2: u32, length: u32) -> Range {
     }
 }
 
+/// Clamp the given range to the dimensions of the content: lines are limited to the last line
+/// and characters to the length of their line. The end is also moved to the start if it was before.
+pub fn clamp_range(range: Range, content: &str) -> Range {
+    clamp_range_to_lines(range, &get_line_lengths(content))
+}
+
+/// The length in chars of each line of the content
+fn get_line_lengths(content: &str) -> Vec<u32> {
+    content.lines().map(|l| l.chars().count() as u32).collect()
+}
+
+/// Same as clamp_range() with the lengths of the lines of the content, to clamp several ranges
+/// without going through the content each time
+fn clamp_range_to_lines(range: Range, line_lengths: &[u32]) -> Range {
+    let clamp_position = |position: Position| {
+        let line = position
+            .line
+            .min(line_lengths.len().saturating_sub(1) as u32);
+        let line_length = line_lengths.get(line as usize).copied().unwrap_or(0);
+        Position {
+            line,
+            character: position.character.min(line_length),
+        }
+    };
+    let start = clamp_position(range.start);
+    let end = clamp_position(range.end);
+    if (end.line, end.character) < (start.line, start.character) {
+        Range { start, end: start }
+    } else {
+        Range { start, end }
+    }
+}
+
+/// Convert a position into a byte offset in the content, the character of the position is a
+/// number of chars. A position after the end of its line is clamped to the end of the line, and a
+/// position after the last line is clamped to the end of the content.
+pub fn position_to_byte_offset(content: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        if index == position.line as usize {
+            let line_without_newline = line.trim_end_matches('\n').trim_end_matches('\r');
+            let in_line_offset = line_without_newline
+                .char_indices()
+                .nth(position.character as usize)
+                .map(|(offset, _)| offset)
+                .unwrap_or(line_without_newline.len());
+            return line_start + in_line_offset;
+        }
+        line_start += line.len();
+    }
+    content.len()
+}
+
 pub fn range_on_line_part(line: u32, start: u32, end: u32) -> Range {
     Range {
         start: Position {
@@ -184,3 +670,504 @@ pub fn range_on_line_part(line: u32, start: u32, end: u32) -> Range {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::common::tests::{
+        COURSE_SPEC, TESTING_COURSE_SPEC, TESTING_EXOS_SPEC, TESTING_SKILLS_SPEC,
+    };
+    use crate::error::{ParseError, ParseErrorType};
+    use crate::generic::DyValue;
+    use crate::lexer::KeyMatcher;
+    use crate::spec::{DYSpec, KeySpec, ValidDYSpec, ValueType};
+    use crate::{
+        ParseOptions, ParseResult, clamp_range, is_valid, parse_headers, parse_incremental,
+        parse_with_options, parse_with_spec, range_on_line_part, range_on_line_with_length,
+        range_on_lines, used_keys,
+    };
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeSet;
+
+    /// Expand the `${...}` references with fixed values, a real transformer could read a config
+    fn expand_references(text: &str) -> String {
+        [("${code}", "PRG1"), ("${year}", "2025")]
+            .iter()
+            .fold(text.to_string(), |text, (reference, value)| {
+                text.replace(reference, value)
+            })
+    }
+
+    #[test]
+    fn test_transformer_can_expand_references() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let options = ParseOptions {
+            transformer: Some(expand_references),
+            ..Default::default()
+        };
+        let result: ParseResult<DyValue> = parse_with_options(
+            &spec,
+            &None,
+            "course ${code} ${year}\ncode PRG1\ngoal Learn C++ in ${code}",
+            &options,
+        );
+        assert_eq!(result.errors, vec![]);
+        assert_eq!(result.items[0].value, "PRG1 2025");
+        assert_eq!(result.items[0].children[1].value, "Learn C++ in PRG1");
+    }
+
+    static TEMPLATE_SPEC: &KeySpec = &KeySpec {
+        id: "template",
+        desc: "The content of the generated course.dy files",
+        subkeys: &[],
+        vt: ValueType::Multiline,
+        max_occurs: Some(1),
+        raw_body: true,
+        ..KeySpec::DEFAULT
+    };
+
+    #[test]
+    fn test_raw_body_keeps_key_lines_as_text() {
+        let spec: &DYSpec = &[COURSE_SPEC, TEMPLATE_SPEC];
+        let spec = ValidDYSpec::new(spec).unwrap();
+        let result: ParseResult<DyValue> = parse_with_spec(
+            &spec,
+            &None,
+            "course PRG1\ncode PRG1\ngoal Learn C++\ntemplate\ncourse ${name}\ncode ${code}\n// not a comment\ngoal\n",
+        );
+        assert_eq!(result.errors, vec![]);
+        assert_eq!(result.items.len(), 2);
+        assert_eq!(result.items[1].key, "template");
+        assert_eq!(
+            result.items[1].value,
+            "course ${name}\ncode ${code}\n// not a comment\ngoal"
+        );
+        assert_eq!(result.items[1].children, vec![]);
+    }
+
+    #[test]
+    fn test_only_doc_comments_are_captured() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let result: ParseResult<DyValue> = parse_with_spec(
+            &spec,
+            &None,
+            "//! The course of the first semester
+// A simple comment
+//!Maintained by the teachers
+course PRG1
+/*
+//! Commented out
+*/
+code PRG1
+goal Learn C++
+//!
+",
+        );
+        assert_eq!(result.errors, vec![]);
+        assert_eq!(
+            result.doc_comments,
+            vec![
+                "The course of the first semester".to_string(),
+                "Maintained by the teachers".to_string(),
+                "".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors_can_be_filtered_by_file_after_merge() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let first_file = Some("first/course.dy".to_string());
+        let second_file = Some("second/course.dy".to_string());
+        let first: ParseResult<DyValue> =
+            parse_with_spec(&spec, &first_file, "course PRG1\ncode PRG1");
+        let second: ParseResult<DyValue> =
+            parse_with_spec(&spec, &second_file, "wrong line\ncourse PRG2\ngoal Learn C");
+        let merged = ParseResult::merge_all(vec![first, second]);
+        assert_eq!(merged.items.len(), 2);
+        assert_eq!(merged.errors.len(), 3);
+        assert_eq!(
+            merged.errors_for_file("first/course.dy"),
+            vec![&ParseError {
+                range: range_on_line_with_length(0, 0),
+                error: ParseErrorType::MissingRequiredKey("goal".to_string()),
+                path: vec![],
+                some_file: first_file,
+                severity: None,
+            }]
+        );
+        assert_eq!(merged.errors_for_file("second/course.dy").len(), 2);
+        assert_eq!(
+            merged.errors_for_file("third/course.dy"),
+            Vec::<&ParseError>::new()
+        );
+    }
+
+    #[test]
+    fn test_incomplete_last_block_has_no_missing_required_errors() {
+        let spec = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let text = "exo first
+check done
+see ok
+
+check being typed
+args --help
+";
+        let incomplete: ParseResult<DyValue> = parse_incremental(&spec, text, false);
+        assert_eq!(incomplete.errors, vec![]);
+        assert_eq!(incomplete.items[0].children.len(), 2);
+
+        let complete: ParseResult<DyValue> = parse_incremental(&spec, text, true);
+        assert_eq!(
+            complete.errors,
+            vec![ParseError::new(
+                range_on_line_with_length(4, 0),
+                ParseErrorType::MissingRequiredKey("see".to_string()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_comment_prefix_can_be_configured() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC)
+            .unwrap()
+            .with_comment_prefix("#")
+            .unwrap();
+        let result: ParseResult<DyValue> = parse_with_spec(
+            &spec,
+            &None,
+            "# A comment
+out of key
+#! The doc
+course PRG1
+code PRG1
+goal Learn C++
+// part of the goal
+",
+        );
+        assert_eq!(
+            result.errors,
+            vec![ParseError::new(
+                range_on_line_with_length(1, 10),
+                ParseErrorType::ContentOutOfKey("#".to_string()),
+            )]
+        );
+        assert!(
+            result.errors[0]
+                .error
+                .to_string()
+                .contains("a comment starting with # or")
+        );
+        assert_eq!(
+            result.items[0].children[1].value,
+            "Learn C++\n// part of the goal"
+        );
+        assert_eq!(result.doc_comments, vec!["The doc".to_string()]);
+        assert!(
+            ValidDYSpec::new(TESTING_COURSE_SPEC)
+                .unwrap()
+                .with_comment_prefix(" ")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_can_coalesce_consecutive_content_out_of_key() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let content = "Some junk
+more junk here
+and the end
+
+alone
+course PRG1
+code PRG1
+goal Learn C++";
+        let options = ParseOptions {
+            coalesce_content_out_of_key: true,
+            ..Default::default()
+        };
+        let result: ParseResult<DyValue> = parse_with_options(&spec, &None, content, &options);
+        assert_eq!(
+            result.errors,
+            vec![
+                ParseError::new(
+                    range_on_lines(0, 2, 11),
+                    ParseErrorType::ContentOutOfKey("//".to_string()),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(4, 5),
+                    ParseErrorType::ContentOutOfKey("//".to_string()),
+                )
+            ]
+        );
+
+        // Without the option, there is one error per line
+        let result: ParseResult<DyValue> = parse_with_spec(&spec, &None, content);
+        assert_eq!(result.errors.len(), 4);
+    }
+
+    #[test]
+    fn test_can_check_validity_without_items() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let valid = "course PRG1\ncode PRG1\ngoal Learn C++";
+        assert!(is_valid(&spec, valid));
+        let invalid_contents = [
+            // Missing required key
+            "course PRG1\ngoal Learn C++",
+            "",
+            "hey\ncourse PRG1\ncode PRG1\ngoal Learn C++",
+            "course PRG1\ncode PRG1\ngoal Learn C++\n/*\ncourse PRG2",
+        ];
+        for content in invalid_contents {
+            assert!(!is_valid(&spec, content), "{content:?} should be invalid");
+        }
+        // Same answer as a full parse, that also creates the items
+        for content in invalid_contents.iter().chain([&valid]) {
+            let result: ParseResult<DyValue> = parse_with_spec(&spec, &None, content);
+            assert_eq!(is_valid(&spec, content), result.errors.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_can_list_used_keys() {
+        let spec = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let content = "exo Hello
+Print a greeting
+// see in a comment
+```
+exit in a code snippet
+```
+check it works
+args --name Jack
+see Your name?
+type John
+see Hello John
+exit 0
+";
+        assert_eq!(
+            used_keys(&spec, content),
+            BTreeSet::from(["exo", "check", "args", "see", "type", "exit"])
+        );
+        assert_eq!(
+            used_keys(
+                &spec,
+                "exo Hello
+// check in a comment
+```
+exit 0
+```"
+            ),
+            BTreeSet::from(["exo"])
+        );
+    }
+
+    #[test]
+    fn test_key_matcher_can_capture_a_regex() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        // Keys are written in any case, the regex is compiled once and moved in the matcher
+        let first_word = regex::Regex::new(r"^(\w+)( |$)").unwrap();
+        let options = ParseOptions {
+            key_matcher: Some(KeyMatcher::new(move |line, keys| {
+                let word = first_word.captures(line)?.get(1)?.as_str();
+                keys.iter()
+                    .find(|k| k.id.eq_ignore_ascii_case(word))
+                    .copied()
+            })),
+            ..Default::default()
+        };
+        let result: ParseResult<DyValue> = parse_with_options(
+            &spec,
+            &None,
+            "Course PRG1\nCODE PRG1\ngoal Learn C++",
+            &options,
+        );
+        assert_eq!(result.errors, vec![]);
+        assert_eq!(result.items[0].value, "PRG1");
+        assert_eq!(result.items[0].children.len(), 2);
+    }
+
+    #[test]
+    fn test_allow_directive_ignores_errors_on_the_next_line() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let result: ParseResult<DyValue> = parse_with_spec(
+            &spec,
+            &None,
+            "course PRG1
+code PRG1
+goal Learn C++
+// dy:allow(duplicated-key)
+course PRG2
+// dy:allow(content-out-of-key, missing-required-key)
+course PRG3
+",
+        );
+        assert_eq!(
+            result.errors,
+            vec![ParseError::new(
+                range_on_line_with_length(6, 11),
+                ParseErrorType::DuplicatedKey("course".to_string(), 0),
+            )]
+        );
+        // The validity check applies the directives too
+        assert!(is_valid(
+            &spec,
+            "course PRG1\ncode PRG1\ngoal Learn C++\n// dy:allow(duplicated-key)\ncourse PRG2"
+        ));
+        assert!(!is_valid(
+            &spec,
+            "course PRG1\ncode PRG1\ngoal Learn C++\ncourse PRG2"
+        ));
+    }
+
+    #[test]
+    fn test_shebang_line_is_ignored() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let content = "#!/usr/bin/env dy\ncourse PRG1\ncode PRG1\ngoal Learn C++";
+        let result: ParseResult<DyValue> = parse_with_spec(&spec, &None, content);
+        assert_eq!(result.errors, vec![]);
+        assert_eq!(result.items[0].value, "PRG1");
+
+        let (blocks, _) = crate::parser::build_blocks_tree(
+            &spec,
+            crate::lexer::tokenize_into_lines(&spec, content),
+        );
+        assert_eq!(blocks[0].range, range_on_line_with_length(1, 11));
+        assert_eq!(
+            blocks[0].subblocks[1].range,
+            range_on_line_with_length(3, 14)
+        );
+
+        // Even with # as comment prefix, it is not a doc comment
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC)
+            .unwrap()
+            .with_comment_prefix("#")
+            .unwrap();
+        let result: ParseResult<DyValue> = parse_with_spec(&spec, &None, content);
+        assert_eq!(result.errors, vec![]);
+        assert_eq!(result.doc_comments, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_ranges_count_chars_of_accented_text() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let result: ParseResult<DyValue> = parse_with_spec(
+            &spec,
+            &Some("course.dy".to_string()),
+            "Éléments hors clé\ncourse Précision\ncode PRÉ\ngoal Éléments de précision",
+        );
+        assert_eq!(
+            result.errors,
+            vec![ParseError::new(
+                range_on_line_with_length(0, 17),
+                ParseErrorType::ContentOutOfKey("//".to_string()),
+            )]
+        );
+        assert!(
+            format!("{result}").contains("\nÉléments hors clé\n^^^^^^^^^^^^^^^^^ This content")
+        );
+
+        let (blocks, _) = crate::parser::build_blocks_tree(
+            &spec,
+            crate::lexer::tokenize_into_lines(
+                &spec,
+                "course Précision\ncode PRÉ\ngoal Éléments de précision",
+            ),
+        );
+        assert_eq!(blocks[0].range, range_on_line_with_length(0, 16));
+        assert_eq!(
+            blocks[0].subblocks[1].range,
+            range_on_line_with_length(2, 26)
+        );
+    }
+
+    #[test]
+    fn test_summary_and_exit_code() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let ok: ParseResult<DyValue> =
+            parse_with_spec(&spec, &None, "course PRG1\ncode PRG1\ngoal Learn C++");
+        assert_eq!(ok.summary(), (0, 0, true));
+        assert_eq!(ok.to_exit_code(), 0);
+
+        let mut with_warning = ok;
+        with_warning.errors.push(ParseError::new(
+            range_on_line_with_length(2, 14),
+            ParseErrorType::UnbalancedInlineCode,
+        ));
+        assert_eq!(with_warning.summary(), (0, 1, true));
+        assert_eq!(with_warning.to_exit_code(), 0);
+
+        let failed: ParseResult<DyValue> = parse_with_spec(&spec, &None, "course PRG1\ncode PRG1");
+        assert_eq!(failed.summary(), (1, 0, false));
+        assert_ne!(failed.to_exit_code(), 0);
+    }
+
+    #[test]
+    fn test_empty_documents_only_miss_required_keys() {
+        let spec = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        for text in ["", "   \n\n", "// just a comment\n\n// and another one"] {
+            let result: ParseResult<DyValue> = parse_with_spec(&spec, &None, text);
+            assert_eq!(result.items, vec![]);
+            assert_eq!(
+                result.errors,
+                vec![ParseError::new(
+                    range_on_line_with_length(0, 0),
+                    ParseErrorType::MissingRequiredKey("course".to_string()),
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn test_can_parse_only_headers() {
+        let text = "// skills
+skill Classes
+description of classes
+subskill Constructors
+skill Operators
+subskill skill not a header";
+        let spec = ValidDYSpec::new(TESTING_SKILLS_SPEC).unwrap();
+        assert_eq!(
+            parse_headers(&spec, text),
+            vec![
+                (
+                    "skill".to_string(),
+                    "Classes".to_string(),
+                    range_on_line_with_length(1, 13)
+                ),
+                (
+                    "skill".to_string(),
+                    "Operators".to_string(),
+                    range_on_line_with_length(4, 15)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_can_clamp_range_exceeding_content() {
+        let content = "course PRG1\ncode PRG1\n";
+        // The end exceeds the last line
+        assert_eq!(
+            clamp_range(range_on_lines(0, 5, 20), content),
+            range_on_lines(0, 1, 9)
+        );
+        // The end exceeds the last character
+        assert_eq!(
+            clamp_range(range_on_line_part(1, 2, 40), content),
+            range_on_line_part(1, 2, 9)
+        );
+        // A valid range is not changed
+        assert_eq!(
+            clamp_range(range_on_line_part(0, 0, 6), content),
+            range_on_line_part(0, 0, 6)
+        );
+        // The end before the start is moved to the start
+        assert_eq!(
+            clamp_range(range_on_line_part(0, 5, 2), content),
+            range_on_line_part(0, 5, 5)
+        );
+        assert_eq!(
+            clamp_range(range_on_line_part(3, 5, 8), ""),
+            range_on_line_part(0, 0, 0)
+        );
+    }
+}
@@ -0,0 +1,378 @@
+/// Optional lints running on the tokenized lines, they are all disabled by default and enabled via ParseOptions
+use crate::{
+    error::{ParseError, ParseErrorType},
+    lexer::{Line, LineType, is_code_fence, is_partial_key},
+    range_on_line_part, range_on_line_with_length,
+    spec::{KeySpec, ValidDYSpec, all_valid_keys},
+};
+
+/// Report value lines of multiline keys that contain an odd number of single backticks, as this
+/// is an inline code span that is not terminated. Lines inside code snippets are ignored.
+pub(crate) fn lint_unbalanced_inline_code(lines: &[Line]) -> Vec<ParseError> {
+    let mut errors = Vec::new();
+    let mut inside_multiline_value = false;
+    let mut inside_a_markdown_code_snippet = false;
+
+    for line in lines {
+        match line.lt {
+            LineType::WithKey(key_spec) => {
+                inside_multiline_value = !key_spec.vt.is_single_line();
+            }
+            LineType::Comment => continue,
+            LineType::Unknown => {
+                if is_code_fence(line.slice) {
+                    inside_a_markdown_code_snippet = !inside_a_markdown_code_snippet;
+                    continue;
+                }
+            }
+        }
+
+        if inside_multiline_value
+            && !inside_a_markdown_code_snippet
+            && count_single_backticks(line.slice) % 2 == 1
+        {
+            errors.push(ParseError::new(
+                range_on_line_with_length(line.index as u32, line.slice.chars().count() as u32),
+                ParseErrorType::UnbalancedInlineCode,
+            ));
+        }
+    }
+    errors
+}
+
+/// Report the last non empty line of the content if it's a single word being the start of a key,
+/// as it's probably a key being typed at the end of the file, like `cod` for `code`
+pub(crate) fn lint_partial_key_at_eof(spec: &ValidDYSpec, lines: &[Line]) -> Vec<ParseError> {
+    let Some(last_line) = lines
+        .iter()
+        .rev()
+        .find(|line| !line.slice.trim().is_empty())
+    else {
+        return Vec::new();
+    };
+    if last_line.lt != LineType::Unknown || last_line.slice.contains(' ') {
+        return Vec::new();
+    }
+    match is_partial_key(spec, last_line.slice) {
+        Some(key) => vec![ParseError::new(
+            range_on_line_with_length(
+                last_line.index as u32,
+                last_line.slice.chars().count() as u32,
+            ),
+            ParseErrorType::PartialKey(key.id.to_string()),
+        )],
+        None => Vec::new(),
+    }
+}
+
+/// Report lines where the value starts with the key itself, like `code code PRG1`, as the key was
+/// probably typed twice. There are false positives when the value legitimately starts with the
+/// same word, like `see see you later`, that's why this lint is opt-in.
+pub(crate) fn lint_repeated_key_in_value(lines: &[Line]) -> Vec<ParseError> {
+    let mut errors = Vec::new();
+    for line in lines {
+        if let LineType::WithKey(key_spec) = line.lt {
+            let key = &line.slice[..line.key_len()];
+            let value = &line.slice[key.len()..];
+            let value_start = key.len() + value.len() - value.trim_start().len();
+            if value.trim_start().split(' ').next() == Some(key) {
+                errors.push(ParseError::new(
+                    range_on_line_part(
+                        line.index as u32,
+                        value_start as u32,
+                        (value_start + key.len()) as u32,
+                    ),
+                    ParseErrorType::RepeatedKeyInValue(key_spec.id.to_string()),
+                ));
+            }
+        }
+    }
+    errors
+}
+
+/// Report the last line of multiline values when its first word is a key that could come right
+/// after this value (a subkey or a sibling key), but written in a way that is not detected as a key,
+/// like ` check foo` or `Check foo` at the end of an exo instruction. The key was probably meant to
+/// start a new block, the line is still kept in the value. Lines inside code snippets are ignored.
+pub(crate) fn lint_possible_missing_key(spec: &ValidDYSpec, lines: &[Line]) -> Vec<ParseError> {
+    let mut errors = Vec::new();
+    let mut current_key: Option<&KeySpec> = None;
+    let mut last_value_line: Option<&Line> = None;
+    let mut inside_a_markdown_code_snippet = false;
+
+    for line in lines {
+        match line.lt {
+            LineType::WithKey(key_spec) => {
+                errors.extend(possible_missing_key(spec, current_key, last_value_line));
+                current_key = (!key_spec.vt.is_single_line()).then_some(key_spec);
+                last_value_line = None;
+            }
+            LineType::Comment => {}
+            LineType::Unknown => {
+                if is_code_fence(line.slice) {
+                    inside_a_markdown_code_snippet = !inside_a_markdown_code_snippet;
+                    last_value_line = Some(line);
+                } else if !inside_a_markdown_code_snippet && !line.slice.trim().is_empty() {
+                    last_value_line = Some(line);
+                }
+            }
+        }
+    }
+    errors.extend(possible_missing_key(spec, current_key, last_value_line));
+    errors
+}
+
+fn possible_missing_key(
+    spec: &ValidDYSpec,
+    key: Option<&KeySpec>,
+    last_value_line: Option<&Line>,
+) -> Option<ParseError> {
+    let (key, line) = (key?, last_value_line?);
+    let first_word = line.slice.split_whitespace().next()?;
+    let siblings = all_valid_keys(spec.get())
+        .into_iter()
+        .find(|k| k.subkeys.iter().any(|s| s.id == key.id))
+        .map_or(spec.get(), |parent| parent.subkeys);
+    let found = key
+        .subkeys
+        .iter()
+        .chain(siblings.iter())
+        .find(|k| k.id.eq_ignore_ascii_case(first_word))?;
+    Some(ParseError::new(
+        range_on_line_with_length(line.index as u32, line.slice.chars().count() as u32),
+        ParseErrorType::PossibleMissingKey(found.id.to_string()),
+    ))
+}
+
+/// Report the key lines that directly follow a line of a multiline value (without an empty line
+/// between) and whose key cannot be used at this position, like `type anything` in the middle of an
+/// exo instruction. The line was probably meant as content but it has been taken as a misplaced key,
+/// it can be escaped with a backslash to stay in the value.
+pub(crate) fn lint_ambiguous_key_like_value(spec: &ValidDYSpec, lines: &[Line]) -> Vec<ParseError> {
+    let mut errors = Vec::new();
+    // The keys of the currently opened blocks, from the top-level one
+    let mut parents: Vec<&KeySpec> = Vec::new();
+    let mut previous_line_in_value = false;
+
+    for line in lines {
+        match line.lt {
+            LineType::WithKey(key_spec) => {
+                let level = (0..=parents.len()).rev().find(|&level| {
+                    let valid_keys = if level == 0 {
+                        spec.get()
+                    } else {
+                        parents[level - 1].subkeys
+                    };
+                    valid_keys.iter().any(|k| std::ptr::eq(*k, key_spec))
+                });
+                match level {
+                    Some(level) => {
+                        parents.truncate(level);
+                        parents.push(key_spec);
+                        previous_line_in_value = !key_spec.vt.is_single_line();
+                    }
+                    None => {
+                        if previous_line_in_value {
+                            errors.push(ParseError::new(
+                                range_on_line_part(line.index as u32, 0, line.key_len() as u32),
+                                ParseErrorType::AmbiguousKeyLikeValue(key_spec.id.to_string()),
+                            ));
+                        }
+                    }
+                }
+            }
+            LineType::Comment => {}
+            LineType::Unknown => {
+                previous_line_in_value = parents.last().is_some_and(|k| !k.vt.is_single_line())
+                    && !line.slice.trim().is_empty();
+            }
+        }
+    }
+    errors
+}
+
+/// Report the lines that have more chars than `max`, from the limit to the end of the line
+pub(crate) fn lint_line_too_long(lines: &[Line], max: u32) -> Vec<ParseError> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let length = line.slice.chars().count() as u32;
+            (length > max).then(|| {
+                ParseError::new(
+                    range_on_line_part(line.index as u32, max, length),
+                    ParseErrorType::LineTooLong(max),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Count the backticks that are not part of a sequence of several backticks
+fn count_single_backticks(text: &str) -> usize {
+    text.split(|c| c != '`')
+        .filter(|backticks| backticks.len() == 1)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::tests::{TESTING_COURSE_SPEC, TESTING_EXOS_SPEC};
+    use crate::error::{ParseError, ParseErrorType};
+    use crate::lexer::tokenize_into_lines;
+    use crate::lint::{
+        lint_ambiguous_key_like_value, lint_line_too_long, lint_partial_key_at_eof,
+        lint_possible_missing_key, lint_repeated_key_in_value, lint_unbalanced_inline_code,
+    };
+    use crate::parser::build_blocks_tree;
+    use crate::range_on_line_part;
+    use crate::range_on_line_with_length;
+    use crate::spec::ValidDYSpec;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_can_detect_unbalanced_inline_code() {
+        let text = "exo hey
+use the `printf function
+use the `scanf` function
+```c
+char c = '`';
+```
+check test
+see hey";
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let lines = tokenize_into_lines(&binding, text);
+        assert_eq!(
+            lint_unbalanced_inline_code(&lines),
+            vec![ParseError::new(
+                range_on_line_with_length(1, 24),
+                ParseErrorType::UnbalancedInlineCode,
+            )]
+        );
+    }
+
+    #[test]
+    fn test_can_detect_partial_key_at_eof() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let text = "course Programmation 1\ncod\n\n";
+        let lines = tokenize_into_lines(&binding, text);
+        assert_eq!(
+            lint_partial_key_at_eof(&binding, &lines),
+            vec![ParseError::new(
+                range_on_line_with_length(1, 3),
+                ParseErrorType::PartialKey("code".to_string()),
+            )]
+        );
+
+        // Only the last line is considered
+        let text = "course Programmation 1\ncod\ncode PRG1";
+        let lines = tokenize_into_lines(&binding, text);
+        assert_eq!(lint_partial_key_at_eof(&binding, &lines), vec![]);
+    }
+
+    #[test]
+    fn test_can_detect_possible_missing_key() {
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let text = "exo hey
+Some instruction
+ check foo
+check ok
+see the output
+```
+exit in a code snippet
+```
+";
+        let lines = tokenize_into_lines(&binding, text);
+        assert_eq!(
+            lint_possible_missing_key(&binding, &lines),
+            vec![ParseError::new(
+                range_on_line_with_length(2, 10),
+                ParseErrorType::PossibleMissingKey("check".to_string()),
+            )]
+        );
+        // The line is still part of the instruction
+        let (blocks, _) = build_blocks_tree(&binding, lines);
+        assert_eq!(blocks[0].text.last(), Some(&" check foo"));
+
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let lines = tokenize_into_lines(&binding, "course PRG1\ngoal Learn C++\nCode PRG2");
+        assert_eq!(
+            lint_possible_missing_key(&binding, &lines),
+            vec![ParseError::new(
+                range_on_line_with_length(2, 9),
+                ParseErrorType::PossibleMissingKey("code".to_string()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_can_detect_repeated_key_in_value() {
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let text = "exo hey
+check test
+see  see you later
+type types are fine
+exit 0";
+        let lines = tokenize_into_lines(&binding, text);
+        // Note: this one is a false positive, the value really starts with "see"
+        assert_eq!(
+            lint_repeated_key_in_value(&lines),
+            vec![ParseError::new(
+                range_on_line_part(2, 5, 8),
+                ParseErrorType::RepeatedKeyInValue("see".to_string()),
+            )]
+        );
+
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let lines = tokenize_into_lines(&binding, "course PRG1\ncode code PRG1\ngoal code");
+        assert_eq!(
+            lint_repeated_key_in_value(&lines),
+            vec![ParseError::new(
+                range_on_line_part(1, 5, 9),
+                ParseErrorType::RepeatedKeyInValue("code".to_string()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_can_detect_lines_too_long() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let text = "course PRG1\ncode PRG1\ngoal Apprendre à écrire du C++\n// a long comment too";
+        let lines = tokenize_into_lines(&binding, text);
+        assert_eq!(
+            lint_line_too_long(&lines, 20),
+            vec![
+                ParseError::new(
+                    range_on_line_part(2, 20, 30),
+                    ParseErrorType::LineTooLong(20),
+                ),
+                ParseError::new(
+                    range_on_line_part(3, 20, 21),
+                    ParseErrorType::LineTooLong(20),
+                )
+            ]
+        );
+        assert_eq!(lint_line_too_long(&lines, 30), vec![]);
+    }
+
+    #[test]
+    fn test_can_detect_ambiguous_key_like_value() {
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let text = "exo hey
+Write the program and
+type anything
+\\type escaped stays in the value
+
+type after an empty line is just misplaced
+check ok
+type this is valid here
+";
+        let lines = tokenize_into_lines(&binding, text);
+        assert_eq!(
+            lint_ambiguous_key_like_value(&binding, &lines),
+            vec![ParseError::new(
+                range_on_line_part(2, 0, 4),
+                ParseErrorType::AmbiguousKeyLikeValue("type".to_string()),
+            )]
+        );
+    }
+}
@@ -1,23 +1,31 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::iter::Peekable;
+use std::num::ParseIntError;
+use std::str::ParseBoolError;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 /// The semantic analyzer is responsible for building tree of blocks, building and verifying the hierarchy as the tree is built and at the end
 use lsp_types::{Position, Range};
+use regex::Regex;
+use serde::Serialize;
 
-use crate::range_on_line_part;
+use crate::{ParseOptions, position_to_byte_offset, range_on_line_part};
 use crate::{
-    error::{ParseError, ParseErrorType},
-    lexer::{Line, LinePart, LineType},
+    error::{ParseError, ParseErrorType, Severity},
+    lexer::{Line, LinePart, LineType, is_partial_key_of},
     range_on_line_with_length,
-    spec::{DYSpec, KeySpec, ValidDYSpec},
+    spec::{DYSpec, KeySpec, MergeMode, ValidDYSpec, ValueType, all_valid_keys},
 };
 
-#[derive(PartialEq)]
+#[derive(Clone)]
 /// A block represents the instance of a key found in the text, including subblocks for subkeys.
 /// A block has a textual value for its key under field `text`
 pub struct Block<'a> {
     pub key: &'a KeySpec<'a>,
+    /// The key exactly as it was written in the text, the canonical id is available under `key.id`
+    pub matched_key_text: &'a str,
     /// The text contained in the value of this block, when multiline it can contains several &str
     /// This doesn't contain the key
     pub text: Vec<&'a str>,
@@ -25,57 +33,491 @@ pub struct Block<'a> {
     pub range: Range,
     /// The sub blocks
     pub subblocks: Vec<Block<'a>>,
+    /// Applied on the joined text when it is accessed, see ParseOptions::transformer
+    pub transformer: Option<TextTransformer>,
+    /// The lines of the document exactly as written with their line terminator, from the key line
+    /// of this block to the next block, including comments and invalid lines. Only filled with
+    /// ParseOptions::preserve_all.
+    pub raw: Vec<&'a str>,
+    /// Created by the parser to wrap an orphan key, see is_synthetic()
+    pub synthetic: bool,
+    /// The block is still being typed, the missing required keys and values are not reported in
+    /// it and in its subblocks. Set on the last top-level block with
+    /// ParseOptions::incomplete_last_block.
+    pub incomplete: bool,
+    /// The structural identity of this block, see path_id()
+    pub path_id: String,
+    /// The range of the value as written, from its first to its last non blank character, it can
+    /// start on the line after the key. Empty when the value is empty.
+    pub value_range: Range,
 }
 
+/// A function to post-process the joined value of blocks, like for variable substitution
+pub type TextTransformer = fn(&str) -> String;
+
 impl<'a> Block<'a> {
     /// Push a new line of text, with given line and the line index where it was found
     /// The line_index is necessary because comments could be present in the middle of the text
     fn push_text(&mut self, line: &'a str, line_index: usize) {
         self.text.push(line);
         self.range.end.line = line_index as u32;
-        self.range.end.character = line.len() as u32;
+        self.range.end.character = line.chars().count() as u32;
+        self.extend_value_range(line, line_index, 0);
+    }
+
+    /// Extend the value_range to the non blank characters of the given part of a line, that
+    /// starts at the given character
+    fn extend_value_range(&mut self, part: &str, line_index: usize, start_character: u32) {
+        let trimmed_start = part.trim_start();
+        let trimmed = trimmed_start.trim_end();
+        if trimmed.is_empty() {
+            return;
+        }
+        let start = start_character + (part.chars().count() - trimmed_start.chars().count()) as u32;
+        let end = start + trimmed.chars().count() as u32;
+        if self.value_range.start == self.value_range.end {
+            self.value_range.start = Position::new(line_index as u32, start);
+        }
+        self.value_range.end = Position::new(line_index as u32, end);
     }
 
     /// Get the different recolted lines into a single String, after triming the final text
     pub fn get_joined_text(&self) -> String {
-        self.text.join("\n").trim().to_string()
+        if self.key.vt == ValueType::Raw {
+            return self.transform(self.get_raw_text());
+        }
+        self.transform(self.text.join("\n").trim().to_string())
+    }
+
+    /// Join the lines of a ValueType::Raw value without trimming them. The empty lines at the end
+    /// are ignored, and the empty first line when the value starts on the line after the key.
+    fn get_raw_text(&self) -> String {
+        let mut lines = self.text.as_slice();
+        while let [rest @ .., last] = lines
+            && !rest.is_empty()
+            && last.trim().is_empty()
+        {
+            lines = rest;
+        }
+        if let [first, rest @ ..] = lines
+            && first.is_empty()
+            && !rest.is_empty()
+        {
+            lines = rest;
+        }
+        lines.join("\n")
+    }
+
+    /// Apply the transformer on the given text, if there is one
+    fn transform(&self, text: String) -> String {
+        match self.transformer {
+            Some(transformer) => transformer(&text),
+            None => text,
+        }
+    }
+
+    /// Get the value of a block of a single line key, or an InvalidMultilineContent error if there is
+    /// text after the first line. Parsed blocks already respect this, but blocks built or edited by
+    /// hand might not.
+    pub fn require_single_line(&self) -> Result<String, Box<ParseError>> {
+        if self.text.iter().skip(1).any(|line| !line.trim().is_empty()) {
+            return Err(Box::new(ParseError::new(
+                self.range,
+                ParseErrorType::InvalidMultilineContent(self.key.id.to_string()),
+            )));
+        }
+        let first = self.text.first().unwrap_or(&"");
+        if self.key.vt == ValueType::Raw {
+            return Ok(self.transform(first.to_string()));
+        }
+        Ok(self.transform(first.trim().to_string()))
+    }
+
+    /// Get the value of a block of a multiline key, all lines are joined, this is the same as
+    /// get_joined_text() but makes the expected value type visible in the entity code
+    pub fn require_multiline(&self) -> String {
+        self.get_joined_text()
+    }
+
+    /// Get the joined text of the first subblock of the given key, or the key's default value
+    /// if there is no such subblock
+    pub fn get_subvalue_or_default(&self, key: &KeySpec) -> Option<String> {
+        self.get_subvalue(key.id)
+            .or_else(|| key.default.map(|d| d.to_string()))
+    }
+
+    /// Get the joined text of the first subblock with the given key id, if any
+    pub fn get_subvalue(&self, key_id: &str) -> Option<String> {
+        self.find_subblock(key_id).map(|b| b.get_joined_text())
+    }
+
+    /// The number of chars of the key as written, it can be an alias of the key. A synthetic block
+    /// has no key written, the length of the key id is given.
+    pub fn key_len(&self) -> u32 {
+        if self.is_synthetic() {
+            self.key.id.chars().count() as u32
+        } else {
+            self.matched_key_text.chars().count() as u32
+        }
+    }
+
+    /// Get the first direct subblock with the given key id, if any
+    pub fn find_subblock(&self, key_id: &str) -> Option<&Block<'a>> {
+        self.subblocks.iter().find(|b| b.key.id == key_id)
+    }
+
+    /// Iterate over the direct subblocks with the given key id, in the document order
+    pub fn subblocks_with<'s>(&'s self, key_id: &'s str) -> impl Iterator<Item = &'s Block<'a>> {
+        self.subblocks.iter().filter(move |b| b.key.id == key_id)
+    }
+
+    /// Call `f` on this block and all its subblocks recursively, depth first in the document
+    /// order, with the depth of the block (0 for this block)
+    pub fn walk<F: FnMut(&Block<'a>, u8)>(&self, mut f: F) {
+        self.walk_at(0, &mut f);
+    }
+
+    fn walk_at<F: FnMut(&Block<'a>, u8)>(&self, depth: u8, f: &mut F) {
+        f(self, depth);
+        for subblock in self.subblocks.iter() {
+            subblock.walk_at(depth + 1, f);
+        }
+    }
+
+    /// Extract the subblocks with the `record_key` as records, each record maps the given field
+    /// keys to the joined text of its first subblock with this key. Absent fields are not in the map.
+    pub fn collect_records<'k>(
+        &self,
+        record_key: &str,
+        field_keys: &[&'k str],
+    ) -> Vec<HashMap<&'k str, String>> {
+        self.subblocks_with(record_key)
+            .map(|record| {
+                field_keys
+                    .iter()
+                    .filter_map(|field| record.get_subvalue(field).map(|value| (*field, value)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Whether this block has been created by the parser to wrap an orphan key, with the
+    /// `recover_orphans` option. It has no text and its key doesn't exist in the content.
+    pub fn is_synthetic(&self) -> bool {
+        self.synthetic
+    }
+
+    /// The structural identity of this block, like `exo[0]/check[1]/see[0]`, where the index is
+    /// the occurrence of the key among its siblings. Unlike the range, it doesn't change when
+    /// unrelated lines are edited, so it can be used as a cache key. Set when building the tree.
+    pub fn path_id(&self) -> String {
+        self.path_id.clone()
+    }
+
+    /// Whether the text is empty once trimmed, same as `get_joined_text().is_empty()` without allocating
+    pub fn text_is_empty(&self) -> bool {
+        self.text.iter().all(|line| line.trim().is_empty())
+    }
+
+    /// The range of this block extended to the end of its last subblock, recursively
+    pub fn full_range(&self) -> Range {
+        let mut range = self.range;
+        if let Some(last_subblock) = self.subblocks.last() {
+            range.end = last_subblock.full_range().end;
+        }
+        range
+    }
+
+    /// Get the exact source of this block in the given content, including its subblocks. The
+    /// content must be the one that has been parsed to build this block.
+    pub fn source<'c>(&self, content: &'c str) -> &'c str {
+        let range = self.full_range();
+        &content[position_to_byte_offset(content, range.start)
+            ..position_to_byte_offset(content, range.end)]
+    }
+
+    /// Render this block and its subblocks as an indented outline, one line per block with the key
+    /// id, the first line of the value and the range. Each level adds 2 spaces of indentation.
+    pub fn to_outline(&self, indent: usize) -> String {
+        let mut outline = format!(
+            "{}{} {} ({:?})\n",
+            "  ".repeat(indent),
+            self.key.id,
+            self.text.first().map(|t| t.trim()).unwrap_or_default(),
+            NiceRange(&self.range)
+        );
+        for subblock in self.subblocks.iter() {
+            outline.push_str(&subblock.to_outline(indent + 1));
+        }
+        outline
+    }
+
+    /// Parse the joined text as a duration, see parse_duration(). The parser reports the invalid
+    /// values as InvalidDuration errors for keys of type ValueType::Duration
+    pub fn get_duration(&self) -> Option<Duration> {
+        parse_duration(&self.get_joined_text())
+    }
+
+    /// Parse the trimmed joined text as an integer. The parser reports the invalid values as
+    /// InvalidInteger errors for keys of type ValueType::Integer
+    pub fn value_as_i32(&self) -> Result<i32, ParseIntError> {
+        self.get_joined_text().trim().parse()
+    }
+
+    /// Parse the trimmed joined text as `true` or `false`. The parser reports the invalid values as
+    /// InvalidBool errors for keys of type ValueType::Bool
+    pub fn value_as_bool(&self) -> Result<bool, ParseBoolError> {
+        self.get_joined_text().trim().parse()
+    }
+
+    /// Split the joined text on the given delimiter, with trimmed entries. Empty entries are
+    /// skipped, the parser reports them as EmptyListEntry errors for keys of type ValueType::List
+    pub fn get_list(&self, delimiter: char) -> Vec<String> {
+        self.get_joined_text()
+            .split(delimiter)
+            .map(|entry| entry.trim())
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| entry.to_string())
+            .collect()
+    }
+
+    /// The index in `text` of the line containing the name of an entity: the value of the key line,
+    /// or the first non empty line after it when the key line has no value, like `exo` followed by
+    /// the name on the next line
+    pub fn name_line_index(&self) -> usize {
+        self.text
+            .iter()
+            .position(|line| !line.trim().is_empty())
+            .unwrap_or(0)
     }
 
     /// Split joined text with at split the text after `split_after_lines` lines and returns a tuple of both trim results
     pub fn get_text_with_joined_splits_at(&self, split_after_lines: usize) -> (String, String) {
         let (first, second) = self.text.split_at(split_after_lines);
         (
-            first.join("\n").trim().to_string(),
-            second.join("\n").trim().to_string(),
+            self.transform(first.join("\n").trim().to_string()),
+            self.transform(second.join("\n").trim().to_string()),
+        )
+    }
+
+    /// Same as the first part of `get_text_with_joined_splits_at`, like to get the name of an
+    /// entity without joining its description
+    pub fn get_text_before(&self, split_after_lines: usize) -> String {
+        let first = &self.text[..split_after_lines.min(self.text.len())];
+        self.transform(first.join("\n").trim().to_string())
+    }
+
+    /// Same as the second part of `get_text_with_joined_splits_at` but the text is only joined,
+    /// trimmed and transformed when it is accessed, useful for big values that are not always read
+    pub fn get_lazy_text_after(&self, split_after_lines: usize) -> LazyText<'a> {
+        let second = self.text.get(split_after_lines..).unwrap_or_default();
+        LazyText::new(second.to_vec(), self.transformer)
+    }
+}
+
+/// The lines of a value kept as written, they are joined, trimmed and given to the transformer only
+/// on first access via `get()`
+#[derive(Default, Clone)]
+pub struct LazyText<'a> {
+    lines: Vec<&'a str>,
+    transformer: Option<TextTransformer>,
+    text: OnceLock<String>,
+}
+
+impl<'a> LazyText<'a> {
+    pub fn new(lines: Vec<&'a str>, transformer: Option<TextTransformer>) -> Self {
+        LazyText {
+            lines,
+            transformer,
+            text: OnceLock::new(),
+        }
+    }
+
+    /// Get the trimmed text, the lines are joined and transformed on first call only
+    pub fn get(&self) -> &str {
+        self.text.get_or_init(|| {
+            let joined = self.lines.join("\n");
+            match self.transformer {
+                Some(transformer) => transformer(joined.trim()),
+                None if joined.trim().len() == joined.len() => joined,
+                None => joined.trim().to_string(),
+            }
+        })
+    }
+
+    /// Whether the lines have already been joined and transformed
+    pub fn is_joined(&self) -> bool {
+        self.text.get().is_some()
+    }
+
+    /// Get a text that doesn't borrow the lines anymore, they are joined and transformed now
+    pub fn into_owned(self) -> LazyText<'static> {
+        LazyText::from(self.get().to_string())
+    }
+}
+
+impl<'a> From<&'a str> for LazyText<'a> {
+    fn from(value: &'a str) -> Self {
+        LazyText::new(vec![value], None)
+    }
+}
+
+impl From<String> for LazyText<'static> {
+    fn from(value: String) -> Self {
+        LazyText {
+            text: OnceLock::from(value.trim().to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+impl<'a> PartialEq for LazyText<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl<'a> Debug for LazyText<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.get(), f)
+    }
+}
+
+impl<'a> Serialize for LazyText<'a> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.get())
+    }
+}
+
+/// A shorter display of Range, like `1:0-3:12`
+pub(crate) struct NiceRange<'a>(pub(crate) &'a Range);
+impl<'a> Debug for NiceRange<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}-{}:{}",
+            &self.0.start.line, &self.0.start.character, &self.0.end.line, &self.0.end.character,
         )
     }
 }
 
+/// The transformer is ignored, function pointers cannot be compared reliably. Keys are compared by
+/// id, they are unique in a spec. The path id and the value range are ignored, they are derived
+/// from the position in the tree and from the text.
+impl<'a> PartialEq for Block<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key.id == other.key.id
+            && self.matched_key_text == other.matched_key_text
+            && self.text == other.text
+            && self.range == other.range
+            && self.subblocks == other.subblocks
+            && self.raw == other.raw
+            && self.synthetic == other.synthetic
+            && self.incomplete == other.incomplete
+    }
+}
+
 // Implement Debug so we can have a shorter display of Range
 impl<'a> Debug for Block<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        struct NiceRange<'a>(&'a Range);
-        impl<'a> Debug for NiceRange<'a> {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                write!(
-                    f,
-                    "{}:{}-{}:{}",
-                    &self.0.start.line,
-                    &self.0.start.character,
-                    &self.0.end.line,
-                    &self.0.end.character,
-                )
-            }
-        }
         f.debug_struct("Block")
             .field("key", &self.key)
+            .field("matched_key_text", &self.matched_key_text)
             .field("text", &self.text)
             .field("range", &NiceRange(&self.range))
             .field("subblocks", &self.subblocks)
+            .field("raw", &self.raw)
+            .field("synthetic", &self.synthetic)
+            .field("incomplete", &self.incomplete)
             .finish()
     }
 }
 
+/// Render the given blocks as an indented outline, see Block::to_outline()
+pub fn blocks_to_outline(blocks: &[Block]) -> String {
+    blocks.iter().map(|b| b.to_outline(0)).collect()
+}
+
+/// A key that can be written at a given position, see suggest_keys_at()
+#[derive(Debug, PartialEq)]
+pub struct KeySuggestion<'a> {
+    pub key: &'a KeySpec<'a>,
+    /// The key is required and not present yet under the same parent
+    pub required_and_missing: bool,
+}
+
+/// Suggest the keys that can be written on the given line, ordered by relevance for completion:
+/// the required keys that are missing first, then the optional ones, in the spec order. The keys
+/// that already reached their maximum occurrences are excluded. The parent is the last block
+/// starting before this line and having subkeys, recursively, the root keys are suggested if there
+/// is none. The `typed` word is the start of a key already written on the line, like `cod`, only
+/// the keys it is a partial key of are suggested, see is_partial_key(). All keys are suggested if
+/// it's empty.
+pub fn suggest_keys_at<'a>(
+    spec: &'a ValidDYSpec,
+    blocks: &[Block<'a>],
+    line: u32,
+    typed: &str,
+) -> Vec<KeySuggestion<'a>> {
+    let mut siblings = blocks;
+    let mut candidates: &'a DYSpec<'a> = spec.get();
+    while let Some(parent) = siblings
+        .iter()
+        .rev()
+        .find(|b| b.range.start.line < line)
+        .filter(|b| !b.key.subkeys.is_empty())
+    {
+        siblings = &parent.subblocks;
+        candidates = parent.key.subkeys;
+    }
+    let mut suggestions: Vec<KeySuggestion> = candidates
+        .iter()
+        .filter(|key| typed.is_empty() || is_partial_key_of(key, typed))
+        .filter_map(|key| {
+            let occurrences = siblings.iter().filter(|b| b.key.id == key.id).count() as u32;
+            let present = occurrences > 0;
+            if !key.allow_duplicates && key.max_occurs.is_some_and(|max| occurrences >= max) {
+                return None;
+            }
+            Some(KeySuggestion {
+                key,
+                required_and_missing: key.required && !present,
+            })
+        })
+        .collect();
+    // The sort is stable so the spec order is kept in each group
+    suggestions.sort_by_key(|suggestion| !suggestion.required_and_missing);
+    suggestions
+}
+
+/// Get the Block::path_id() of each block of the tree, blocks are given depth-first.
+pub fn blocks_path_ids<'b, 'a>(blocks: &'b [Block<'a>]) -> Vec<(String, &'b Block<'a>)> {
+    blocks
+        .iter()
+        .flat_map(|block| {
+            std::iter::once((block.path_id(), block)).chain(blocks_path_ids(&block.subblocks))
+        })
+        .collect()
+}
+
+/// Get the value of every leaf block (without subblocks) with its dotted path of key ids, like
+/// `exo.check.args` and `1 2 3`, to export the tree as flat key values. Values are joined like in
+/// Block::get_joined_text(), several blocks can have the same path. Blocks are given depth-first.
+pub fn flatten_values(blocks: &[Block]) -> Vec<(String, String)> {
+    let mut values = Vec::new();
+    for block in blocks {
+        let mut path: Vec<&str> = Vec::new();
+        block.walk(|block, depth| {
+            path.truncate(depth as usize);
+            path.push(block.key.id);
+            if block.subblocks.is_empty() {
+                values.push((path.join("."), block.get_joined_text()));
+            }
+        });
+    }
+    values
+}
+
 /// Given a flat list of Line, build a blocks tree, with a tree's hierarchy respecting the given tree spec. Return possible hierarchy errors.
 /// It groups Unknown content after a multiline prefix in a single block for the associated key
 /// On each line WithKey we try to determine whether the key is valid at this position
@@ -106,7 +548,7 @@ impl<'a> Debug for Block<'a> {
 /// line 1: WithKey(exo) -> testing "exo" -> ok -> new block
 /// line 2: Unknown -> append its text to the last created block (for "exo")
 /// line 3: Comment -> ignore the comment
-/// line 4: WithKey(see) -> recursive call with subkeys spec -> testing "check" KO, level--, testing "exo" KO -> level == 0 so report the WrongKeyPosition error!
+/// line 4: WithKey(see) -> recursive call with subkeys spec -> testing "check" KO, "see" is not valid at any parent level either, so report the WrongKeyPosition error and stay at this level
 /// line 5: WithKey(check) -> recursive call. testing "check" -> found immediately
 /// line 6: WithKey(args) -> recursive call with subkeys spec -> testing "args" OK new block
 /// line 7: WithKey(see) -> testing "args", testing "see" OK new block
@@ -117,83 +559,433 @@ impl<'a> Debug for Block<'a> {
 ///
 /// See result of structure in test_strange_exo_parsing_can_correctly_ignore_error()
 ///
-/// TODO: there is a very special edge case of error recovering that is not managed. The above
-/// example shows that after the WrongKeyPosition of "see" we continue with "exo", but that's only
-/// because that's the root spec, not because that's the right place to start. If had a
-/// WrongKeyPosition at level 2 and it will go up at level 0 to generate the error, a key at level
-/// 3 right after that will not be correctly extracted...
+/// A key is only given back to the parent levels if it is valid at one of them, otherwise it is
+/// reported where it is found. This way, a misplaced key deep in the tree doesn't close the
+/// current blocks, and a valid key right after it is still extracted at the right level.
 pub fn build_blocks_tree<'a>(
-    spec: &ValidDYSpec,
+    spec: &'a ValidDYSpec,
+    lines: Vec<Line<'a>>,
+) -> (Vec<Block<'a>>, Vec<ParseError>) {
+    // The content is only needed to preserve all the lines
+    build_blocks_tree_with_options(spec, "", lines, &ParseOptions::default())
+}
+
+/// Same as build_blocks_tree() but with the given options, only `recover_orphans`, `transformer`,
+/// `preserve_all` and `incomplete_last_block` are used here. The `content` the lines were
+/// tokenized from is only used by `preserve_all`.
+pub fn build_blocks_tree_with_options<'a>(
+    spec: &'a ValidDYSpec,
+    content: &'a str,
     lines: Vec<Line<'a>>,
+    options: &ParseOptions,
 ) -> (Vec<Block<'a>>, Vec<ParseError>) {
-    let (blocks, mut errors) =
-        build_blocks_subtree_recursive(&mut lines.iter().peekable(), spec.get(), 0);
+    let (mut blocks, mut errors) = build_blocks_subtree_recursive(
+        &mut lines.iter().peekable(),
+        spec.get(),
+        &mut Vec::new(),
+        spec.comment_prefix(),
+        options,
+    );
+
+    if options.incomplete_last_block
+        && let Some(last_block) = blocks.last_mut()
+    {
+        last_block.incomplete = true;
+    }
 
-    check_required_constraint(&blocks, spec.get(), None, &mut errors);
+    errors.extend(validate_blocks(spec, &blocks));
+
+    // Only given after validation, so the errors are about the text as written
+    if let Some(transformer) = options.transformer {
+        set_transformer(&mut blocks, transformer);
+    }
+
+    if options.preserve_all {
+        attach_raw_lines(&mut blocks, content);
+        // Nothing is dropped, so the errors only point to the lines that are not at their place
+        for error in errors.iter_mut() {
+            error.severity = Some(Severity::Warning);
+        }
+    }
+
+    set_path_ids(&mut blocks, "");
 
     (blocks, errors)
 }
 
-/// Check the required constraint is respected on given blocks (only at this level)
+/// The key of the synthetic block containing the lines before the first block, with
+/// ParseOptions::preserve_all. It is not in any spec, so it is never given to FromDYBlock.
+pub static PRESERVED_LINES_KEY: KeySpec = KeySpec {
+    id: "",
+    desc: "The lines before the first block, like comments",
+    subkeys: &[],
+    vt: ValueType::Multiline,
+    max_occurs: Some(1),
+    ..KeySpec::DEFAULT
+};
+
+/// Give each block the lines from its start to the start of the next block in the document order,
+/// the lines before the first block go into a synthetic block with the PRESERVED_LINES_KEY.
+/// Blocks must be in the document order, so duplicates must not be merged.
+fn attach_raw_lines<'a>(blocks: &mut Vec<Block<'a>>, content: &'a str) {
+    // The same lines as the tokenized ones, but with their line terminator
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let mut starts = Vec::new();
+    collect_starts(blocks, &mut starts);
+    let first_start = starts.first().map_or(lines.len(), |start| *start as usize);
+    if first_start > 0 {
+        let start = Position::new(0, 0);
+        blocks.insert(
+            0,
+            Block {
+                key: &PRESERVED_LINES_KEY,
+                matched_key_text: "",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: lines[..first_start].to_vec(),
+                text: vec![],
+                range: Range::new(start, start),
+                subblocks: vec![],
+            },
+        );
+        starts.insert(0, 0);
+    }
+    let mut index = 0;
+    assign_raw_lines(blocks, &lines, &starts, &mut index);
+}
+
+fn collect_starts(blocks: &[Block], starts: &mut Vec<u32>) {
+    for block in blocks {
+        starts.push(block.range.start.line);
+        collect_starts(&block.subblocks, starts);
+    }
+}
+
+/// The `index` is the position of the next block in the document order, in `starts`
+fn assign_raw_lines<'a>(
+    blocks: &mut [Block<'a>],
+    lines: &[&'a str],
+    starts: &[u32],
+    index: &mut usize,
+) {
+    for block in blocks {
+        *index += 1;
+        if block.raw.is_empty() {
+            let start = (block.range.start.line as usize).min(lines.len());
+            let end = starts
+                .get(*index)
+                .map_or(lines.len(), |next| *next as usize)
+                .clamp(start, lines.len());
+            block.raw = lines[start..end].to_vec();
+        }
+        assign_raw_lines(&mut block.subblocks, lines, starts, index);
+    }
+}
+
+/// Write back the blocks built with ParseOptions::preserve_all, this gives exactly the original
+/// content
+pub fn blocks_to_raw_text(blocks: &[Block]) -> String {
+    let mut text = String::new();
+    for block in blocks {
+        for line in block.raw.iter() {
+            text.push_str(line);
+        }
+        text.push_str(&blocks_to_raw_text(&block.subblocks));
+    }
+    text
+}
+
+/// The number of blank lines immediately before each of the given blocks in the `content` they
+/// were built from, like for a formatter enforcing a single blank line between top-level blocks.
+/// Comment lines are not blank, they stop the counting.
+pub fn blank_lines_before_blocks(blocks: &[Block], content: &str) -> Vec<usize> {
+    let lines: Vec<&str> = content.lines().collect();
+    blocks
+        .iter()
+        .map(|block| {
+            let start = (block.range.start.line as usize).min(lines.len());
+            lines[..start]
+                .iter()
+                .rev()
+                .take_while(|line| line.trim().is_empty())
+                .count()
+        })
+        .collect()
+}
+
+/// Give the transformer to all blocks of the tree
+fn set_path_ids(blocks: &mut [Block], parent_path: &str) {
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    for block in blocks {
+        let occurrence = occurrences.entry(block.key.id).or_default();
+        block.path_id = format!("{parent_path}{}[{occurrence}]", block.key.id);
+        *occurrence += 1;
+        set_path_ids(&mut block.subblocks, &format!("{}/", block.path_id));
+    }
+}
+
+fn set_transformer(blocks: &mut [Block], transformer: TextTransformer) {
+    for block in blocks {
+        block.transformer = Some(transformer);
+        set_transformer(&mut block.subblocks, transformer);
+    }
+}
+
+/// Run all the spec-driven validations on an already built blocks tree: keys position, minimum and
+/// maximum occurrences, single line values, required keys and values, list entries, durations and
+/// patterns.
+/// This is called by build_blocks_tree() but can be run again after programmatic edits of the tree,
+/// without tokenizing again. On a tree built by build_blocks_tree(), the position, maximum
+/// occurrences and single line checks never fail as these errors are already generated (and the blocks dropped) while building.
+pub fn validate_blocks(spec: &ValidDYSpec, blocks: &[Block]) -> Vec<ParseError> {
+    let mut errors = Vec::new();
+    check_structure(spec, blocks, spec.get(), 0, &mut errors);
+    check_required_constraint(blocks, spec.get(), None, false, &mut errors);
+    check_values(blocks, &mut HashMap::new(), &mut errors);
+    errors
+}
+
+/// Parse a duration made of an unsigned integer and a unit, `ms` for milliseconds, `s` for
+/// seconds or `m` for minutes, like `500ms`, `2s` or `1m`. There is no space before the unit.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let unit_start = text.find(|c: char| !c.is_ascii_digit())?;
+    let number: u64 = text[..unit_start].parse().ok()?;
+    match &text[unit_start..] {
+        "ms" => Some(Duration::from_millis(number)),
+        "s" => Some(Duration::from_secs(number)),
+        "m" => number.checked_mul(60).map(Duration::from_secs),
+        _ => None,
+    }
+}
+
+/// Get the id of the key that has the given key id in its subkeys, the key under which it should
+/// have been written. Key ids are unique in a spec, so there is only one possible parent.
+fn parent_key_id<'a>(spec: &'a DYSpec<'a>, key_id: &str) -> &'a str {
+    all_valid_keys(spec)
+        .into_iter()
+        .find(|k| k.subkeys.iter().any(|s| s.id == key_id))
+        .map(|k| k.id)
+        .unwrap_or_default()
+}
+
+/// Check that blocks are at a valid position, respect their maximum occurrences and that single
+/// line keys only have a single line of text, recursively
+fn check_structure(
+    spec: &ValidDYSpec,
+    blocks: &[Block],
+    specs: &DYSpec,
+    level: u8,
+    errors: &mut Vec<ParseError>,
+) {
+    let mut occurrences: HashMap<&str, u32> = HashMap::new();
+    for block in blocks {
+        let key_range = range_on_line_with_length(block.range.start.line, block.key_len());
+        if !specs.iter().any(|s| s.id == block.key.id) {
+            errors.push(ParseError::new(
+                key_range,
+                ParseErrorType::WrongKeyPosition(
+                    block.key.id.to_string(),
+                    parent_key_id(spec.get(), block.key.id).to_string(),
+                ),
+            ));
+            continue;
+        }
+        if is_extra_occurrence(block.key, &mut occurrences)
+            && !(block.key.is_once() && block.key.merge == MergeMode::Merge)
+        {
+            errors.push(ParseError::new(
+                // The whole lines of the block, so a quick fix can delete the duplicate
+                block.range,
+                extra_occurrence_error(block.key, level),
+            ));
+        }
+        if block.key.vt.is_single_line() && block.text.iter().skip(1).any(|l| !l.trim().is_empty())
+        {
+            errors.push(ParseError::new(
+                block.range,
+                ParseErrorType::InvalidMultilineContent(block.key.id.to_string()),
+            ));
+        }
+        check_structure(spec, &block.subblocks, block.key.subkeys, level + 1, errors);
+    }
+}
+
+/// Count a new occurrence of the given key and check if it is more than its maximum occurrences.
+/// The keys with `allow_duplicates` never have extra occurrences.
+fn is_extra_occurrence<'a>(key: &KeySpec<'a>, occurrences: &mut HashMap<&'a str, u32>) -> bool {
+    let count = occurrences.entry(key.id).or_default();
+    *count += 1;
+    !key.allow_duplicates && key.max_occurs.is_some_and(|max| *count > max)
+}
+
+/// The error of an occurrence of the key over its maximum, a key used only once is duplicated
+fn extra_occurrence_error(key: &KeySpec, level: u8) -> ParseErrorType {
+    match key.max_occurs {
+        Some(max) if max != 1 => ParseErrorType::TooManyOccurrences(key.id.to_string(), max),
+        _ => ParseErrorType::DuplicatedKey(key.id.to_string(), level),
+    }
+}
+
+/// Check that blocks with a ValueType::List don't contain empty entries (like `a,,c`), that
+/// blocks with a ValueType::Duration, Integer or Bool contain a valid value and that values match
+/// the pattern and the allowed values of their key, recursively. Empty values are ignored, they are
+/// reported as missing values if they are required. The compiled patterns are cached in `patterns`
+/// to compile them once per validation.
+fn check_values<'a>(
+    blocks: &[Block<'a>],
+    patterns: &mut HashMap<&'a str, Regex>,
+    errors: &mut Vec<ParseError>,
+) {
+    for block in blocks {
+        if let Some(error) = get_value_error(block, patterns) {
+            errors.push(ParseError::new(block.value_range, error));
+        }
+        check_values(&block.subblocks, patterns, errors);
+    }
+}
+
+/// The first invalid constraint on the value of the block, see check_values()
+fn get_value_error<'a>(
+    block: &Block<'a>,
+    patterns: &mut HashMap<&'a str, Regex>,
+) -> Option<ParseErrorType> {
+    let key = block.key;
+    // Most keys have no constraint on their value, their text is not joined for nothing
+    let is_checked = matches!(
+        key.vt,
+        ValueType::List(_) | ValueType::Duration | ValueType::Integer | ValueType::Bool
+    ) || key.pattern.is_some()
+        || key.allowed_values.is_some();
+    if !is_checked {
+        return None;
+    }
+    let text = block.get_joined_text();
+    match key.vt {
+        ValueType::List(delimiter) => (!text.is_empty()
+            && text.split(delimiter).any(|entry| entry.trim().is_empty()))
+        .then(|| ParseErrorType::EmptyListEntry(key.id.to_string())),
+        ValueType::Duration => (!text.is_empty() && parse_duration(&text).is_none())
+            .then(|| ParseErrorType::InvalidDuration(text.clone())),
+        ValueType::Integer => (!text.is_empty() && text.trim().parse::<i32>().is_err())
+            .then(|| ParseErrorType::InvalidInteger(text.clone())),
+        ValueType::Bool => (!text.is_empty() && text.trim().parse::<bool>().is_err())
+            .then(|| ParseErrorType::InvalidBool(text.clone())),
+        _ => None,
+    }
+    .or_else(|| {
+        let pattern = key.pattern.filter(|_| !text.is_empty())?;
+        // The pattern has been validated by ValidDYSpec::new(), it cannot fail to compile
+        let regex = patterns
+            .entry(pattern)
+            .or_insert_with(|| Regex::new(pattern).expect("invalid pattern in a valid spec"));
+        (!regex.is_match(&text))
+            .then(|| ParseErrorType::PatternMismatch(key.id.to_string(), pattern.to_string()))
+    })
+    .or_else(|| {
+        let allowed = key.allowed_values.filter(|_| !text.is_empty())?;
+        let values = match key.vt {
+            ValueType::List(delimiter) => text.split(delimiter).map(str::trim).collect(),
+            _ => vec![text.as_str()],
+        };
+        let invalid = values.into_iter().find(|value| !allowed.contains(value))?;
+        Some(ParseErrorType::InvalidEnumValue(
+            key.id.to_string(),
+            invalid.to_string(),
+            Box::new(allowed.iter().map(|v| v.to_string()).collect()),
+        ))
+    })
+}
+
+/// Check the required constraint (including the conditional one given by `required_if`) and the
+/// minimum occurrences are respected on given blocks (only at this level). Missing keys and values
+/// are not reported when the blocks are inside an `incomplete` block.
 fn check_required_constraint(
-    blocks: &Vec<Block>,
+    blocks: &[Block],
     specs: &DYSpec,
     parent_range: Option<&Range>,
+    incomplete: bool,
     errors: &mut Vec<ParseError>,
 ) {
-    let mut required_subkeys: HashSet<&str> = HashSet::from_iter(
-        specs
-            .iter()
-            .filter_map(|sk| if sk.required { Some(sk.id) } else { None }),
-    );
+    let mut required_subkeys: HashSet<&str> = specs
+        .iter()
+        .filter(|sk| {
+            sk.required
+                || sk.required_if.is_some_and(|(sibling, value)| {
+                    blocks
+                        .iter()
+                        .any(|b| b.key.id == sibling && b.get_joined_text().trim() == value)
+                })
+        })
+        .map(|sk| sk.id)
+        .collect();
+
+    let mut occurrences: HashMap<&str, u32> = HashMap::new();
 
     for block in blocks {
-        if block.key.required {
-            required_subkeys.remove(block.key.id);
-            if block.get_joined_text().is_empty() {
-                errors.push(ParseError {
-                    // Note: the range is pointing just after the key as it's where the value need to come
-                    range: range_on_line_part(
-                        block.range.start.line,
-                        block.key.id.len() as u32,
-                        block.key.id.len() as u32,
-                    ),
-                    error: ParseErrorType::MissingRequiredValue(block.key.id.to_string()),
-                });
-            }
+        *occurrences.entry(block.key.id).or_default() += 1;
+        required_subkeys.remove(block.key.id);
+        let incomplete = incomplete || block.incomplete;
+        if block.key.required && block.text_is_empty() && !block.is_synthetic() && !incomplete {
+            errors.push(ParseError::new(
+                // Note: the range is pointing just after the key as it's where the value need to come
+                range_on_line_part(block.range.start.line, block.key_len(), block.key_len()),
+                ParseErrorType::MissingRequiredValue(block.key.id.to_string()),
+            ));
         }
         // Checking subblocks with the subkeys specs
         check_required_constraint(
             &block.subblocks,
             block.key.subkeys,
             Some(&block.range),
+            incomplete,
             errors,
         );
     }
 
+    let parent_line_index = if let Some(range) = parent_range {
+        range.start.line
+    } else {
+        0
+    };
     // Every key that is required but not found with during the blocks loop, is a missing key
-    for missing_key in required_subkeys {
-        let parent_line_index = if let Some(range) = parent_range {
-            range.start.line
-        } else {
-            0
-        };
-        errors.push(ParseError {
-            range: range_on_line_with_length(parent_line_index, 0),
-            error: ParseErrorType::MissingRequiredKey(missing_key.to_string()),
-        });
+    for missing_key in required_subkeys.into_iter().filter(|_| !incomplete) {
+        errors.push(ParseError::new(
+            range_on_line_with_length(parent_line_index, 0),
+            ParseErrorType::MissingRequiredKey(missing_key.to_string()),
+        ));
+    }
+    // An absent required key is already reported as missing
+    for key in specs.iter().filter(|k| k.min_occurs > 0) {
+        let count = occurrences.get(key.id).copied().unwrap_or_default();
+        if count < key.min_occurs && !(count == 0 && key.required) {
+            errors.push(ParseError::new(
+                range_on_line_with_length(parent_line_index, 0),
+                ParseErrorType::TooFewOccurrences(key.id.to_string(), key.min_occurs),
+            ));
+        }
     }
 }
 
-/// Recursive function to build a subtree of blocks
+/// The maximum nesting of blocks, whatever the spec. Recursive keys (a key that is its own subkey)
+/// allow a document to nest blocks indefinitely, this avoids a stack overflow on malicious input.
+pub const MAX_BLOCKS_DEPTH: u8 = 128;
+
+/// Recursive function to build a subtree of blocks. The `parents_specs` are the specs of the
+/// levels above, the first one is the root spec, so the level is its length.
 fn build_blocks_subtree_recursive<'a>(
     lines: &mut Peekable<std::slice::Iter<'_, Line<'a>>>,
-    specs: &DYSpec,
-    level: u8,
+    specs: &'a DYSpec<'a>,
+    parents_specs: &mut Vec<&'a DYSpec<'a>>,
+    comment_prefix: &str,
+    options: &ParseOptions,
 ) -> (Vec<Block<'a>>, Vec<ParseError>) {
+    let level = parents_specs.len() as u8;
     let mut errors: Vec<ParseError> = Vec::new();
     let mut blocks: Vec<Block> = Vec::new();
-    let mut blocks_starting_line_indexes: Vec<usize> = Vec::new();
 
     while let Some(line) = lines.peek() {
         match line.lt {
@@ -201,6 +993,16 @@ fn build_blocks_subtree_recursive<'a>(
                 if specs.iter().any(|s| s.id == associated_spec.id) {
                     // Build the new block as it is valid
                     let parts = line.tokenize_parts();
+                    let matched_key_text = parts
+                        .iter()
+                        .find_map(|f| {
+                            if let LinePart::Key(k) = f {
+                                Some(*k)
+                            } else {
+                                None
+                            }
+                        })
+                        .unwrap_or(associated_spec.id);
                     let text = parts
                         .iter()
                         .filter_map(|f| {
@@ -211,34 +1013,81 @@ fn build_blocks_subtree_recursive<'a>(
                             }
                         })
                         .collect();
-                    let new_block = Block {
+                    let mut new_block = Block {
                         key: associated_spec,
+                        matched_key_text,
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text,
                         range: Range::new(
                             Position::new(line.index as u32, 0),
-                            Position::new(line.index as u32, line.slice.len() as u32),
+                            Position::new(line.index as u32, line.slice.chars().count() as u32),
                         ),
                         subblocks: vec![],
                     };
+                    let key_len = line.key_len();
+                    new_block.extend_value_range(
+                        &line.slice[key_len..],
+                        line.index,
+                        line.slice[..key_len].chars().count() as u32,
+                    );
                     blocks.push(new_block);
-                    blocks_starting_line_indexes.push(line.index);
 
                     // The line was valid, we can move to the next line
                     lines.next();
-                } else if level == 0 {
-                    errors.push(ParseError {
-                        range: range_on_line_with_length(
-                            line.index as u32,
-                            associated_spec.id.len() as u32,
-                        ),
-                        error: ParseErrorType::WrongKeyPosition(
-                            associated_spec.id.to_string(),
-                            "??".to_string(), // how to get the parent ??
-                        ),
-                    });
-                    lines.next();
                 } else {
-                    break; // break the while, so we return from this function
+                    // The key might be valid at a parent level, where it closes the current blocks
+                    if parents_specs
+                        .iter()
+                        .any(|parent_specs| parent_specs.iter().any(|s| s.id == associated_spec.id))
+                    {
+                        break; // break the while, so we return from this function
+                    }
+                    let root_specs = parents_specs.first().copied().unwrap_or(specs);
+                    let recovered_parent = root_specs
+                        .iter()
+                        .find(|s| s.subkeys.iter().any(|sk| sk.id == associated_spec.id))
+                        .filter(|_| options.recover_orphans);
+                    // Only the root level can wrap an orphan in a synthetic parent
+                    if level > 0 && recovered_parent.is_some() {
+                        break;
+                    }
+                    let key_id = associated_spec.id.to_string();
+                    let parent_id = parent_key_id(root_specs, associated_spec.id).to_string();
+                    errors.push(ParseError::new(
+                        range_on_line_with_length(line.index as u32, line.key_len() as u32),
+                        if recovered_parent.is_some() {
+                            ParseErrorType::RecoveredOrphanKey(key_id, parent_id)
+                        } else {
+                            ParseErrorType::WrongKeyPosition(key_id, parent_id)
+                        },
+                    ));
+                    match recovered_parent {
+                        // The line is not consumed, it will be the first subblock of the synthetic parent
+                        Some(parent) => {
+                            let start = Position::new(line.index as u32, 0);
+                            blocks.push(Block {
+                                key: parent,
+                                matched_key_text: "",
+                                transformer: None,
+                                synthetic: true,
+                                incomplete: false,
+                                path_id: String::new(),
+                                value_range: Range::default(),
+                                raw: vec![],
+                                text: vec![],
+                                range: Range::new(start, start),
+                                subblocks: vec![],
+                            });
+                        }
+                        _ => {
+                            lines.next();
+                        }
+                    }
                 }
             }
             LineType::Comment => {
@@ -246,30 +1095,30 @@ fn build_blocks_subtree_recursive<'a>(
             }
             LineType::Unknown => {
                 if let Some(existing_block) = blocks.last_mut() {
-                    if matches!(existing_block.key.vt, crate::spec::ValueType::SingleLine) {
+                    if existing_block.key.vt.is_single_line() {
                         if !line.slice.trim().is_empty() {
-                            errors.push(ParseError {
-                                range: range_on_line_with_length(
+                            errors.push(ParseError::new(
+                                range_on_line_with_length(
                                     line.index as u32,
-                                    line.slice.len() as u32,
+                                    line.slice.chars().count() as u32,
                                 ),
-                                error: ParseErrorType::InvalidMultilineContent(
+                                ParseErrorType::InvalidMultilineContent(
                                     existing_block.key.id.to_string(),
                                 ),
-                            });
+                            ));
                         }
                     } else {
                         existing_block.push_text(line.slice, line.index);
                     }
                 } else if !line.slice.trim().is_empty() {
                     // Non empty lines without an existing block are ContentOutOfKey
-                    errors.push(ParseError {
-                        range: range_on_line_with_length(
+                    errors.push(ParseError::new(
+                        range_on_line_with_length(
                             line.index as u32,
-                            line.slice.len() as u32,
+                            line.slice.chars().count() as u32,
                         ),
-                        error: ParseErrorType::ContentOutOfKey,
-                    });
+                        ParseErrorType::ContentOutOfKey(comment_prefix.to_string()),
+                    ));
                 }
                 lines.next();
             }
@@ -284,35 +1133,86 @@ fn build_blocks_subtree_recursive<'a>(
             })
         ) {
             // If there is an existing block and it's key spec contains subkeys, we have to go check if they match
-            if let Some(existing_block) = blocks.last_mut() {
-                if !existing_block.key.subkeys.is_empty() {
-                    let (subblocks, suberrors) = build_blocks_subtree_recursive(
-                        lines,
-                        existing_block.key.subkeys,
-                        level + 1,
-                    );
-                    errors.extend(suberrors);
-                    existing_block.subblocks = subblocks;
+            if let Some(existing_block) = blocks.last_mut()
+                && !existing_block.key.subkeys.is_empty()
+            {
+                if level + 1 >= MAX_BLOCKS_DEPTH {
+                    let line = lines.next().expect("the next line has just been peeked");
+                    errors.push(ParseError::new(
+                        range_on_line_with_length(
+                            line.index as u32,
+                            line.slice.chars().count() as u32,
+                        ),
+                        ParseErrorType::MaxDepthExceeded(MAX_BLOCKS_DEPTH),
+                    ));
+                    // Skip the lines that would be nested as deep, until a key that cannot be a
+                    // subblock of this block, it is handled at this level or a parent one
+                    let subkeys = existing_block.key.subkeys;
+                    while lines.peek().is_some_and(|line| match line.lt {
+                        LineType::WithKey(key) => subkeys.iter().any(|s| s.id == key.id),
+                        _ => true,
+                    }) {
+                        lines.next();
+                    }
+                    continue;
                 }
+                parents_specs.push(specs);
+                let (subblocks, suberrors) = build_blocks_subtree_recursive(
+                    lines,
+                    existing_block.key.subkeys,
+                    parents_specs,
+                    comment_prefix,
+                    options,
+                );
+                parents_specs.pop();
+                errors.extend(suberrors);
+                existing_block.subblocks = subblocks;
             }
         }
     }
 
+    // The blocks must stay in the document order to preserve all the lines
+    if !options.preserve_all {
+        move_orphans_into_next_block(&mut blocks);
+    }
+
     // Once the blocks have been entirely extracted at this level (with possible subkeys)
     // there are ready to be removed in case they are duplicates !
-    let mut once_keys_found: HashSet<&str> = HashSet::new(); // TODO: change this to a normal vec with an index access, to improve performance
-    let mut non_duplicated_blocks = Vec::with_capacity(blocks.len());
-    for (idx, block) in blocks.into_iter().enumerate() {
-        // Make sure keys with once=true are not inserted more than once !
-        if block.key.once && !once_keys_found.insert(block.key.id) {
-            errors.push(ParseError {
-                range: range_on_line_with_length(
-                    blocks_starting_line_indexes[idx] as u32,
-                    block.key.id.len() as u32,
-                ),
-                error: ParseErrorType::DuplicatedKey(block.key.id.to_string(), level),
-            });
-        } else {
+    let mut occurrences: HashMap<&str, u32> = HashMap::new();
+    let mut non_duplicated_blocks: Vec<Block> = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        // Duplicates are reported by validate_blocks() and kept, to keep the document order.
+        // A synthetic block is not an occurrence of its key, as the key is not written.
+        if options.preserve_all || block.is_synthetic() {
+            non_duplicated_blocks.push(block);
+            continue;
+        }
+        // Make sure keys are not inserted more than their maximum occurrences !
+        if is_extra_occurrence(block.key, &mut occurrences) {
+            if block.key.is_once() && block.key.merge == MergeMode::Merge {
+                let first_block = non_duplicated_blocks
+                    .iter_mut()
+                    .find(|b| b.key.id == block.key.id)
+                    .expect("a block with this key has already been found");
+                merge_blocks(first_block, block, level + 1, &mut errors);
+                continue;
+            }
+            errors.push(ParseError::new(
+                // The whole lines of the block, so a quick fix can delete the duplicate
+                block.range,
+                extra_occurrence_error(block.key, level),
+            ));
+            // The subblocks are lost with the duplicate, they might have been written on purpose
+            if !block.subblocks.is_empty() {
+                errors.push(ParseError::new(
+                    range_on_line_with_length(block.range.start.line, block.key_len()),
+                    ParseErrorType::DroppedSubblocks(
+                        block.key.id.to_string(),
+                        block.subblocks.len(),
+                    ),
+                ));
+            }
+        } else {
             non_duplicated_blocks.push(block);
         }
     }
@@ -320,21 +1220,95 @@ fn build_blocks_subtree_recursive<'a>(
     (non_duplicated_blocks, errors)
 }
 
+/// Move the subblocks of each synthetic block into the next block of the same key, if any, so
+/// orphans written before their parent (like a `check` before the `exo`) end up in it. The
+/// synthetic block is then removed.
+fn move_orphans_into_next_block(blocks: &mut Vec<Block>) {
+    let mut index = 0;
+    while index < blocks.len() {
+        if blocks[index].is_synthetic()
+            && let Some(offset) = blocks[index + 1..]
+                .iter()
+                .position(|b| !b.is_synthetic() && b.key.id == blocks[index].key.id)
+        {
+            let synthetic = blocks.remove(index);
+            blocks[index + offset]
+                .subblocks
+                .splice(0..0, synthetic.subblocks);
+        } else {
+            index += 1;
+        }
+    }
+}
+
+/// Merge the `duplicate` block into the `first` block, see MergeMode::Merge.
+/// A single line value is only taken from the duplicate if the first block has none, two values
+/// are reported as ConflictingMergedValue and the first one is kept.
+/// The subblocks are merged too, so subblocks used only once that are present in both blocks are
+/// either merged too or reported as DuplicatedKey at the given `subblocks_level`.
+fn merge_blocks<'a>(
+    first: &mut Block<'a>,
+    duplicate: Block<'a>,
+    subblocks_level: u8,
+    errors: &mut Vec<ParseError>,
+) {
+    if !first.key.vt.is_single_line() {
+        first.text.extend(duplicate.text);
+    } else if first.text_is_empty() {
+        first.text = duplicate.text;
+    } else if !duplicate.text_is_empty() {
+        let value_start = duplicate.key_len() + 1;
+        let value_length = duplicate.text.first().unwrap_or(&"").chars().count() as u32;
+        errors.push(ParseError::new(
+            range_on_line_part(
+                duplicate.range.start.line,
+                value_start,
+                value_start + value_length,
+            ),
+            ParseErrorType::ConflictingMergedValue(duplicate.key.id.to_string()),
+        ));
+    }
+    first.range.end = duplicate.range.end;
+    for subblock in duplicate.subblocks {
+        let existing = first.subblocks.iter_mut().find(|b| {
+            subblock.key.is_once() && !subblock.key.allow_duplicates && b.key.id == subblock.key.id
+        });
+        match existing {
+            Some(existing) if subblock.key.merge == MergeMode::Merge => {
+                merge_blocks(existing, subblock, subblocks_level + 1, errors)
+            }
+            Some(_) => errors.push(ParseError::new(
+                subblock.range,
+                ParseErrorType::DuplicatedKey(subblock.key.id.to_string(), subblocks_level),
+            )),
+            None => first.subblocks.push(subblock),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
 
     use crate::common::tests::{
         ARGS_SPEC, CHECK_SPEC, EXIT_SPEC, EXO_SPEC, SEE_SPEC, SKILL_SPEC, SUBSKILL_SPEC,
         TESTING_EXOS_SPEC, TESTING_SKILLS_SPEC, TYPE_SPEC,
     };
-    use crate::error::{ParseError, ParseErrorType};
+    use crate::error::{ParseError, ParseErrorType, Severity};
     use crate::{
+        ParseOptions,
         common::tests::{CODE_SPEC, COURSE_SPEC, GOAL_SPEC, TESTING_COURSE_SPEC},
-        lexer::tokenize_into_lines,
-        parser::{Block, build_blocks_tree},
-        spec::ValidDYSpec,
+        lexer::{is_partial_key, tokenize_into_lines},
+        parser::{
+            Block, MAX_BLOCKS_DEPTH, blank_lines_before_blocks, blocks_path_ids, blocks_to_outline,
+            blocks_to_raw_text, build_blocks_tree, build_blocks_tree_with_options, flatten_values,
+            parse_duration, suggest_keys_at, validate_blocks,
+        },
+        spec::{DYSpec, KeySpec, MergeMode, ValidDYSpec, ValueType},
     };
     use crate::{range_on_line_part, range_on_line_with_length, range_on_lines};
+    use lsp_types::{Position, Range};
     use pretty_assertions::assert_eq;
 
     fn get_blocks<'a>(
@@ -359,17 +1333,38 @@ goal Apprendre des bases solides du C++";
             blocks,
             vec![Block {
                 key: COURSE_SPEC,
+                matched_key_text: "course",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
                 text: vec!["Programmation 1",],
                 range: range_on_line_with_length(0, 22),
                 subblocks: vec![
                     Block {
                         key: CODE_SPEC,
+                        matched_key_text: "code",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["PRG1",],
                         range: range_on_line_with_length(1, 9),
                         subblocks: vec![],
                     },
                     Block {
                         key: GOAL_SPEC,
+                        matched_key_text: "goal",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["Apprendre des bases solides du C++",],
                         range: range_on_line_with_length(2, 39),
                         subblocks: vec![],
@@ -395,10 +1390,24 @@ subskill E";
             vec![
                 Block {
                     key: SKILL_SPEC,
+                    matched_key_text: "skill",
+                    transformer: None,
+                    synthetic: false,
+                    incomplete: false,
+                    path_id: String::new(),
+                    value_range: Range::default(),
+                    raw: vec![],
                     text: vec!["A",],
                     range: range_on_line_with_length(0, 7),
                     subblocks: vec![Block {
                         key: SUBSKILL_SPEC,
+                        matched_key_text: "subskill",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["B",],
                         range: range_on_line_with_length(1, 10),
                         subblocks: vec![],
@@ -406,16 +1415,37 @@ subskill E";
                 },
                 Block {
                     key: SKILL_SPEC,
+                    matched_key_text: "skill",
+                    transformer: None,
+                    synthetic: false,
+                    incomplete: false,
+                    path_id: String::new(),
+                    value_range: Range::default(),
+                    raw: vec![],
                     text: vec!["C",],
                     range: range_on_line_with_length(2, 7),
                     subblocks: vec![],
                 },
                 Block {
                     key: SKILL_SPEC,
+                    matched_key_text: "skill",
+                    transformer: None,
+                    synthetic: false,
+                    incomplete: false,
+                    path_id: String::new(),
+                    value_range: Range::default(),
+                    raw: vec![],
                     text: vec!["D",],
                     range: range_on_line_with_length(3, 7),
                     subblocks: vec![Block {
                         key: SUBSKILL_SPEC,
+                        matched_key_text: "subskill",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["E",],
                         range: range_on_line_with_length(4, 10),
                         subblocks: vec![],
@@ -437,14 +1467,14 @@ code hey";
         assert_eq!(
             errors,
             vec![
-                ParseError {
-                    range: range_on_line_with_length(0, 4),
-                    error: ParseErrorType::WrongKeyPosition("goal".to_string(), "??".to_string()) // "course".to_string())
-                },
-                ParseError {
-                    range: range_on_line_with_length(1, 0),
-                    error: ParseErrorType::MissingRequiredKey("goal".to_string())
-                }
+                ParseError::new(
+                    range_on_line_with_length(0, 4),
+                    ParseErrorType::WrongKeyPosition("goal".to_string(), "course".to_string()),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(1, 0),
+                    ParseErrorType::MissingRequiredKey("goal".to_string()),
+                )
             ]
         );
     }
@@ -462,17 +1492,38 @@ course oups";
             blocks,
             vec![Block {
                 key: COURSE_SPEC,
+                matched_key_text: "course",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
                 text: vec!["Programmation 1",],
                 range: range_on_line_with_length(0, 22),
                 subblocks: vec![
                     Block {
                         key: CODE_SPEC,
+                        matched_key_text: "code",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["prg1",],
                         range: range_on_line_with_length(1, 9),
                         subblocks: vec![],
                     },
                     Block {
                         key: GOAL_SPEC,
+                        matched_key_text: "goal",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["hey there",],
                         range: range_on_line_with_length(2, 14),
                         subblocks: vec![],
@@ -482,10 +1533,10 @@ course oups";
         );
         assert_eq!(
             errors,
-            vec![ParseError {
-                range: range_on_line_with_length(3, 6),
-                error: ParseErrorType::DuplicatedKey("course".to_string(), 0)
-            }]
+            vec![ParseError::new(
+                range_on_line_with_length(3, 11),
+                ParseErrorType::DuplicatedKey("course".to_string(), 0),
+            )]
         );
     }
 
@@ -521,10 +1572,24 @@ C desc 2
             vec![
                 Block {
                     key: SKILL_SPEC,
+                    matched_key_text: "skill",
+                    transformer: None,
+                    synthetic: false,
+                    incomplete: false,
+                    path_id: String::new(),
+                    value_range: Range::default(),
+                    raw: vec![],
                     text: vec!["A", "A desc", "A desc 2"],
                     range: range_on_lines(2, 6, 8),
                     subblocks: vec![Block {
                         key: SUBSKILL_SPEC,
+                        matched_key_text: "subskill",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["AA",],
                         range: range_on_line_with_length(7, 11),
                         subblocks: vec![],
@@ -532,10 +1597,24 @@ C desc 2
                 },
                 Block {
                     key: SKILL_SPEC,
+                    matched_key_text: "skill",
+                    transformer: None,
+                    synthetic: false,
+                    incomplete: false,
+                    path_id: String::new(),
+                    value_range: Range::default(),
+                    raw: vec![],
                     text: vec!["B", "B desc"],
                     range: range_on_lines(8, 10, 6),
                     subblocks: vec![Block {
                         key: SUBSKILL_SPEC,
+                        matched_key_text: "subskill",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["C", "C desc", "C desc 2",],
                         range: range_on_lines(12, 17, 8),
                         subblocks: vec![],
@@ -557,26 +1636,47 @@ goal Apprendre des bases solides du C++";
         let (blocks, errors) = get_blocks(&binding, text);
         assert_eq!(
             errors,
-            vec![ParseError {
-                range: range_on_line_with_length(1, 27),
-                error: ParseErrorType::InvalidMultilineContent("course".to_string())
-            }]
+            vec![ParseError::new(
+                range_on_line_with_length(1, 27),
+                ParseErrorType::InvalidMultilineContent("course".to_string()),
+            )]
         );
         assert_eq!(
             blocks,
             vec![Block {
                 key: COURSE_SPEC,
+                matched_key_text: "course",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
                 text: vec!["Programmation 1"],
                 range: range_on_line_with_length(0, 22),
                 subblocks: vec![
                     Block {
                         key: CODE_SPEC,
+                        matched_key_text: "code",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["PRG1"],
                         range: range_on_line_with_length(2, 9),
                         subblocks: vec![],
                     },
                     Block {
                         key: GOAL_SPEC,
+                        matched_key_text: "goal",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["Apprendre des bases solides du C++"],
                         range: range_on_line_with_length(3, 39),
                         subblocks: vec![],
@@ -599,26 +1699,47 @@ goal Apprendre des bases solides du C++";
         let (blocks, errors) = get_blocks(&binding, text);
         assert_eq!(
             errors,
-            vec![ParseError {
-                range: range_on_line_with_length(1, 19),
-                error: ParseErrorType::ContentOutOfKey
-            }]
+            vec![ParseError::new(
+                range_on_line_with_length(1, 19),
+                ParseErrorType::ContentOutOfKey("//".to_string()),
+            )]
         );
         assert_eq!(
             blocks,
             vec![Block {
                 key: COURSE_SPEC,
+                matched_key_text: "course",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
                 text: vec!["Programmation 1"],
                 range: range_on_line_with_length(3, 22),
                 subblocks: vec![
                     Block {
                         key: CODE_SPEC,
+                        matched_key_text: "code",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["PRG1"],
                         range: range_on_line_with_length(4, 9),
                         subblocks: vec![],
                     },
                     Block {
                         key: GOAL_SPEC,
+                        matched_key_text: "goal",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["Apprendre des bases solides du C++"],
                         range: range_on_line_with_length(5, 39),
                         subblocks: vec![],
@@ -628,6 +1749,104 @@ goal Apprendre des bases solides du C++";
         );
     }
 
+    #[test]
+    #[ntest::timeout(50)]
+    fn test_raw_values_are_not_trimmed() {
+        static RAW_TYPE_SPEC: KeySpec = KeySpec {
+            id: "type",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::Raw,
+            ..KeySpec::DEFAULT
+        };
+        let spec: &DYSpec = &[&RAW_TYPE_SPEC];
+        let text = "type  two spaces before and after  
+type
+  indented
+    more  
+
+type 
+type normal
+";
+        let binding = ValidDYSpec::new(spec).unwrap();
+        let (blocks, errors) = get_blocks(&binding, text);
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            blocks
+                .iter()
+                .map(|b| b.get_joined_text())
+                .collect::<Vec<_>>(),
+            vec![
+                " two spaces before and after  ",
+                "  indented\n    more  ",
+                "",
+                "normal"
+            ]
+        );
+        assert_eq!(
+            blocks[0].require_single_line(),
+            Ok(" two spaces before and after  ".to_string())
+        );
+    }
+
+    #[test]
+    #[ntest::timeout(50)]
+    fn test_can_count_blank_lines_before_blocks() {
+        let text = "
+skill A
+subskill A1
+
+subskill A2
+skill B
+
+// separator
+
+
+skill C
+skill D
+";
+        let binding = ValidDYSpec::new(TESTING_SKILLS_SPEC).unwrap();
+        let (blocks, errors) = get_blocks(&binding, text);
+        assert_eq!(errors, vec![]);
+        assert_eq!(blank_lines_before_blocks(&blocks, text), vec![1, 0, 2, 0]);
+        assert_eq!(
+            blank_lines_before_blocks(&blocks[0].subblocks, text),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    #[ntest::timeout(50)]
+    fn test_dropped_subblocks_of_duplicated_key_are_reported() {
+        let text = "exo first
+check one
+see ok
+
+exo second
+check two
+see ok
+check three
+see ok
+";
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let (blocks, errors) = get_blocks(&binding, text);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(
+            errors,
+            vec![
+                ParseError::new(
+                    range_on_line_with_length(4, 10),
+                    ParseErrorType::DuplicatedKey("exo".to_string(), 0),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(4, 3),
+                    ParseErrorType::DroppedSubblocks("exo".to_string(), 2),
+                ),
+            ]
+        );
+        assert_eq!(errors[1].error.severity(), Severity::Warning);
+    }
+
     #[test]
     #[ntest::timeout(50)]
     fn test_can_extract_complex_exos_blocks_with_errors_ignorance() {
@@ -659,18 +1878,22 @@ check error with duplicate
         assert_eq!(
             errors,
             vec![
-                ParseError {
-                    range: range_on_line_with_length(14, 4),
-                    error: ParseErrorType::DuplicatedKey("args".to_string(), 2),
-                },
-                ParseError {
-                    range: range_on_line_with_length(17, 4),
-                    error: ParseErrorType::DuplicatedKey("exit".to_string(), 2),
-                },
-                ParseError {
-                    range: range_on_line_with_length(20, 3),
-                    error: ParseErrorType::DuplicatedKey("exo".to_string(), 0),
-                },
+                ParseError::new(
+                    range_on_line_with_length(14, 30),
+                    ParseErrorType::DuplicatedKey("args".to_string(), 2),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(17, 18),
+                    ParseErrorType::DuplicatedKey("exit".to_string(), 2),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(20, 28),
+                    ParseErrorType::DuplicatedKey("exo".to_string(), 0),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(20, 3),
+                    ParseErrorType::DroppedSubblocks("exo".to_string(), 1),
+                ),
             ]
         );
         assert_eq!(
@@ -678,40 +1901,89 @@ check error with duplicate
             vec![
                 Block {
                     key: EXO_SPEC,
+                    matched_key_text: "exo",
+                    transformer: None,
+                    synthetic: false,
+                    incomplete: false,
+                    path_id: String::new(),
+                    value_range: Range::default(),
+                    raw: vec![],
                     text: vec!["hey", "a great instruction", "on several lines", ""],
                     range: range_on_lines(1, 4, 0),
                     subblocks: vec![
                         Block {
                             key: CHECK_SPEC,
+                            matched_key_text: "check",
+                            transformer: None,
+                            synthetic: false,
+                            incomplete: false,
+                            path_id: String::new(),
+                            value_range: Range::default(),
+                            raw: vec![],
                             text: vec!["validate it",],
                             range: range_on_line_with_length(5, 17),
                             subblocks: vec![
                                 Block {
                                     key: ARGS_SPEC,
+                                    matched_key_text: "args",
+                                    transformer: None,
+                                    synthetic: false,
+                                    incomplete: false,
+                                    path_id: String::new(),
+                                    value_range: Range::default(),
+                                    raw: vec![],
                                     text: vec!["John",],
                                     range: range_on_line_with_length(6, 9),
                                     subblocks: vec![],
                                 },
                                 Block {
                                     key: SEE_SPEC,
+                                    matched_key_text: "see",
+                                    transformer: None,
+                                    synthetic: false,
+                                    incomplete: false,
+                                    path_id: String::new(),
+                                    value_range: Range::default(),
+                                    raw: vec![],
                                     text: vec!["Hello John",],
                                     range: range_on_line_with_length(7, 14),
                                     subblocks: vec![],
                                 },
                                 Block {
                                     key: TYPE_SPEC,
+                                    matched_key_text: "type",
+                                    transformer: None,
+                                    synthetic: false,
+                                    incomplete: false,
+                                    path_id: String::new(),
+                                    value_range: Range::default(),
+                                    raw: vec![],
                                     text: vec!["Doe",],
                                     range: range_on_line_with_length(8, 8),
                                     subblocks: vec![],
                                 },
                                 Block {
                                     key: SEE_SPEC,
+                                    matched_key_text: "see",
+                                    transformer: None,
+                                    synthetic: false,
+                                    incomplete: false,
+                                    path_id: String::new(),
+                                    value_range: Range::default(),
+                                    raw: vec![],
                                     text: vec!["Hello John Doe",],
                                     range: range_on_line_with_length(9, 18),
                                     subblocks: vec![],
                                 },
                                 Block {
                                     key: EXIT_SPEC,
+                                    matched_key_text: "exit",
+                                    transformer: None,
+                                    synthetic: false,
+                                    incomplete: false,
+                                    path_id: String::new(),
+                                    value_range: Range::default(),
+                                    raw: vec![],
                                     text: vec!["0",],
                                     range: range_on_line_with_length(10, 6),
                                     subblocks: vec![],
@@ -720,23 +1992,51 @@ check error with duplicate
                         },
                         Block {
                             key: CHECK_SPEC,
+                            matched_key_text: "check",
+                            transformer: None,
+                            synthetic: false,
+                            incomplete: false,
+                            path_id: String::new(),
+                            value_range: Range::default(),
+                            raw: vec![],
                             text: vec!["error",],
                             range: range_on_line_with_length(12, 11),
                             subblocks: vec![
                                 Block {
                                     key: ARGS_SPEC,
+                                    matched_key_text: "args",
+                                    transformer: None,
+                                    synthetic: false,
+                                    incomplete: false,
+                                    path_id: String::new(),
+                                    value_range: Range::default(),
+                                    raw: vec![],
                                     text: vec!["john doe",],
                                     range: range_on_line_with_length(13, 13),
                                     subblocks: vec![],
                                 },
                                 Block {
                                     key: SEE_SPEC,
+                                    matched_key_text: "see",
+                                    transformer: None,
+                                    synthetic: false,
+                                    incomplete: false,
+                                    path_id: String::new(),
+                                    value_range: Range::default(),
+                                    raw: vec![],
                                     text: vec!["too many arguments",],
                                     range: range_on_line_with_length(15, 22),
                                     subblocks: vec![],
                                 },
                                 Block {
                                     key: EXIT_SPEC,
+                                    matched_key_text: "exit",
+                                    transformer: None,
+                                    synthetic: false,
+                                    incomplete: false,
+                                    path_id: String::new(),
+                                    value_range: Range::default(),
+                                    raw: vec![],
                                     text: vec!["1",],
                                     range: range_on_line_with_length(16, 6),
                                     subblocks: vec![],
@@ -770,50 +2070,85 @@ check 2
         assert_eq!(
             errors,
             vec![
-                ParseError {
-                    range: range_on_line_with_length(0, 11),
-                    error: ParseErrorType::ContentOutOfKey
-                },
-                ParseError {
-                    range: range_on_line_with_length(4, 3),
-                    error: ParseErrorType::WrongKeyPosition("see".to_string(), "??".to_string()),
-                },
-                ParseError {
-                    range: range_on_line_with_length(8, 4),
-                    error: ParseErrorType::DuplicatedKey("args".to_string(), 2),
-                },
-                ParseError {
-                    range: range_on_line_with_length(10, 0),
-                    error: ParseErrorType::MissingRequiredKey("see".to_string()),
-                },
+                ParseError::new(
+                    range_on_line_with_length(0, 11),
+                    ParseErrorType::ContentOutOfKey("//".to_string()),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(4, 3),
+                    ParseErrorType::WrongKeyPosition("see".to_string(), "check".to_string()),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(8, 17),
+                    ParseErrorType::DuplicatedKey("args".to_string(), 2),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(10, 0),
+                    ParseErrorType::MissingRequiredKey("see".to_string()),
+                ),
             ]
         );
         assert_eq!(
             blocks,
             vec![Block {
                 key: EXO_SPEC,
+                matched_key_text: "exo",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
                 text: vec!["hey there", "some content",],
                 range: range_on_lines(1, 2, 12),
                 subblocks: vec![
                     Block {
                         key: CHECK_SPEC,
+                        matched_key_text: "check",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["yes",],
                         range: range_on_line_with_length(5, 9),
                         subblocks: vec![
                             Block {
                                 key: ARGS_SPEC,
+                                matched_key_text: "args",
+                                transformer: None,
+                                synthetic: false,
+                                incomplete: false,
+                                path_id: String::new(),
+                                value_range: Range::default(),
+                                raw: vec![],
                                 text: vec!["1",],
                                 range: range_on_line_with_length(6, 6),
                                 subblocks: vec![],
                             },
                             Block {
                                 key: SEE_SPEC,
+                                matched_key_text: "see",
+                                transformer: None,
+                                synthetic: false,
+                                incomplete: false,
+                                path_id: String::new(),
+                                value_range: Range::default(),
+                                raw: vec![],
                                 text: vec!["good",],
                                 range: range_on_line_with_length(7, 8),
                                 subblocks: vec![],
                             },
                             Block {
                                 key: TYPE_SPEC,
+                                matched_key_text: "type",
+                                transformer: None,
+                                synthetic: false,
+                                incomplete: false,
+                                path_id: String::new(),
+                                value_range: Range::default(),
+                                raw: vec![],
                                 text: vec!["good",],
                                 range: range_on_line_with_length(9, 9),
                                 subblocks: vec![],
@@ -822,6 +2157,13 @@ check 2
                     },
                     Block {
                         key: CHECK_SPEC,
+                        matched_key_text: "check",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
                         text: vec!["2",],
                         range: range_on_line_with_length(10, 7),
                         subblocks: vec![],
@@ -831,6 +2173,164 @@ check 2
         );
     }
 
+    #[test]
+    fn test_valid_key_after_a_deep_misplaced_key_is_still_extracted() {
+        // The code key is valid under a course, not anywhere in the exo, the course is missing
+        const EXOS_AND_COURSE_SPEC: &DYSpec = &[EXO_SPEC, COURSE_SPEC];
+        let text = "exo hey there
+check yes
+see good
+code PRG1
+args 1
+check 2
+see ok
+";
+        let binding = ValidDYSpec::new(EXOS_AND_COURSE_SPEC).unwrap();
+        let (blocks, errors) = get_blocks(&binding, text);
+        assert_eq!(
+            errors,
+            vec![
+                ParseError::new(
+                    range_on_line_with_length(3, 4),
+                    ParseErrorType::WrongKeyPosition("code".to_string(), "course".to_string()),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(0, 0),
+                    ParseErrorType::MissingRequiredKey("course".to_string()),
+                ),
+            ]
+        );
+        assert_eq!(
+            blocks,
+            vec![Block {
+                key: EXO_SPEC,
+                matched_key_text: "exo",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
+                text: vec!["hey there"],
+                range: range_on_line_with_length(0, 13),
+                subblocks: vec![
+                    Block {
+                        key: CHECK_SPEC,
+                        matched_key_text: "check",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
+                        text: vec!["yes",],
+                        range: range_on_line_with_length(1, 9),
+                        subblocks: vec![
+                            Block {
+                                key: SEE_SPEC,
+                                matched_key_text: "see",
+                                transformer: None,
+                                synthetic: false,
+                                incomplete: false,
+                                path_id: String::new(),
+                                value_range: Range::default(),
+                                raw: vec![],
+                                text: vec!["good",],
+                                range: range_on_line_with_length(2, 8),
+                                subblocks: vec![],
+                            },
+                            Block {
+                                key: ARGS_SPEC,
+                                matched_key_text: "args",
+                                transformer: None,
+                                synthetic: false,
+                                incomplete: false,
+                                path_id: String::new(),
+                                value_range: Range::default(),
+                                raw: vec![],
+                                text: vec!["1",],
+                                range: range_on_line_with_length(4, 6),
+                                subblocks: vec![],
+                            },
+                        ],
+                    },
+                    Block {
+                        key: CHECK_SPEC,
+                        matched_key_text: "check",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
+                        text: vec!["2",],
+                        range: range_on_line_with_length(5, 7),
+                        subblocks: vec![Block {
+                            key: SEE_SPEC,
+                            matched_key_text: "see",
+                            transformer: None,
+                            synthetic: false,
+                            incomplete: false,
+                            path_id: String::new(),
+                            value_range: Range::default(),
+                            raw: vec![],
+                            text: vec!["ok",],
+                            range: range_on_line_with_length(6, 6),
+                            subblocks: vec![],
+                        }],
+                    },
+                ],
+            },]
+        );
+    }
+
+    #[test]
+    #[ntest::timeout(50)]
+    fn test_crlf_content_gives_the_same_blocks_as_lf() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let lf = get_blocks(&binding, "course X\ncode Y\ngoal Z\nand more\n");
+        assert_eq!(
+            get_blocks(&binding, "course X\r\ncode Y\r\ngoal Z\r\nand more\r\n"),
+            lf
+        );
+        assert_eq!(
+            get_blocks(&binding, "course X\r\ncode Y\r\ngoal Z\r\nand more\r"),
+            lf
+        );
+        assert_eq!(lf.0[0].subblocks[0].range, range_on_line_with_length(1, 6));
+    }
+
+    #[test]
+    #[ntest::timeout(50)]
+    fn test_escaped_comment_prefix_and_key_are_kept_as_content() {
+        let text = r"exo hey
+\// this is content
+// this is a comment
+a \// in the middle stays
+\/ alone stays
+\exo is not a key
+\exoskeleton stays
+check \// escaped in a value stays
+see ok
+";
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let (blocks, errors) = get_blocks(&binding, text);
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            blocks[0].get_joined_text(),
+            r"hey
+// this is content
+a \// in the middle stays
+\/ alone stays
+exo is not a key
+\exoskeleton stays"
+        );
+        assert_eq!(
+            blocks[0].subblocks[0].get_joined_text(),
+            r"\// escaped in a value stays"
+        );
+    }
+
     #[test]
     #[ntest::timeout(50)]
     fn test_empty_lines_are_present_in_block_text() {
@@ -849,6 +2349,13 @@ some code
             blocks,
             vec![Block {
                 key: EXO_SPEC,
+                matched_key_text: "exo",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
                 text: vec![
                     "hey there",
                     "some instruction",
@@ -865,6 +2372,23 @@ some code
         );
     }
 
+    #[test]
+    fn test_missing_required_key_is_reported_once() {
+        let text = "course PRG1
+// no code here
+goal Learn C++
+";
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let (_, errors) = get_blocks(&binding, text);
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_with_length(0, 0),
+                ParseErrorType::MissingRequiredKey("code".to_string()),
+            )]
+        );
+    }
+
     #[test]
     fn test_missing_keys_and_values_with_required_keys_are_detected() {
         let text = "course
@@ -876,28 +2400,42 @@ goal";
             errors,
             vec![
                 // not this is not sorted... only sorted in parse_with_spec in lib.rs
-                ParseError {
-                    range: range_on_line_part(0, 6, 6),
-                    error: ParseErrorType::MissingRequiredValue("course".to_string())
-                },
-                ParseError {
-                    range: range_on_line_part(2, 4, 4),
-                    error: ParseErrorType::MissingRequiredValue("goal".to_string())
-                },
-                ParseError {
-                    range: range_on_line_with_length(0, 0),
-                    error: ParseErrorType::MissingRequiredKey("code".to_string())
-                },
+                ParseError::new(
+                    range_on_line_part(0, 6, 6),
+                    ParseErrorType::MissingRequiredValue("course".to_string()),
+                ),
+                ParseError::new(
+                    range_on_line_part(2, 4, 4),
+                    ParseErrorType::MissingRequiredValue("goal".to_string()),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(0, 0),
+                    ParseErrorType::MissingRequiredKey("code".to_string()),
+                ),
             ]
         );
         assert_eq!(
             blocks,
             vec![Block {
                 key: COURSE_SPEC,
+                matched_key_text: "course",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
                 text: vec!["",],
                 range: range_on_line_with_length(0, 6),
                 subblocks: vec![Block {
                     key: GOAL_SPEC,
+                    matched_key_text: "goal",
+                    transformer: None,
+                    synthetic: false,
+                    incomplete: false,
+                    path_id: String::new(),
+                    value_range: Range::default(),
+                    raw: vec![],
                     text: vec!["",],
                     range: range_on_line_with_length(2, 4),
                     subblocks: vec![],
@@ -913,11 +2451,1166 @@ goal";
         let (blocks, errors) = get_blocks(&binding, text);
         assert_eq!(
             errors,
-            vec![ParseError {
-                range: range_on_line_with_length(0, 0),
-                error: ParseErrorType::MissingRequiredKey("course".to_string())
-            },]
+            vec![ParseError::new(
+                range_on_line_with_length(0, 0),
+                ParseErrorType::MissingRequiredKey("course".to_string()),
+            ),]
         );
         assert_eq!(blocks, vec![]);
     }
+
+    #[test]
+    fn test_can_parse_durations_with_units() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2s"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration(" 1m "), Some(Duration::from_secs(60)));
+        assert_eq!(parse_duration("0s"), Some(Duration::ZERO));
+        for invalid in ["", "500", "ms", "2 s", "1.5s", "-1s", "3h", "2sec"] {
+            assert_eq!(
+                parse_duration(invalid),
+                None,
+                "{invalid:?} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_min_and_max_occurrences_are_checked() {
+        const TAG_SPEC: &KeySpec = &KeySpec {
+            id: "tag",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::SingleLine,
+            min_occurs: 2,
+            max_occurs: Some(3),
+            ..KeySpec::DEFAULT
+        };
+        const ITEM_SPEC: &KeySpec = &KeySpec::new(
+            "item",
+            "test",
+            &[TAG_SPEC],
+            ValueType::SingleLine,
+            false,
+            false,
+        );
+        let binding = ValidDYSpec::new(&[ITEM_SPEC]).unwrap();
+
+        // At the boundaries
+        let (blocks, errors) = get_blocks(&binding, "item a\ntag 1\ntag 2");
+        assert_eq!(errors, vec![]);
+        assert_eq!(blocks[0].subblocks.len(), 2);
+        let (blocks, errors) = get_blocks(&binding, "item a\ntag 1\ntag 2\ntag 3");
+        assert_eq!(errors, vec![]);
+        assert_eq!(blocks[0].subblocks.len(), 3);
+
+        // Over the maximum, the extra block is dropped
+        let (blocks, errors) = get_blocks(&binding, "item a\ntag 1\ntag 2\ntag 3\ntag 4");
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_with_length(4, 5),
+                ParseErrorType::TooManyOccurrences("tag".to_string(), 3),
+            )]
+        );
+        assert_eq!(blocks[0].subblocks.len(), 3);
+
+        // Under the minimum, even when absent as the key is not required
+        for text in ["item a\ntag 1", "item a"] {
+            let (_, errors) = get_blocks(&binding, text);
+            assert_eq!(
+                errors,
+                vec![ParseError::new(
+                    range_on_line_with_length(0, 0),
+                    ParseErrorType::TooFewOccurrences("tag".to_string(), 2),
+                )]
+            );
+        }
+        assert_eq!(
+            suggest_keys_at(
+                &binding,
+                &get_blocks(&binding, "item a\ntag 1\ntag 2\ntag 3\n").0,
+                4,
+                ""
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_invalid_duration_values_are_detected() {
+        const TIMEOUT_SPEC: &KeySpec = &KeySpec {
+            id: "timeout",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::Duration,
+            ..KeySpec::DEFAULT
+        };
+        let binding = ValidDYSpec::new(&[TIMEOUT_SPEC]).unwrap();
+        let (blocks, errors) = get_blocks(
+            &binding,
+            "timeout 500ms
+timeout 2 seconds
+timeout",
+        );
+        assert_eq!(blocks[0].get_duration(), Some(Duration::from_millis(500)));
+        assert_eq!(blocks[1].get_duration(), None);
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_part(1, 8, 17),
+                ParseErrorType::InvalidDuration("2 seconds".to_string()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_invalid_integer_and_bool_values_are_detected() {
+        const EXIT_SPEC: &KeySpec =
+            &KeySpec::new("exit", "test", &[], ValueType::Integer, false, false);
+        const GRADED_SPEC: &KeySpec =
+            &KeySpec::new("graded", "test", &[], ValueType::Bool, false, false);
+        let binding = ValidDYSpec::new(&[EXIT_SPEC, GRADED_SPEC]).unwrap();
+        let (blocks, errors) = get_blocks(
+            &binding,
+            "exit -2
+exit two
+exit 3000000000
+graded true
+graded yes",
+        );
+        assert_eq!(blocks[0].value_as_i32(), Ok(-2));
+        assert!(blocks[1].value_as_i32().is_err());
+        assert_eq!(blocks[3].value_as_bool(), Ok(true));
+        assert!(blocks[4].value_as_bool().is_err());
+        assert_eq!(
+            errors,
+            vec![
+                ParseError::new(
+                    range_on_line_part(1, 5, 8),
+                    ParseErrorType::InvalidInteger("two".to_string()),
+                ),
+                ParseError::new(
+                    range_on_line_part(2, 5, 15),
+                    ParseErrorType::InvalidInteger("3000000000".to_string()),
+                ),
+                ParseError::new(
+                    range_on_line_part(4, 7, 10),
+                    ParseErrorType::InvalidBool("yes".to_string()),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_values_are_split_and_empty_entries_are_detected() {
+        const TAGS_SPEC: &KeySpec = &KeySpec {
+            id: "tags",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::List(','),
+            ..KeySpec::DEFAULT
+        };
+        let binding = ValidDYSpec::new(&[TAGS_SPEC]).unwrap();
+        let (blocks, errors) = get_blocks(&binding, "tags a, b ,c\ntags a,,c");
+        assert_eq!(blocks[0].get_list(','), vec!["a", "b", "c"]);
+        // The empty entry is skipped but reported
+        assert_eq!(blocks[1].get_list(','), vec!["a", "c"]);
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_part(1, 5, 9),
+                ParseErrorType::EmptyListEntry("tags".to_string()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_values_not_matching_the_pattern_are_detected() {
+        const CODE_PATTERN_SPEC: &KeySpec = &KeySpec {
+            pattern: Some("^[A-Z]{3}[0-9]*$"),
+            ..KeySpec::new("code", "test", &[], ValueType::SingleLine, false, false)
+        };
+        let binding = ValidDYSpec::new(&[CODE_PATTERN_SPEC]).unwrap();
+        let (blocks, errors) = get_blocks(
+            &binding,
+            "code PRG2
+code prg2
+code",
+        );
+        assert_eq!(blocks.len(), 3);
+        // The range covers the value only, and empty values are ignored
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_part(1, 5, 9),
+                ParseErrorType::PatternMismatch("code".to_string(), "^[A-Z]{3}[0-9]*$".to_string()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_pattern_mismatch_range_covers_the_value_as_written() {
+        const CODE_PATTERN_SPEC: &KeySpec = &KeySpec {
+            pattern: Some("^[A-Z]{3}[0-9]*$"),
+            ..KeySpec::new("code", "test", &[], ValueType::SingleLine, false, false)
+        };
+        const GOAL_PATTERN_SPEC: &KeySpec = &KeySpec {
+            pattern: Some("^[A-Z]"),
+            ..KeySpec::new("goal", "test", &[], ValueType::Multiline, false, false)
+        };
+        let binding = ValidDYSpec::new(&[CODE_PATTERN_SPEC, GOAL_PATTERN_SPEC]).unwrap();
+        let (_, errors) = get_blocks(
+            &binding,
+            "code   prg2  
+goal
+  learn
+// a comment
+  things
+
+goal learn
+  more",
+        );
+        let mismatch = |key: &str, pattern: &str| {
+            ParseErrorType::PatternMismatch(key.to_string(), pattern.to_string())
+        };
+        assert_eq!(
+            errors,
+            vec![
+                ParseError::new(
+                    range_on_line_part(0, 7, 11),
+                    mismatch("code", "^[A-Z]{3}[0-9]*$"),
+                ),
+                ParseError::new(
+                    Range::new(Position::new(2, 2), Position::new(4, 8)),
+                    mismatch("goal", "^[A-Z]"),
+                ),
+                ParseError::new(
+                    Range::new(Position::new(6, 5), Position::new(7, 6)),
+                    mismatch("goal", "^[A-Z]"),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_values_not_in_allowed_values_are_detected() {
+        const DIFFICULTY_SPEC: &KeySpec = &KeySpec {
+            allowed_values: Some(&["easy", "medium", "hard"]),
+            ..KeySpec::new(
+                "difficulty",
+                "test",
+                &[],
+                ValueType::SingleLine,
+                false,
+                false,
+            )
+        };
+        let binding = ValidDYSpec::new(&[DIFFICULTY_SPEC]).unwrap();
+        let (_, errors) = get_blocks(
+            &binding,
+            "difficulty easy
+difficulty simple
+difficulty",
+        );
+        let error = ParseErrorType::InvalidEnumValue(
+            "difficulty".to_string(),
+            "simple".to_string(),
+            Box::new(vec![
+                "easy".to_string(),
+                "medium".to_string(),
+                "hard".to_string(),
+            ]),
+        );
+        assert_eq!(
+            error.to_string(),
+            "The value 'simple' is not valid for the 'difficulty' key, it must be one of: easy, medium, hard"
+        );
+        assert_eq!(
+            errors,
+            vec![ParseError::new(range_on_line_part(1, 11, 17), error,)]
+        );
+    }
+
+    #[test]
+    fn test_key_can_be_required_depending_on_a_sibling_value() {
+        const TIMEOUT_SPEC: &KeySpec = &KeySpec {
+            required_if: Some(("type", "interactive")),
+            ..KeySpec::new("timeout", "test", &[], ValueType::SingleLine, true, false)
+        };
+        const KIND_SPEC: &KeySpec =
+            &KeySpec::new("type", "test", &[], ValueType::SingleLine, true, false);
+        const CHECK_SPEC: &KeySpec = &KeySpec::new(
+            "check",
+            "test",
+            &[KIND_SPEC, TIMEOUT_SPEC],
+            ValueType::SingleLine,
+            false,
+            false,
+        );
+        let binding = ValidDYSpec::new(&[CHECK_SPEC]).unwrap();
+        let (_, errors) = get_blocks(
+            &binding,
+            "check with timeout
+type interactive
+timeout 2s
+check batch
+type batch
+check without timeout
+type interactive",
+        );
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_with_length(5, 0),
+                ParseErrorType::MissingRequiredKey("timeout".to_string()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_aliases_give_blocks_of_the_canonical_key() {
+        const GOAL_WITH_ALIAS_SPEC: &KeySpec = &KeySpec {
+            aliases: &["objective"],
+            ..KeySpec::new("goal", "test", &[], ValueType::Multiline, false, true)
+        };
+        const COURSE_WITH_ALIAS_SPEC: &KeySpec = &KeySpec::new(
+            "course",
+            "test",
+            &[CODE_SPEC, GOAL_WITH_ALIAS_SPEC],
+            ValueType::SingleLine,
+            true,
+            true,
+        );
+        let binding = ValidDYSpec::new(&[COURSE_WITH_ALIAS_SPEC]).unwrap();
+        let (blocks, errors) = get_blocks(
+            &binding,
+            "course PRG1\ncode PRG1\nobjective Learn C++\ngoal Learn Rust\nobjective",
+        );
+        let goals = &blocks[0].subblocks[1..];
+        assert_eq!(goals.len(), 3);
+        assert!(goals.iter().all(|b| b.key == GOAL_WITH_ALIAS_SPEC));
+        assert_eq!(goals[0].matched_key_text, "objective");
+        assert_eq!(goals[0].get_joined_text(), "Learn C++");
+        assert_eq!(goals[1].matched_key_text, "goal");
+        // The ranges use the length of the alias
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_part(4, 9, 9),
+                ParseErrorType::MissingRequiredValue("goal".to_string()),
+            )]
+        );
+        // An alias being typed is a partial key of the canonical key
+        assert_eq!(is_partial_key(&binding, "obj"), Some(GOAL_WITH_ALIAS_SPEC));
+        assert_eq!(is_partial_key(&binding, "objective"), None);
+        let suggested: Vec<&str> = suggest_keys_at(&binding, &blocks, 5, "obj")
+            .iter()
+            .map(|s| s.key.id)
+            .collect();
+        assert_eq!(suggested, vec!["goal"]);
+    }
+
+    #[test]
+    fn test_blocks_keep_the_key_as_written() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let (blocks, _) = get_blocks(&binding, "course Foo\ncode FOO\ngoal bar");
+        assert_eq!(blocks[0].matched_key_text, "course");
+        assert_eq!(blocks[0].subblocks[0].matched_key_text, "code");
+        assert_eq!(blocks[0].subblocks[0].key, CODE_SPEC);
+    }
+
+    #[test]
+    fn test_duplicated_blocks_can_be_merged() {
+        const MERGED_COURSE_SPEC: &KeySpec = &KeySpec {
+            id: "course",
+            desc: "test",
+            subkeys: &[CODE_SPEC, GOAL_SPEC],
+            vt: ValueType::SingleLine,
+            max_occurs: Some(1),
+            required: true,
+            merge: MergeMode::Merge,
+            ..KeySpec::DEFAULT
+        };
+        let text = "course Programmation 1
+code PRG1
+// something else
+course
+goal Learn C++
+course
+code PRG2";
+        let binding = ValidDYSpec::new(&[MERGED_COURSE_SPEC]).unwrap();
+        let (blocks, errors) = get_blocks(&binding, text);
+        assert_eq!(
+            blocks,
+            vec![Block {
+                key: MERGED_COURSE_SPEC,
+                matched_key_text: "course",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
+                text: vec!["Programmation 1"],
+                range: range_on_lines(0, 5, 6),
+                subblocks: vec![
+                    Block {
+                        key: CODE_SPEC,
+                        matched_key_text: "code",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
+                        text: vec!["PRG1"],
+                        range: range_on_line_with_length(1, 9),
+                        subblocks: vec![],
+                    },
+                    Block {
+                        key: GOAL_SPEC,
+                        matched_key_text: "goal",
+                        transformer: None,
+                        synthetic: false,
+                        incomplete: false,
+                        path_id: String::new(),
+                        value_range: Range::default(),
+                        raw: vec![],
+                        text: vec!["Learn C++"],
+                        range: range_on_line_with_length(4, 14),
+                        subblocks: vec![],
+                    },
+                ],
+            }]
+        );
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_with_length(6, 9),
+                ParseErrorType::DuplicatedKey("code".to_string(), 1),
+            )]
+        );
+
+        // The first single line value is kept, without an InvalidMultilineContent
+        let (blocks, errors) = get_blocks(
+            &binding,
+            "course PRG1\ncourse PRG2\ncode PRG1\ngoal Learn C",
+        );
+        assert_eq!(blocks[0].text, vec!["PRG1"]);
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_part(1, 7, 11),
+                ParseErrorType::ConflictingMergedValue("course".to_string()),
+            )]
+        );
+        // The value can be given by a merged block only
+        let (blocks, errors) = get_blocks(&binding, "course\ncode PRG1\ncourse PRG2\ngoal Learn C");
+        assert_eq!(blocks[0].get_joined_text(), "PRG2");
+        assert_eq!(errors, vec![]);
+    }
+
+    #[test]
+    fn test_duplicates_can_be_allowed() {
+        const FORGIVING_CODE_SPEC: &KeySpec = &KeySpec {
+            id: "code",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::SingleLine,
+            max_occurs: Some(1),
+            allow_duplicates: true,
+            ..KeySpec::DEFAULT
+        };
+        let binding = ValidDYSpec::new(&[FORGIVING_CODE_SPEC]).unwrap();
+        let (blocks, errors) = get_blocks(&binding, "code PRG1\ncode PRG2");
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            blocks,
+            vec![
+                Block {
+                    key: FORGIVING_CODE_SPEC,
+                    matched_key_text: "code",
+                    transformer: None,
+                    synthetic: false,
+                    incomplete: false,
+                    path_id: String::new(),
+                    value_range: Range::default(),
+                    raw: vec![],
+                    text: vec!["PRG1"],
+                    range: range_on_line_with_length(0, 9),
+                    subblocks: vec![],
+                },
+                Block {
+                    key: FORGIVING_CODE_SPEC,
+                    matched_key_text: "code",
+                    transformer: None,
+                    synthetic: false,
+                    incomplete: false,
+                    path_id: String::new(),
+                    value_range: Range::default(),
+                    raw: vec![],
+                    text: vec!["PRG2"],
+                    range: range_on_line_with_length(1, 9),
+                    subblocks: vec![],
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_can_validate_a_hand_built_tree() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let blocks = vec![Block {
+            key: COURSE_SPEC,
+            matched_key_text: "course",
+            transformer: None,
+            synthetic: false,
+            incomplete: false,
+            path_id: String::new(),
+            value_range: Range::default(),
+            raw: vec![],
+            text: vec!["Programmation 1"],
+            range: range_on_line_with_length(2, 22),
+            subblocks: vec![Block {
+                key: GOAL_SPEC,
+                matched_key_text: "goal",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
+                text: vec!["Learn C++"],
+                range: range_on_line_with_length(3, 14),
+                subblocks: vec![],
+            }],
+        }];
+        assert_eq!(
+            validate_blocks(&binding, &blocks),
+            vec![ParseError::new(
+                range_on_line_with_length(2, 0),
+                ParseErrorType::MissingRequiredKey("code".to_string()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_validation_detects_wrong_position_and_duplicates_after_edits() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let (mut blocks, errors) = get_blocks(&binding, "course Foo\ncode FOO\ngoal bar");
+        assert_eq!(errors, vec![]);
+        let code = blocks[0].subblocks[0].clone();
+        blocks[0].subblocks.push(code.clone());
+        blocks.push(code);
+        assert_eq!(
+            validate_blocks(&binding, &blocks),
+            vec![
+                ParseError::new(
+                    range_on_line_with_length(1, 8),
+                    ParseErrorType::DuplicatedKey("code".to_string(), 1),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(1, 4),
+                    ParseErrorType::WrongKeyPosition("code".to_string(), "course".to_string()),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_absent_subkey_gives_default_value() {
+        const DEFAULT_GOAL_SPEC: &KeySpec = &KeySpec {
+            id: "goal",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::Multiline,
+            max_occurs: Some(1),
+            default: Some("Learn things"),
+            ..KeySpec::DEFAULT
+        };
+        const DEFAULT_COURSE_SPEC: &KeySpec = &KeySpec {
+            id: "course",
+            desc: "test",
+            subkeys: &[CODE_SPEC, DEFAULT_GOAL_SPEC],
+            vt: ValueType::SingleLine,
+            required: true,
+            ..KeySpec::DEFAULT
+        };
+        let binding = ValidDYSpec::new(&[DEFAULT_COURSE_SPEC]).unwrap();
+        let (blocks, _) = get_blocks(&binding, "course Foo\ncode FOO\ncourse Bar\ngoal bar");
+        assert_eq!(
+            blocks[0].get_subvalue_or_default(DEFAULT_GOAL_SPEC),
+            Some("Learn things".to_string())
+        );
+        assert_eq!(
+            blocks[0].get_subvalue_or_default(CODE_SPEC),
+            Some("FOO".to_string())
+        );
+        assert_eq!(
+            blocks[1].get_subvalue_or_default(DEFAULT_GOAL_SPEC),
+            Some("bar".to_string())
+        );
+        assert_eq!(blocks[1].get_subvalue_or_default(CODE_SPEC), None);
+    }
+
+    #[test]
+    fn test_get_subvalue() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let (blocks, _) = get_blocks(&binding, "course PRG1\ncode  PRG1 \n");
+        assert_eq!(blocks[0].get_subvalue("code"), Some("PRG1".to_string()));
+        assert_eq!(blocks[0].get_subvalue("goal"), None);
+    }
+
+    #[test]
+    fn test_can_walk_and_find_subblocks() {
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let (blocks, errors) = get_blocks(
+            &binding,
+            "exo hey
+check one
+args 1
+see hello
+see world
+check two
+see bye",
+        );
+        assert_eq!(errors, vec![]);
+        let exo = &blocks[0];
+
+        let mut visited = Vec::new();
+        exo.walk(|block, depth| visited.push((block.key.id, block.get_joined_text(), depth)));
+        assert_eq!(
+            visited,
+            vec![
+                ("exo", "hey".to_string(), 0),
+                ("check", "one".to_string(), 1),
+                ("args", "1".to_string(), 2),
+                ("see", "hello".to_string(), 2),
+                ("see", "world".to_string(), 2),
+                ("check", "two".to_string(), 1),
+                ("see", "bye".to_string(), 2),
+            ]
+        );
+
+        let first_check = exo.find_subblock("check").unwrap();
+        assert_eq!(first_check.get_joined_text(), "one");
+        assert_eq!(
+            first_check
+                .find_subblock("see")
+                .map(|b| b.get_joined_text()),
+            Some("hello".to_string())
+        );
+        // Only direct subblocks are searched
+        assert_eq!(exo.find_subblock("see"), None);
+        assert_eq!(
+            first_check
+                .subblocks_with("see")
+                .map(|b| b.get_joined_text())
+                .collect::<Vec<_>>(),
+            vec!["hello".to_string(), "world".to_string()]
+        );
+        assert_eq!(exo.subblocks_with("check").count(), 2);
+        assert_eq!(exo.subblocks_with("type").count(), 0);
+    }
+
+    #[test]
+    fn test_can_flatten_leaf_values_with_their_path() {
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let (blocks, errors) = get_blocks(
+            &binding,
+            "exo hey
+check one
+args 1 2 3
+see hello
+world
+type John
+check two
+see bye",
+        );
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            flatten_values(&blocks),
+            vec![
+                ("exo.check.args".to_string(), "1 2 3".to_string()),
+                ("exo.check.see".to_string(), "hello\nworld".to_string()),
+                ("exo.check.type".to_string(), "John".to_string()),
+                ("exo.check.see".to_string(), "bye".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_value_type_accessors() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let (mut blocks, errors) =
+            get_blocks(&binding, "course PRG1\ncode PRG1\ngoal Learn\n  C++");
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            blocks[0].subblocks[0].require_single_line(),
+            Ok("PRG1".to_string())
+        );
+        assert_eq!(blocks[0].subblocks[1].require_multiline(), "Learn\n  C++");
+
+        // A block edited by hand doesn't respect the value type anymore
+        blocks[0].subblocks[0].text.push("PRG2");
+        assert_eq!(
+            blocks[0].subblocks[0].require_single_line(),
+            Err(Box::new(ParseError::new(
+                range_on_line_with_length(1, 9),
+                ParseErrorType::InvalidMultilineContent("code".to_string()),
+            )))
+        );
+    }
+
+    static RECURSIVE_SKILL_SPEC: KeySpec = KeySpec {
+        id: "skill",
+        desc: "test",
+        subkeys: &[&RECURSIVE_SKILL_SPEC],
+        vt: ValueType::Multiline,
+        ..KeySpec::DEFAULT
+    };
+
+    #[test]
+    #[ntest::timeout(500)]
+    fn test_max_depth_is_an_error_instead_of_a_crash() {
+        let spec: &DYSpec = &[&RECURSIVE_SKILL_SPEC];
+        let binding = ValidDYSpec::new(spec).unwrap();
+        // Each skill is a subblock of the previous one as the key is its own subkey
+        let text = "skill nested\n".repeat(1000);
+        let (blocks, errors) = get_blocks(&binding, &text);
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_with_length(MAX_BLOCKS_DEPTH as u32, 12),
+                ParseErrorType::MaxDepthExceeded(MAX_BLOCKS_DEPTH),
+            )]
+        );
+        let mut depth = 0;
+        let mut current = &blocks;
+        while let Some(block) = current.first() {
+            depth += 1;
+            current = &block.subblocks;
+        }
+        assert_eq!(depth, MAX_BLOCKS_DEPTH);
+        // Blocks of a recursive spec can be compared
+        let (same_blocks, _) = get_blocks(&binding, &text);
+        assert_eq!(blocks, same_blocks);
+
+        // The following keys that cannot be nested are still parsed
+        let spec: &DYSpec = &[&RECURSIVE_SKILL_SPEC, CODE_SPEC];
+        let binding = ValidDYSpec::new(spec).unwrap();
+        let text = format!("{}code PRG1\nskill other\n", "skill nested\n".repeat(200));
+        let (blocks, errors) = get_blocks(&binding, &text);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[1].key, CODE_SPEC);
+        assert_eq!(blocks[1].get_joined_text(), "PRG1");
+        assert_eq!(blocks[2].get_joined_text(), "other");
+    }
+
+    #[test]
+    fn test_preserve_all_can_write_back_a_messy_document() {
+        let text = "// header comment
+some text out of key
+see orphan see
+exo  hey   there
+instruction with spaces   
+
+check first
+args   a b
+invalid content after args
+// a comment in the middle
+see hello
+  indented line
+check first
+exo duplicated
+check second
+type
+";
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let options = ParseOptions {
+            preserve_all: true,
+            ..Default::default()
+        };
+        let lines = tokenize_into_lines(&binding, text);
+        let (blocks, errors) = build_blocks_tree_with_options(&binding, text, lines, &options);
+        assert_eq!(blocks_to_raw_text(&blocks), text);
+        assert_eq!(
+            blocks[0].raw,
+            vec![
+                "// header comment\n",
+                "some text out of key\n",
+                "see orphan see\n"
+            ]
+        );
+        // The block of the preserved lines has no key written but doesn't wrap an orphan
+        assert!(!blocks[0].is_synthetic());
+        // The duplicated exo is kept and still reported, as a warning as nothing is lost
+        assert_eq!(blocks.len(), 3);
+        let duplicated_error = errors
+            .iter()
+            .find(|e| e.error == ParseErrorType::DuplicatedKey("exo".to_string(), 0))
+            .unwrap();
+        assert_eq!(duplicated_error.range, range_on_line_with_length(13, 14));
+        assert!(errors.iter().all(|e| e.severity() == Severity::Warning));
+
+        // The synthetic block is not given to FromDYBlock
+        let result: crate::ParseResult<crate::generic::DyValue> =
+            crate::parse_with_options(&binding, &None, text, &options);
+        assert_eq!(result.items.len(), 2);
+        assert_eq!(
+            result.items[0].value,
+            "hey   there\ninstruction with spaces"
+        );
+    }
+
+    #[test]
+    fn test_preserve_all_keeps_line_terminators_and_escapes() {
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let options = ParseOptions {
+            preserve_all: true,
+            ..Default::default()
+        };
+        for text in [
+            "course A\ncode X",
+            "course A\r\ncode X\r\n\r\ngoal G\r\n",
+            "\\// x\ncourse A\n\\code in the name\ngoal G\n",
+            "",
+        ] {
+            let lines = tokenize_into_lines(&binding, text);
+            let (blocks, _) = build_blocks_tree_with_options(&binding, text, lines, &options);
+            assert_eq!(blocks_to_raw_text(&blocks), text);
+        }
+    }
+
+    #[test]
+    fn test_suggest_keys_by_relevance() {
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let (blocks, _) = get_blocks(&binding, "exo hey\ncheck first\nargs John\ntype Doe\n");
+        let suggested = |line| -> Vec<(&str, bool)> {
+            suggest_keys_at(&binding, &blocks, line, "")
+                .iter()
+                .map(|s| (s.key.id, s.required_and_missing))
+                .collect()
+        };
+        assert_eq!(
+            suggested(4),
+            vec![("see", true), ("type", false), ("exit", false)]
+        );
+        assert_eq!(suggested(1), vec![("check", false)]);
+        // The exo is `once` and already present
+        assert_eq!(suggested(0), vec![]);
+    }
+
+    #[test]
+    fn test_path_ids_are_stable_across_unrelated_edits() {
+        let text = "exo hey
+instruction
+check first
+see hello
+see world
+check second
+type John
+see John";
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let (blocks, _) = get_blocks(&binding, text);
+        let ids: Vec<String> = blocks_path_ids(&blocks)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                "exo[0]",
+                "exo[0]/check[0]",
+                "exo[0]/check[0]/see[0]",
+                "exo[0]/check[0]/see[1]",
+                "exo[0]/check[1]",
+                "exo[0]/check[1]/type[0]",
+                "exo[0]/check[1]/see[0]",
+            ]
+        );
+        assert_eq!(blocks_path_ids(&blocks)[6].1.get_joined_text(), "John");
+        assert_eq!(
+            blocks[0].subblocks[1].subblocks[1].path_id(),
+            "exo[0]/check[1]/see[0]"
+        );
+
+        let edited_text = text.replace("instruction", "a longer\ninstruction");
+        let (edited_blocks, _) = get_blocks(&binding, &edited_text);
+        let edited_ids: Vec<String> = blocks_path_ids(&edited_blocks)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, edited_ids);
+    }
+
+    #[test]
+    fn test_can_collect_records() {
+        const NAME_SPEC: &KeySpec = &KeySpec {
+            id: "name",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::SingleLine,
+            max_occurs: Some(1),
+            required: true,
+            ..KeySpec::DEFAULT
+        };
+        const VALUE_SPEC: &KeySpec = &KeySpec {
+            id: "value",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::SingleLine,
+            max_occurs: Some(1),
+            ..KeySpec::DEFAULT
+        };
+        const ROW_SPEC: &KeySpec = &KeySpec {
+            id: "row",
+            desc: "test",
+            subkeys: &[NAME_SPEC, VALUE_SPEC],
+            vt: ValueType::SingleLine,
+            ..KeySpec::DEFAULT
+        };
+        const TABLE_SPEC: &KeySpec = &KeySpec {
+            id: "table",
+            desc: "test",
+            subkeys: &[ROW_SPEC],
+            vt: ValueType::SingleLine,
+            required: true,
+            ..KeySpec::DEFAULT
+        };
+        let text = "table scores
+row
+name Alice
+value 12
+row
+name Bob
+value 8
+row
+name Eve";
+        let binding = ValidDYSpec::new(&[TABLE_SPEC]).unwrap();
+        let (blocks, errors) = get_blocks(&binding, text);
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            blocks[0].collect_records("row", &["name", "value"]),
+            vec![
+                HashMap::from([("name", "Alice".to_string()), ("value", "12".to_string())]),
+                HashMap::from([("name", "Bob".to_string()), ("value", "8".to_string())]),
+                HashMap::from([("name", "Eve".to_string())]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_last_block_can_be_flagged_as_incomplete() {
+        let text = "skill
+subskill A
+skill
+";
+        let binding = ValidDYSpec::new(TESTING_SKILLS_SPEC).unwrap();
+        let options = ParseOptions {
+            incomplete_last_block: true,
+            ..Default::default()
+        };
+        let lines = tokenize_into_lines(&binding, text);
+        let (blocks, errors) = build_blocks_tree_with_options(&binding, text, lines, &options);
+        assert!(!blocks[0].incomplete);
+        assert!(blocks[1].incomplete);
+        // Only the complete block is reported, also when validating again
+        let expected = vec![ParseError::new(
+            range_on_line_part(0, 5, 5),
+            ParseErrorType::MissingRequiredValue("skill".to_string()),
+        )];
+        assert_eq!(errors, expected);
+        assert_eq!(validate_blocks(&binding, &blocks), expected);
+    }
+
+    #[test]
+    fn test_orphan_key_before_its_parent_is_moved_into_it() {
+        let text = "check orphan
+see a
+exo Real
+instr
+check real
+see b";
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let options = ParseOptions {
+            recover_orphans: true,
+            ..Default::default()
+        };
+        let lines = tokenize_into_lines(&binding, text);
+        let (blocks, errors) = build_blocks_tree_with_options(&binding, text, lines, &options);
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_with_length(0, 5),
+                ParseErrorType::RecoveredOrphanKey("check".to_string(), "exo".to_string()),
+            )]
+        );
+        assert_eq!(blocks.len(), 1);
+        assert!(!blocks[0].is_synthetic());
+        assert_eq!(blocks[0].get_joined_text(), "Real\ninstr");
+        let checks: Vec<String> = blocks[0]
+            .subblocks_with("check")
+            .map(|b| b.get_joined_text())
+            .collect();
+        assert_eq!(checks, vec!["orphan", "real"]);
+    }
+
+    #[test]
+    fn test_orphan_key_can_be_wrapped_in_a_synthetic_parent() {
+        let text = "check test
+args hey
+see hello";
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let options = ParseOptions {
+            recover_orphans: true,
+            ..Default::default()
+        };
+        let lines = tokenize_into_lines(&binding, text);
+        let (blocks, errors) = build_blocks_tree_with_options(&binding, text, lines, &options);
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_with_length(0, 5),
+                ParseErrorType::RecoveredOrphanKey("check".to_string(), "exo".to_string()),
+            )]
+        );
+        assert_eq!(errors[0].error.severity(), Severity::Warning);
+        assert_eq!(
+            blocks,
+            vec![Block {
+                key: EXO_SPEC,
+                matched_key_text: "",
+                transformer: None,
+                synthetic: true,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: Range::default(),
+                raw: vec![],
+                text: vec![],
+                range: range_on_line_with_length(0, 0),
+                subblocks: vec![Block {
+                    key: CHECK_SPEC,
+                    matched_key_text: "check",
+                    transformer: None,
+                    synthetic: false,
+                    incomplete: false,
+                    path_id: String::new(),
+                    value_range: Range::default(),
+                    raw: vec![],
+                    text: vec!["test"],
+                    range: range_on_line_with_length(0, 10),
+                    subblocks: vec![
+                        Block {
+                            key: ARGS_SPEC,
+                            matched_key_text: "args",
+                            transformer: None,
+                            synthetic: false,
+                            incomplete: false,
+                            path_id: String::new(),
+                            value_range: Range::default(),
+                            raw: vec![],
+                            text: vec!["hey"],
+                            range: range_on_line_with_length(1, 8),
+                            subblocks: vec![],
+                        },
+                        Block {
+                            key: SEE_SPEC,
+                            matched_key_text: "see",
+                            transformer: None,
+                            synthetic: false,
+                            incomplete: false,
+                            path_id: String::new(),
+                            value_range: Range::default(),
+                            raw: vec![],
+                            text: vec!["hello"],
+                            range: range_on_line_with_length(2, 9),
+                            subblocks: vec![],
+                        },
+                    ],
+                }],
+            }]
+        );
+        assert!(blocks[0].is_synthetic());
+        assert!(!blocks[0].subblocks[0].is_synthetic());
+
+        // Without the option, the orphan is dropped
+        let (blocks, _) = get_blocks(&binding, text);
+        assert_eq!(blocks, vec![]);
+    }
+
+    #[test]
+    fn test_unicode_whitespace_only_lines_are_empty() {
+        // Note: str::trim() uses the Unicode White_Space property, so non-breaking spaces (U+00A0)
+        // and other Unicode spaces are trimmed like ASCII spaces and tabs
+        let text = "\u{a0}
+course PRG1
+\u{a0}\t\u{2003}
+code PRG1
+goal Learn C++";
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let (blocks, errors) = get_blocks(&binding, text);
+        assert_eq!(errors, vec![]);
+        assert_eq!(blocks[0].subblocks.len(), 2);
+    }
+
+    #[test]
+    fn test_text_is_empty_agrees_with_joined_text() {
+        let binding = ValidDYSpec::new(TESTING_SKILLS_SPEC).unwrap();
+        let (blocks, _) = get_blocks(&binding, "skill\nskill   \n \t\n\nskill A\n\nskill\n  \nB");
+        assert_eq!(blocks.len(), 4);
+        for block in blocks {
+            assert_eq!(block.text_is_empty(), block.get_joined_text().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_blocks_tree_can_be_rendered_as_outline() {
+        let text = "course Programmation 1
+code PRG1
+goal Apprendre des bases solides du C++
+et du Qt";
+        let binding = ValidDYSpec::new(TESTING_COURSE_SPEC).unwrap();
+        let (blocks, _) = get_blocks(&binding, text);
+        assert_eq!(
+            blocks_to_outline(&blocks),
+            "course Programmation 1 (0:0-0:22)
+  code PRG1 (1:0-1:9)
+  goal Apprendre des bases solides du C++ (2:0-3:8)
+"
+        );
+    }
+
+    #[test]
+    fn test_can_get_the_source_of_a_nested_block() {
+        let text = "exo hey
+an instruction
+
+check première vérification
+args John
+// a comment
+see Hello John
+à bientôt
+exit 0
+
+check second
+see hello
+";
+        let binding = ValidDYSpec::new(TESTING_EXOS_SPEC).unwrap();
+        let (blocks, _) = get_blocks(&binding, text);
+        assert_eq!(
+            blocks[0].subblocks[0].source(text),
+            "check première vérification
+args John
+// a comment
+see Hello John
+à bientôt
+exit 0"
+        );
+        assert_eq!(
+            blocks[0].subblocks[1].source(text),
+            "check second\nsee hello"
+        );
+    }
 }
@@ -0,0 +1,90 @@
+/// Integration with miette, to display a ParseError with fancy diagnostics
+use std::fmt::Display;
+
+use miette::{Diagnostic, LabeledSpan, NamedSource, SourceCode, SourceSpan};
+
+use crate::{
+    error::{ParseError, Severity},
+    position_to_byte_offset,
+};
+
+/// A ParseError with the source it comes from, implementing miette::Diagnostic
+#[derive(Debug)]
+pub struct ParseErrorReport {
+    pub error: ParseError,
+    source: NamedSource<String>,
+    span: SourceSpan,
+}
+
+impl ParseError {
+    /// Build a report for miette, the content must be the one that has been parsed
+    pub fn to_report(&self, some_file: &Option<String>, content: &str) -> ParseErrorReport {
+        let start = position_to_byte_offset(content, self.range.start);
+        let end = position_to_byte_offset(content, self.range.end);
+        ParseErrorReport {
+            error: self.clone(),
+            source: NamedSource::new(some_file.clone().unwrap_or_default(), content.to_string()),
+            span: SourceSpan::new(start.into(), end.saturating_sub(start)),
+        }
+    }
+}
+
+impl Display for ParseErrorReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error.error)
+    }
+}
+
+impl std::error::Error for ParseErrorReport {}
+
+impl Diagnostic for ParseErrorReport {
+    /// The code is the name of the error variant, like `dy::MissingRequiredKey`
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let variant = format!("{:?}", self.error.error);
+        let name = variant.split('(').next().unwrap_or_default().to_string();
+        Some(Box::new(format!("dy::{name}")))
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(match self.error.severity() {
+            Severity::Error => miette::Severity::Error,
+            Severity::Warning => miette::Severity::Warning,
+        })
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.source)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        Some(Box::new(std::iter::once(LabeledSpan::new_with_span(
+            Some(self.error.error.to_string()),
+            self.span,
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use miette::{GraphicalReportHandler, GraphicalTheme};
+
+    use crate::error::{ParseError, ParseErrorType};
+    use crate::range_on_line_part;
+
+    #[test]
+    fn test_can_render_a_miette_report() {
+        let content = "course PRG1\ncode PRG1\ncode PRG2";
+        let error = ParseError::new(
+            range_on_line_part(2, 0, 4),
+            ParseErrorType::DuplicatedKey("code".to_string(), 1),
+        );
+        let report = error.to_report(&Some("course.dy".to_string()), content);
+        let mut output = String::new();
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut output, &report)
+            .unwrap();
+        assert!(output.contains("dy::DuplicatedKey"));
+        assert!(output.contains("course.dy:3:1"));
+        assert!(output.contains("The 'code' key can only be used once at this level"));
+    }
+}
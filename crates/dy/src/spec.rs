@@ -1,10 +1,19 @@
 /// Core types to define a DY specification, that is the description of the structure of a file to parse
-use std::{collections::HashSet, fmt::Debug};
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+};
 
-use serde::Serialize;
+use bumpalo::Bump;
+use regex::Regex;
+use self_cell::self_cell;
+use serde::{Deserialize, Serialize};
+
+use crate::lexer::COMMENT_PREFIX;
 
 /// The specification of a key
-#[derive(Serialize, Hash, Eq, PartialEq)]
+#[derive(Serialize)]
 pub struct KeySpec<'a> {
     /// The id of the key, its string representation, like "exo", "course", "code", ...
     pub id: &'a str,
@@ -15,19 +24,102 @@ pub struct KeySpec<'a> {
     pub subkeys: &'a DYSpec<'a>,
     /// The type of this key, impacting the way
     pub vt: ValueType,
-    /// Whether this key is only permitted once for its parent object
+    /// The minimum number of occurrences of this key under its parent object, a TooFewOccurrences
+    /// error is generated below it. When the key is absent and `required`, only the
+    /// MissingRequiredKey error is generated.
+    pub min_occurs: u32,
+    /// The maximum number of occurrences of this key under its parent object, None for unlimited.
+    /// `Some(1)` means the key is only permitted once, see KeySpec::is_once().
     ///
     /// For example, in PLX the `course` key is only acceptable once in a `course.dy` file as we only want
     /// to define a single course. For a skill, the `dir` key is only meaningful when given
     /// once. For a check, the `type` key is totally okay since we want to type different things
     /// as the sequence of the check.
-    pub once: bool,
+    pub max_occurs: Option<u32>,
     /// If required is true, it makes sure there is at least one instance of this key everytime
     /// there is place for this key to exist, and it also make sure the values is not empty.
     /// It can generate MissingRequiredKey and MissingRequiredValue errors
     /// Note: if required = false, the MissingRequiredValue error not be generated ! Each spec has
     /// the responsability to take care of that.
     pub required: bool,
+    /// What to do when a key used only once (see is_once()) is found several times at the same level
+    pub merge: MergeMode,
+    /// Keep all occurrences of a key without generating DuplicatedKey or TooManyOccurrences errors,
+    /// this is useful for forgiving editors. The `merge` mode is ignored when true.
+    pub allow_duplicates: bool,
+    /// The value to consider when this key is absent, only meaningful for optional keys. It is
+    /// returned by Block::get_subvalue_or_default() when there is no subblock for this key.
+    pub default: Option<&'a str>,
+    /// Stop matching keys after this key, all the following lines until the end of the file are
+    /// part of its value, even when they start with a key or look like a comment. This is useful
+    /// for a `template` key holding arbitrary DY-like text.
+    pub raw_body: bool,
+    /// A regular expression that the whole value must match, like `^[A-Z]{3}[0-9]*$` for a course
+    /// code. Non empty values not matching it generate a PatternMismatch error, the pattern is
+    /// validated by ValidDYSpec::new()
+    pub pattern: Option<&'a str>,
+    /// The only values accepted for this key, like `easy`, `medium` and `hard` for a difficulty.
+    /// Other values generate an InvalidEnumValue error, each entry is checked for a List key.
+    /// Only single line keys can have allowed values.
+    pub allowed_values: Option<&'a [&'a str]>,
+    /// Other ids accepted for this key, like `objective` for a `goal` key written differently by
+    /// some authors. The blocks always point to this KeySpec, see Block::matched_key_text for the
+    /// key as written. Aliases must be unique in the spec like ids.
+    pub aliases: &'a [&'a str],
+    /// Make this key required only when a sibling key has a given value, like `Some(("type",
+    /// "interactive"))` for a `timeout` only required for interactive checks. A MissingRequiredKey
+    /// error is generated when the condition holds and the key is absent.
+    pub required_if: Option<(&'a str, &'a str)>,
+}
+
+/// The subkeys are only compared by id, a derived comparison would never end on a recursive spec
+impl<'a> PartialEq for KeySpec<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.desc == other.desc
+            && self
+                .subkeys
+                .iter()
+                .map(|k| k.id)
+                .eq(other.subkeys.iter().map(|k| k.id))
+            && self.vt == other.vt
+            && self.min_occurs == other.min_occurs
+            && self.max_occurs == other.max_occurs
+            && self.required == other.required
+            && self.merge == other.merge
+            && self.allow_duplicates == other.allow_duplicates
+            && self.default == other.default
+            && self.raw_body == other.raw_body
+            && self.pattern == other.pattern
+            && self.allowed_values == other.allowed_values
+            && self.aliases == other.aliases
+            && self.required_if == other.required_if
+    }
+}
+
+impl<'a> Eq for KeySpec<'a> {}
+
+/// Consistent with PartialEq, the subkeys are only hashed by id
+impl<'a> Hash for KeySpec<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.desc.hash(state);
+        for subkey in self.subkeys {
+            subkey.id.hash(state);
+        }
+        self.vt.hash(state);
+        self.min_occurs.hash(state);
+        self.max_occurs.hash(state);
+        self.required.hash(state);
+        self.merge.hash(state);
+        self.allow_duplicates.hash(state);
+        self.default.hash(state);
+        self.raw_body.hash(state);
+        self.pattern.hash(state);
+        self.allowed_values.hash(state);
+        self.aliases.hash(state);
+        self.required_if.hash(state);
+    }
 }
 
 impl<'a> Debug for KeySpec<'a> {
@@ -37,74 +129,585 @@ impl<'a> Debug for KeySpec<'a> {
 }
 
 impl<'a> KeySpec<'a> {
+    /// A key with every field at its default value: no subkeys, a single line value, no occurrences
+    /// constraint, not required, no merge, no duplicates allowed, no default value, no raw body, no
+    /// pattern, no allowed values, no aliases and no conditional requirement. Specs set the fields
+    /// they need and take the others with `..KeySpec::DEFAULT`.
+    pub const DEFAULT: KeySpec<'a> = KeySpec {
+        id: "",
+        desc: "",
+        subkeys: &[],
+        vt: ValueType::SingleLine,
+        min_occurs: 0,
+        max_occurs: None,
+        required: false,
+        merge: MergeMode::Error,
+        allow_duplicates: false,
+        default: None,
+        raw_body: false,
+        pattern: None,
+        allowed_values: None,
+        aliases: &[],
+        required_if: None,
+    };
+
+    /// Create a key with the former `once` flag instead of the occurrences counts, the other fields
+    /// have their default value, see KeySpec::DEFAULT
+    pub const fn new(
+        id: &'a str,
+        desc: &'a str,
+        subkeys: &'a DYSpec<'a>,
+        vt: ValueType,
+        once: bool,
+        required: bool,
+    ) -> KeySpec<'a> {
+        KeySpec {
+            id,
+            desc,
+            subkeys,
+            vt,
+            max_occurs: if once { Some(1) } else { None },
+            required,
+            ..KeySpec::DEFAULT
+        }
+    }
+
+    /// The id followed by the aliases, all the ways to write this key
+    pub fn names(&self) -> impl Iterator<Item = &'a str> {
+        std::iter::once(self.id).chain(self.aliases.iter().copied())
+    }
+
     pub fn is_entity(&self) -> bool {
         !self.subkeys.is_empty()
     }
+
+    /// Whether this key is only permitted once for its parent object, duplicates generate
+    /// DuplicatedKey errors or are merged, depending on `merge`
+    pub fn is_once(&self) -> bool {
+        self.max_occurs == Some(1)
+    }
 }
 
-#[derive(Serialize, Debug, Hash, Eq, PartialEq)]
+/// The type of value of a key, the valid combinations with subkeys are
+/// - a `Multiline` key with subkeys is an entity, the first line is its name and the rest is its description
+/// - a `SingleLine` key with subkeys is an entity, the whole line is its name
+/// - any value type without subkeys is a simple value
+///
+/// A `List` key cannot have subkeys, as a list of entries cannot be the name of an entity.
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Copy)]
 pub enum ValueType {
     SingleLine,
     Multiline,
+    /// A single line value that is a list of entries separated by the given delimiter, like `a,b,c`
+    /// with `List(',')`. Entries are trimmed, and empty entries are reported as an error.
+    List(char),
+    /// A value on one or several lines that is not trimmed: only the single space after the key is
+    /// removed, the other spaces are kept, like for the input typed in a terminal. Empty lines
+    /// at the end are still ignored as they separate the next block.
+    Raw,
+    /// A single line value that is a duration with a unit, like `500ms`, `2s` or `1m`. Invalid
+    /// values are reported as an error, see parser::parse_duration()
+    Duration,
+    /// A single line value that is a signed 32 bits integer, like `2` or `-1`. Invalid values are
+    /// reported as an error, see Block::value_as_i32()
+    Integer,
+    /// A single line value that is `true` or `false`. Invalid values are reported as an error, see
+    /// Block::value_as_bool()
+    Bool,
+}
+
+impl ValueType {
+    /// Whether the value must fit on the line of the key, without any following content line
+    pub fn is_single_line(&self) -> bool {
+        matches!(
+            self,
+            ValueType::SingleLine
+                | ValueType::List(_)
+                | ValueType::Duration
+                | ValueType::Integer
+                | ValueType::Bool
+        )
+    }
+}
+
+/// The behavior when a key used only once (see KeySpec::is_once()) is found more than once under the same parent
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Copy, Default)]
+pub enum MergeMode {
+    /// Generate a DuplicatedKey error and ignore the duplicated block
+    #[default]
+    Error,
+    /// Merge the duplicated block into the first one: its text lines and its subblocks are appended
+    /// to the first block, and the range of the first block is extended until the end of the
+    /// duplicated block. The range then also spans any content present between both blocks.
+    Merge,
 }
 
 /// The specification is just a list of keys that are valid at the current level
 pub type DYSpec<'a> = [&'a KeySpec<'a>];
 
+/// An owned mirror of KeySpec, it is the schema of a key exported in JSON or YAML to share a spec
+/// with tools that are not written in Rust, see ValidDYSpec::to_schema_json()
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct OwnedKeySpec {
+    pub id: String,
+    pub desc: String,
+    #[serde(rename = "type")]
+    pub vt: ValueType,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub min_occurs: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_occurs: Option<u32>,
+    pub required: bool,
+    #[serde(default)]
+    pub merge: MergeMode,
+    #[serde(default)]
+    pub allow_duplicates: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw_body: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_values: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_if: Option<(String, String)>,
+    /// The former flag replaced by `max_occurs`, still read from older schemas where `true` means
+    /// a `max_occurs` of 1. It is never exported.
+    #[serde(default, skip_serializing)]
+    pub once: bool,
+    /// The key is a subkey of itself, this is not part of the `children` to avoid an infinite schema
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub recursive: bool,
+    /// The subkeys
+    #[serde(default)]
+    pub children: Vec<OwnedKeySpec>,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// An owned mirror of DYSpec
+pub type OwnedSpec = Vec<OwnedKeySpec>;
+
+impl OwnedKeySpec {
+    fn from_key_spec(key: &KeySpec) -> OwnedKeySpec {
+        let recursive = key.subkeys.iter().any(|s| std::ptr::eq(*s, key));
+        OwnedKeySpec {
+            id: key.id.to_string(),
+            desc: key.desc.to_string(),
+            vt: key.vt,
+            min_occurs: key.min_occurs,
+            max_occurs: key.max_occurs,
+            required: key.required,
+            merge: key.merge,
+            allow_duplicates: key.allow_duplicates,
+            default: key.default.map(|d| d.to_string()),
+            raw_body: key.raw_body,
+            pattern: key.pattern.map(|p| p.to_string()),
+            allowed_values: key
+                .allowed_values
+                .map(|values| values.iter().map(|v| v.to_string()).collect()),
+            aliases: key.aliases.iter().map(|a| a.to_string()).collect(),
+            required_if: key
+                .required_if
+                .map(|(sibling, value)| (sibling.to_string(), value.to_string())),
+            once: false,
+            recursive,
+            children: key
+                .subkeys
+                .iter()
+                .filter(|s| !std::ptr::eq(**s, key))
+                .map(|s| OwnedKeySpec::from_key_spec(s))
+                .collect(),
+        }
+    }
+
+    /// Build a KeySpec with its strings and subkeys allocated in the given arena, so they are all
+    /// freed with the arena
+    fn alloc_in<'b>(&self, arena: &'b Bump) -> Result<&'b KeySpec<'b>, String> {
+        if self.recursive {
+            return Err(format!(
+                "The key '{}' is recursive, this is only supported for specs defined in Rust",
+                self.id
+            ));
+        }
+        let subkeys = self
+            .children
+            .iter()
+            .map(|child| child.alloc_in(arena))
+            .collect::<Result<Vec<_>, String>>()?;
+        let alloc_strs = |strs: &[String]| -> &'b [&'b str] {
+            let strs: Vec<&str> = strs.iter().map(|s| &*arena.alloc_str(s)).collect();
+            arena.alloc_slice_copy(&strs)
+        };
+        Ok(arena.alloc(KeySpec {
+            id: arena.alloc_str(&self.id),
+            desc: arena.alloc_str(&self.desc),
+            subkeys: arena.alloc_slice_copy(&subkeys),
+            vt: self.vt,
+            min_occurs: self.min_occurs,
+            max_occurs: self.max_occurs.or(self.once.then_some(1)),
+            required: self.required,
+            merge: self.merge,
+            allow_duplicates: self.allow_duplicates,
+            default: self.default.as_deref().map(|d| &*arena.alloc_str(d)),
+            raw_body: self.raw_body,
+            pattern: self.pattern.as_deref().map(|p| &*arena.alloc_str(p)),
+            allowed_values: self.allowed_values.as_deref().map(alloc_strs),
+            aliases: alloc_strs(&self.aliases),
+            required_if: self
+                .required_if
+                .as_ref()
+                .map(|(sibling, value)| (&*arena.alloc_str(sibling), &*arena.alloc_str(value))),
+        }))
+    }
+}
+
+self_cell!(
+    /// A ValidDYSpec built at runtime from an OwnedSpec, that owns its keys instead of borrowing
+    /// them from constants. See ValidDYSpec::from_owned().
+    pub struct OwnedValidSpec {
+        owner: Bump,
+        #[covariant]
+        dependent: ValidDYSpec,
+    }
+    impl {Debug}
+);
+
+impl OwnedValidSpec {
+    /// The spec, to be used like a ValidDYSpec built from constants
+    pub fn spec(&self) -> &ValidDYSpec<'_> {
+        self.borrow_dependent()
+    }
+}
+
 /// Wrapper type of DYSpec, to validate the spec semantically
 #[derive(Debug, Eq, PartialEq)]
-pub struct ValidDYSpec<'a>(&'a DYSpec<'a>);
+pub struct ValidDYSpec<'a> {
+    spec: &'a DYSpec<'a>,
+    /// The start of the lines to ignore as comments, `//` by default
+    comment_prefix: &'a str,
+}
 
 /// Extract a flat vector of key specs to tokenize lines
 pub fn all_valid_keys<'a>(spec: &'a DYSpec<'a>) -> Vec<&'a KeySpec<'a>> {
-    let mut all_keys = spec.to_vec();
-    all_keys.extend(spec.iter().flat_map(|k| all_valid_keys(k.subkeys)));
+    let mut all_keys = Vec::new();
+    collect_keys(spec, &mut all_keys);
     all_keys
 }
 
+/// Keys are compared by pointer identity to never visit the same key twice, so flattening a
+/// recursive spec (like a subskill containing subskills) terminates
+fn collect_keys<'a>(spec: &'a DYSpec<'a>, all_keys: &mut Vec<&'a KeySpec<'a>>) {
+    let new_keys: Vec<&KeySpec> = spec
+        .iter()
+        .filter(|k| !all_keys.iter().any(|known| std::ptr::eq(*known, **k)))
+        .copied()
+        .collect();
+    all_keys.extend(&new_keys);
+    for key in new_keys {
+        collect_keys(key.subkeys, all_keys);
+    }
+}
+
 impl<'a> ValidDYSpec<'a> {
     pub fn new(spec: &'a DYSpec) -> Result<Self, String> {
         let mut keys: HashSet<&str> = HashSet::new();
         if spec.is_empty() {
             return Err("The spec cannot be empty".to_string());
         }
-        Self::spec_does_not_contain_known_keys(&mut keys, spec)?;
-        Ok(ValidDYSpec(spec))
+        Self::spec_does_not_contain_known_keys(&mut keys, spec, &mut Vec::new())?;
+        Ok(ValidDYSpec {
+            spec,
+            comment_prefix: COMMENT_PREFIX,
+        })
+    }
+
+    /// Use another comment prefix than `//`, for specs where `//` is meaningful in values. The
+    /// doc comments then start with this prefix followed by `!`.
+    pub fn with_comment_prefix(mut self, comment_prefix: &'a str) -> Result<Self, String> {
+        if comment_prefix.trim().is_empty() {
+            return Err("The comment prefix cannot be empty".to_string());
+        }
+        self.comment_prefix = comment_prefix;
+        Ok(self)
     }
 
     pub fn get(&'a self) -> &'a DYSpec<'a> {
-        self.0
+        self.spec
+    }
+
+    pub fn comment_prefix(&self) -> &'a str {
+        self.comment_prefix
+    }
+
+    /// Get an owned copy of this spec, that can be serialized or edited at runtime
+    pub fn to_owned_spec(&self) -> OwnedSpec {
+        self.spec
+            .iter()
+            .map(|k| OwnedKeySpec::from_key_spec(k))
+            .collect()
     }
 
+    /// Export this spec as a JSON array of keys with their id, desc, type, occurrences, required and
+    /// children, for editors and validators not written in Rust
+    pub fn to_schema_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_owned_spec())
+            .expect("a spec can always be serialized")
+    }
+
+    /// Same as to_schema_json() but in YAML, easier to write by hand
+    pub fn to_schema_yaml(&self) -> String {
+        serde_yaml_ng::to_string(&self.to_owned_spec()).expect("a spec can always be serialized")
+    }
+
+    /// Build a spec at runtime from an owned spec, like one loaded from a schema file. It is
+    /// validated like with ValidDYSpec::new().
+    pub fn from_owned(owned: &OwnedSpec) -> Result<OwnedValidSpec, String> {
+        OwnedValidSpec::try_new(Bump::new(), |arena| {
+            let keys = owned
+                .iter()
+                .map(|key| key.alloc_in(arena))
+                .collect::<Result<Vec<_>, String>>()?;
+            ValidDYSpec::new(arena.alloc_slice_copy(&keys))
+        })
+    }
+
+    /// Import a spec exported by to_schema_json(), see from_owned()
+    pub fn from_schema_json(json: &str) -> Result<OwnedValidSpec, String> {
+        let owned: OwnedSpec =
+            serde_json::from_str(json).map_err(|e| format!("Invalid spec schema: {e}"))?;
+        Self::from_owned(&owned)
+    }
+
+    /// Import a spec exported by to_schema_yaml(), see from_owned()
+    pub fn from_schema_yaml(yaml: &str) -> Result<OwnedValidSpec, String> {
+        let owned: OwnedSpec =
+            serde_yaml_ng::from_str(yaml).map_err(|e| format!("Invalid spec schema: {e}"))?;
+        Self::from_owned(&owned)
+    }
+
+    /// Find the key with the given id, at any level of the spec
+    pub fn find_key(&self, id: &str) -> Option<&'a KeySpec<'a>> {
+        all_valid_keys(self.spec).into_iter().find(|k| k.id == id)
+    }
+
+    /// The `ancestors` are the keys containing the current `spec`, to detect cycles. A key that is
+    /// directly a subkey of itself is a valid recursive key, its subkeys are not visited again.
+    /// A key that is a subkey of itself through other keys is an error.
     fn spec_does_not_contain_known_keys(
         known_keys: &mut HashSet<&'a str>,
         spec: &'a DYSpec,
+        ancestors: &mut Vec<&'a KeySpec<'a>>,
     ) -> Result<(), String> {
         for key_spec in spec {
-            if known_keys.contains(key_spec.id) {
-                return Err(format!("Duplicated key identifier '{}'", key_spec.id));
-            } else {
-                known_keys.insert(key_spec.id);
+            if ancestors
+                .last()
+                .is_some_and(|parent| std::ptr::eq(*parent, *key_spec))
+            {
+                continue;
+            }
+            if ancestors.iter().any(|a| std::ptr::eq(*a, *key_spec)) {
+                return Err(format!(
+                    "Cycle detected, the key '{}' is contained in one of its subkeys",
+                    key_spec.id
+                ));
+            }
+            if ancestors
+                .last()
+                .is_some_and(|parent| parent.id == key_spec.id)
+            {
+                return Err(format!(
+                    "The key '{}' has a different subkey with the same id, this subkey is unreachable",
+                    key_spec.id
+                ));
+            }
+            // The aliases must not be used by other keys as id or alias either
+            for name in key_spec.names() {
+                if !known_keys.insert(name) {
+                    return Err(format!("Duplicated key identifier '{name}'"));
+                }
+            }
+            Self::key_is_consistent(key_spec)?;
+            if let Some((sibling, _)) = key_spec.required_if
+                && !spec.iter().any(|k| k.id == sibling)
+            {
+                return Err(format!(
+                    "The key '{}' is required depending on the value of '{sibling}' but it is not a sibling key",
+                    key_spec.id
+                ));
             }
             // Search recursively in subkeys
             if !key_spec.subkeys.is_empty() {
-                Self::spec_does_not_contain_known_keys(known_keys, key_spec.subkeys)?;
+                ancestors.push(key_spec);
+                Self::spec_does_not_contain_known_keys(known_keys, key_spec.subkeys, ancestors)?;
+                ancestors.pop();
             }
         }
         Ok(())
     }
+
+    /// Make sure the value type of the given key is compatible with its subkeys, that its
+    /// occurrences constraints can be respected, that its pattern is a valid regex and that its
+    /// allowed values are on a single line
+    fn key_is_consistent(key_spec: &KeySpec) -> Result<(), String> {
+        if let ValueType::List(delimiter) = key_spec.vt {
+            if key_spec.is_entity() {
+                return Err(format!(
+                    "The key '{}' is a list and cannot have subkeys",
+                    key_spec.id
+                ));
+            }
+            if delimiter == '\n' {
+                return Err(format!(
+                    "The key '{}' is a list on a single line, its delimiter cannot be a new line",
+                    key_spec.id
+                ));
+            }
+        }
+        if key_spec.required && key_spec.max_occurs == Some(0) {
+            return Err(format!(
+                "The key '{}' is required but can never be used, its maximum occurrences is 0",
+                key_spec.id
+            ));
+        }
+        if key_spec
+            .max_occurs
+            .is_some_and(|max| key_spec.min_occurs > max)
+        {
+            return Err(format!(
+                "The key '{}' has a minimum occurrences greater than its maximum",
+                key_spec.id
+            ));
+        }
+        if key_spec.raw_body && key_spec.is_entity() {
+            return Err(format!(
+                "The key '{}' has a raw body and cannot have subkeys",
+                key_spec.id
+            ));
+        }
+        if key_spec.allowed_values.is_some() && !key_spec.vt.is_single_line() {
+            return Err(format!(
+                "The key '{}' is not single line and cannot have allowed values",
+                key_spec.id
+            ));
+        }
+        if let Some(pattern) = key_spec.pattern {
+            Regex::new(pattern).map_err(|e| {
+                format!(
+                    "The key '{}' has an invalid pattern '{pattern}': {e}",
+                    key_spec.id
+                )
+            })?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::common::tests::{CODE_SPEC, GOAL_SPEC, TESTING_COURSE_SPEC};
-    use crate::spec::{KeySpec, ValidDYSpec, ValueType};
+    use crate::common::tests::{CODE_SPEC, GOAL_SPEC, TESTING_COURSE_SPEC, TESTING_EXOS_SPEC};
+    use std::collections::HashSet;
+
+    use crate::spec::{DYSpec, KeySpec, ValidDYSpec, ValueType, all_valid_keys};
+
+    #[test]
+    fn test_schema_json_contains_the_keys_tree() {
+        let json = ValidDYSpec::new(TESTING_COURSE_SPEC)
+            .unwrap()
+            .to_schema_json();
+        let schema: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(schema[0]["id"], "course");
+        assert_eq!(schema[0]["type"], "SingleLine");
+        assert_eq!(schema[0]["children"][0]["id"], "code");
+        assert_eq!(schema[0]["children"][1]["id"], "goal");
+        assert_eq!(schema[0]["children"][1]["type"], "Multiline");
+
+        let owned_spec = ValidDYSpec::from_schema_json(&json).unwrap();
+        let imported = owned_spec.spec();
+        let ids: Vec<&str> = all_valid_keys(imported.get())
+            .iter()
+            .map(|k| k.id)
+            .collect();
+        assert_eq!(ids, vec!["course", "code", "goal"]);
+        assert_eq!(imported.to_schema_json(), json);
+    }
+
+    #[test]
+    fn test_schema_yaml_can_be_imported() {
+        let yaml = "
+- id: course
+  desc: The course
+  type: SingleLine
+  max_occurs: 1
+  required: true
+  children:
+  - id: tags
+    desc: Some tags
+    type: !List ','
+    required: false
+";
+        let owned_spec = ValidDYSpec::from_schema_yaml(yaml).unwrap();
+        let imported = owned_spec.spec();
+        let tags = imported.find_key("tags").unwrap();
+        assert_eq!(tags.vt, ValueType::List(','));
+        assert!(imported.find_key("course").unwrap().is_once());
+        assert_eq!(
+            ValidDYSpec::from_schema_yaml(&imported.to_schema_yaml())
+                .unwrap()
+                .spec()
+                .to_owned_spec(),
+            imported.to_owned_spec()
+        );
+        assert!(
+            ValidDYSpec::from_schema_yaml("- id: a")
+                .unwrap_err()
+                .starts_with("Invalid spec schema")
+        );
+    }
+
+    #[test]
+    fn test_invalid_schema_json_is_an_error() {
+        assert!(
+            ValidDYSpec::from_schema_json("[{\"id\": \"a\"}]")
+                .unwrap_err()
+                .starts_with("Invalid spec schema")
+        );
+        let duplicated = r#"[
+            {"id": "a", "desc": "", "type": "SingleLine", "once": true, "required": true},
+            {"id": "a", "desc": "", "type": "SingleLine", "once": true, "required": true}
+        ]"#;
+        assert_eq!(
+            ValidDYSpec::from_schema_json(duplicated).unwrap_err(),
+            "Duplicated key identifier 'a'"
+        );
+    }
+
+    #[test]
+    fn test_legacy_once_in_schema_is_a_max_occurs_of_one() {
+        let legacy = r#"[
+            {"id": "a", "desc": "", "type": "SingleLine", "once": true, "required": true},
+            {"id": "b", "desc": "", "type": "SingleLine", "required": false}
+        ]"#;
+        let owned_spec = ValidDYSpec::from_schema_json(legacy).unwrap();
+        let spec = owned_spec.spec();
+        assert_eq!(spec.find_key("a").unwrap().max_occurs, Some(1));
+        assert_eq!(spec.find_key("b").unwrap().max_occurs, None);
+        assert!(!spec.to_schema_json().contains("\"once\""));
+    }
 
     #[test]
     fn test_can_validate_valid_spec() {
         assert_eq!(
             ValidDYSpec::new(TESTING_COURSE_SPEC),
-            Ok(ValidDYSpec(TESTING_COURSE_SPEC))
+            Ok(ValidDYSpec {
+                spec: TESTING_COURSE_SPEC,
+                comment_prefix: "//"
+            })
         );
     }
 
@@ -132,12 +735,248 @@ mod tests {
                     id: "course",
                     subkeys: &[CODE_SPEC, GOAL_SPEC],
                     vt: ValueType::SingleLine,
-                    once: true,
+                    max_occurs: Some(1),
                     required: true,
+                    ..KeySpec::DEFAULT
                 }
             ])
             .unwrap_err()
             .contains("Duplicated key identifier 'goal'")
         );
     }
+
+    #[test]
+    fn test_value_type_and_subkeys_legal_combinations() {
+        // SingleLine with subkeys
+        assert!(ValidDYSpec::new(TESTING_COURSE_SPEC).is_ok());
+        // Multiline with subkeys
+        assert!(ValidDYSpec::new(TESTING_EXOS_SPEC).is_ok());
+        // List without subkeys
+        assert!(
+            ValidDYSpec::new(&[&KeySpec {
+                desc: "test",
+                id: "tags",
+                subkeys: &[],
+                vt: ValueType::List(','),
+                max_occurs: Some(1),
+                required: true,
+                ..KeySpec::DEFAULT
+            }])
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_value_type_and_subkeys_illegal_combinations() {
+        assert!(
+            ValidDYSpec::new(&[&KeySpec {
+                desc: "test",
+                id: "tags",
+                subkeys: &[CODE_SPEC],
+                vt: ValueType::List(','),
+                max_occurs: Some(1),
+                required: true,
+                ..KeySpec::DEFAULT
+            }])
+            .unwrap_err()
+            .contains("The key 'tags' is a list and cannot have subkeys")
+        );
+        assert!(
+            ValidDYSpec::new(&[&KeySpec {
+                desc: "test",
+                id: "tags",
+                subkeys: &[],
+                vt: ValueType::List('\n'),
+                max_occurs: Some(1),
+                required: true,
+                ..KeySpec::DEFAULT
+            }])
+            .unwrap_err()
+            .contains("its delimiter cannot be a new line")
+        );
+        assert!(
+            ValidDYSpec::new(&[&KeySpec {
+                desc: "test",
+                id: "template",
+                subkeys: &[CODE_SPEC],
+                vt: ValueType::Multiline,
+                max_occurs: Some(1),
+                raw_body: true,
+                ..KeySpec::DEFAULT
+            }])
+            .unwrap_err()
+            .contains("The key 'template' has a raw body and cannot have subkeys")
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_detected() {
+        let key = |pattern| KeySpec {
+            pattern: Some(pattern),
+            ..KeySpec::new("code", "test", &[], ValueType::SingleLine, true, true)
+        };
+        assert!(ValidDYSpec::new(&[&key("^[A-Z]{3}[0-9]*$")]).is_ok());
+        assert!(
+            ValidDYSpec::new(&[&key("^[A-Z{3}$")])
+                .unwrap_err()
+                .contains("The key 'code' has an invalid pattern '^[A-Z{3}$'")
+        );
+    }
+
+    #[test]
+    fn test_aliases_must_be_unique_like_ids() {
+        let key = |aliases| KeySpec {
+            aliases,
+            ..KeySpec::new("objective", "test", &[], ValueType::Multiline, false, false)
+        };
+        assert!(ValidDYSpec::new(&[&key(&["target"]), GOAL_SPEC]).is_ok());
+        assert_eq!(
+            ValidDYSpec::new(&[&key(&["goal"]), GOAL_SPEC]).unwrap_err(),
+            "Duplicated key identifier 'goal'"
+        );
+        assert_eq!(
+            ValidDYSpec::new(&[&key(&["objective"])]).unwrap_err(),
+            "Duplicated key identifier 'objective'"
+        );
+    }
+
+    #[test]
+    fn test_required_if_must_refer_to_a_sibling() {
+        let timeout = KeySpec {
+            required_if: Some(("type", "interactive")),
+            ..KeySpec::new("timeout", "test", &[], ValueType::SingleLine, true, false)
+        };
+        let kind = KeySpec::new("type", "test", &[], ValueType::SingleLine, true, false);
+        assert!(ValidDYSpec::new(&[&timeout, &kind]).is_ok());
+        assert_eq!(
+            ValidDYSpec::new(&[&timeout]).unwrap_err(),
+            "The key 'timeout' is required depending on the value of 'type' but it is not a sibling key"
+        );
+    }
+
+    #[test]
+    fn test_allowed_values_on_multiline_key_are_detected() {
+        let key = |vt| KeySpec {
+            allowed_values: Some(&["easy", "medium", "hard"]),
+            ..KeySpec::new("difficulty", "test", &[], vt, true, false)
+        };
+        assert!(ValidDYSpec::new(&[&key(ValueType::SingleLine)]).is_ok());
+        assert!(ValidDYSpec::new(&[&key(ValueType::List(','))]).is_ok());
+        assert!(
+            ValidDYSpec::new(&[&key(ValueType::Multiline)])
+                .unwrap_err()
+                .contains("The key 'difficulty' is not single line and cannot have allowed values")
+        );
+    }
+
+    static RECURSIVE_SKILL_SPEC: KeySpec = KeySpec {
+        id: "skill",
+        desc: "test",
+        subkeys: &[&RECURSIVE_SKILL_SPEC],
+        vt: ValueType::Multiline,
+        required: true,
+        ..KeySpec::DEFAULT
+    };
+    static CYCLE_A_SPEC: KeySpec = KeySpec {
+        id: "a",
+        desc: "test",
+        subkeys: &[&CYCLE_B_SPEC],
+        vt: ValueType::Multiline,
+        required: true,
+        ..KeySpec::DEFAULT
+    };
+    static CYCLE_B_SPEC: KeySpec = KeySpec {
+        id: "b",
+        desc: "test",
+        subkeys: &[&CYCLE_A_SPEC],
+        vt: ValueType::Multiline,
+        required: true,
+        ..KeySpec::DEFAULT
+    };
+
+    #[test]
+    #[ntest::timeout(50)]
+    fn test_self_referential_spec_is_valid_and_can_be_flattened() {
+        let spec: &DYSpec = &[&RECURSIVE_SKILL_SPEC];
+        assert!(ValidDYSpec::new(spec).is_ok());
+        assert_eq!(all_valid_keys(spec), vec![&RECURSIVE_SKILL_SPEC]);
+        let mut hashes = HashSet::new();
+        hashes.insert(&RECURSIVE_SKILL_SPEC);
+        assert!(hashes.contains(&RECURSIVE_SKILL_SPEC));
+    }
+
+    #[test]
+    #[ntest::timeout(50)]
+    fn test_cycle_in_spec_is_detected() {
+        let spec: &DYSpec = &[&CYCLE_A_SPEC];
+        assert!(
+            ValidDYSpec::new(spec)
+                .unwrap_err()
+                .contains("Cycle detected, the key 'a' is contained in one of its subkeys")
+        );
+        let ids: Vec<&str> = all_valid_keys(spec).iter().map(|k| k.id).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_subkey_with_the_id_of_its_parent_is_unreachable() {
+        const INNER_SKILL_SPEC: &KeySpec = &KeySpec {
+            id: "skill",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::SingleLine,
+            ..KeySpec::DEFAULT
+        };
+        const OUTER_SKILL_SPEC: &KeySpec = &KeySpec {
+            id: "skill",
+            desc: "test",
+            subkeys: &[INNER_SKILL_SPEC],
+            vt: ValueType::Multiline,
+            ..KeySpec::DEFAULT
+        };
+        assert!(
+            ValidDYSpec::new(&[OUTER_SKILL_SPEC])
+                .unwrap_err()
+                .contains("The key 'skill' has a different subkey with the same id")
+        );
+    }
+
+    #[test]
+    fn test_occurrences_constraints_must_be_reachable() {
+        const NEVER_SPEC: &KeySpec = &KeySpec {
+            id: "never",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::SingleLine,
+            max_occurs: Some(0),
+            required: true,
+            ..KeySpec::DEFAULT
+        };
+        assert!(
+            ValidDYSpec::new(&[NEVER_SPEC])
+                .unwrap_err()
+                .contains("The key 'never' is required but can never be used")
+        );
+        const INVERTED_SPEC: &KeySpec = &KeySpec {
+            id: "inverted",
+            desc: "test",
+            subkeys: &[],
+            vt: ValueType::SingleLine,
+            min_occurs: 3,
+            max_occurs: Some(2),
+            ..KeySpec::DEFAULT
+        };
+        assert!(
+            ValidDYSpec::new(&[INVERTED_SPEC])
+                .unwrap_err()
+                .contains("The key 'inverted' has a minimum occurrences greater than its maximum")
+        );
+        // The convenience constructor maps the former once flag
+        const ONCE_SPEC: &KeySpec =
+            &KeySpec::new("once", "test", &[], ValueType::SingleLine, true, false);
+        assert!(ONCE_SPEC.is_once());
+        assert_eq!(ONCE_SPEC.min_occurs, 0);
+        assert!(!KeySpec::new("many", "test", &[], ValueType::SingleLine, false, true).is_once());
+        assert!(ValidDYSpec::new(&[ONCE_SPEC]).is_ok());
+    }
 }
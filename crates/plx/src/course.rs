@@ -20,43 +20,50 @@ const GOAL_KEYSPEC: &KeySpec = &KeySpec {
     desc: "The goal key describes the learning goals of this course.",
     subkeys: &[],
     vt: ValueType::Multiline,
-    once: true,
+    max_occurs: Some(1),
     required: true,
+    ..KeySpec::DEFAULT
 };
 const CODE_KEYSPEC: &KeySpec = &KeySpec {
     id: "code",
     desc: "The code of the course is a shorter name of the course, under 10 letters usually.",
     subkeys: &[],
     vt: ValueType::SingleLine,
-    once: true,
+    max_occurs: Some(1),
     required: true,
+    ..KeySpec::DEFAULT
 };
 const COURSE_KEYSPEC: &KeySpec = &KeySpec {
     id: "course",
     desc: "A PLX course is grouping skills and exos related to a common set of learning goals.",
     subkeys: &[CODE_KEYSPEC, GOAL_KEYSPEC],
     vt: ValueType::SingleLine,
-    once: true,
+    max_occurs: Some(1),
     required: true,
+    ..KeySpec::DEFAULT
 };
 pub const COURSE_SPEC: &DYSpec = &[COURSE_KEYSPEC];
 
 impl<'a> FromDYBlock<'a> for DYCourse {
     fn from_block_with_validation(block: &Block<'a>) -> (Vec<ParseError>, DYCourse) {
-        let errors = Vec::new();
-        let mut course = DYCourse {
-            name: block.get_joined_text(),
-            ..Default::default()
+        let mut errors = Vec::new();
+        let mut single_line = |block: &Block| {
+            block.require_single_line().unwrap_or_else(|error| {
+                errors.push(*error);
+                block.get_joined_text()
+            })
+        };
+        let course = DYCourse {
+            name: single_line(block),
+            code: block
+                .find_subblock(CODE_KEYSPEC.id)
+                .map(&mut single_line)
+                .unwrap_or_default(),
+            goal: block
+                .find_subblock(GOAL_KEYSPEC.id)
+                .map(|b| b.require_multiline())
+                .unwrap_or_default(),
         };
-        for subblock in block.subblocks.iter() {
-            let id = subblock.key.id;
-            if id == CODE_KEYSPEC.id {
-                course.code = subblock.get_joined_text();
-            }
-            if id == GOAL_KEYSPEC.id {
-                course.goal = subblock.get_joined_text();
-            }
-        }
         (errors, course)
     }
 }
@@ -75,7 +82,59 @@ mod tests {
 
     use pretty_assertions::assert_eq;
 
-    use crate::course::{DYCourse, parse_course};
+    use dy::spec::{KeySpec, MergeMode, ValidDYSpec, ValueType};
+    use dy::{
+        FromDYBlock, ParseOptions,
+        error::{ParseError, ParseErrorType},
+        lexer::tokenize_into_lines,
+        parse_with_options, parse_with_spec,
+        parser::build_blocks_tree,
+        range_on_line_with_length,
+    };
+
+    use crate::course::{CODE_KEYSPEC, COURSE_SPEC, DYCourse, GOAL_KEYSPEC, parse_course};
+
+    #[test]
+    fn test_multiline_code_edited_by_hand_is_an_error() {
+        let spec = ValidDYSpec::new(COURSE_SPEC).unwrap();
+        let lines = tokenize_into_lines(&spec, "course Programmation 1\ncode PRG1\ngoal Learn C++");
+        let (mut blocks, _) = build_blocks_tree(&spec, lines);
+        blocks[0].subblocks[0].text.push("PRG2");
+        let (errors, course) = DYCourse::from_block_with_validation(&blocks[0]);
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_line_with_length(1, 9),
+                ParseErrorType::InvalidMultilineContent("code".to_string()),
+            )]
+        );
+        assert_eq!(course.code, "PRG1\nPRG2");
+        assert_eq!(course.goal, "Learn C++");
+    }
+
+    #[test]
+    fn test_transformer_is_applied_on_values_but_not_on_errors() {
+        let text = "course Programmation 1
+code prg1
+goal learn c++
+goal again";
+        let options = ParseOptions {
+            transformer: Some(|text| text.to_uppercase()),
+            ..Default::default()
+        };
+        let spec = ValidDYSpec::new(COURSE_SPEC).unwrap();
+        let result = parse_with_options::<DYCourse>(&spec, &None, text, &options);
+        assert_eq!(result.items[0].code, "PRG1");
+        assert_eq!(result.items[0].goal, "LEARN C++");
+        assert_eq!(
+            result.errors,
+            parse_with_spec::<DYCourse>(&spec, &None, text).errors
+        );
+        assert_eq!(
+            result.errors[0].error,
+            ParseErrorType::DuplicatedKey("goal".to_string(), 1)
+        );
+    }
 
     #[test]
     fn test_can_parse_simple_valid_course() {
@@ -93,7 +152,8 @@ goal Apprendre des bases solides du C++";
                 }],
                 errors: vec![],
                 some_file_path,
-                some_file_content: None // on errors
+                some_file_content: None, // on errors
+                doc_comments: vec![],
             }
         )
     }
@@ -105,11 +165,11 @@ course PRG1
 goal Learn C++
 course PRG2
 goal hey";
-        let expected_output = "Found 1 item in course.dy with 3 errors.
+        let expected_output = "Found 1 item in course.dy with 4 errors.
 
 Error at course.dy:0:0
 code YEP
-^^^^ The 'code' key can be only used under a `??`
+^^^^ The 'code' key can be only used under a `course`
 
 Error at course.dy:1:0
 course PRG1
@@ -117,7 +177,11 @@ course PRG1
 
 Error at course.dy:3:0
 course PRG2
-^^^^^^ The 'course' key can only be used once in the document root
+^^^^^^^^^^^ The 'course' key can only be used once in the document root
+
+Warning at course.dy:3:0
+course PRG2
+^^^^^^ The 1 subblock(s) of this duplicated 'course' key are ignored with it
 ";
 
         let parse_result = parse_course(&Some("course.dy".to_string()), text);
@@ -125,6 +189,28 @@ course PRG2
         assert_eq!(format!("{parse_result}"), expected_output);
     }
 
+    #[test]
+    fn test_parse_result_can_be_displayed_with_one_based_positions() {
+        let text = "course
+code PRG1
+goal Learn C++
+";
+        let expected_output = "Found 1 item in course.dy with 1 error.
+
+Error at course.dy:1:7
+course
+      | Missing a value for the required key 'course'
+";
+
+        let parse_result = parse_course(&Some("course.dy".to_string()), text);
+        assert_eq!(
+            format!("{}", parse_result.display_one_based()),
+            expected_output
+        );
+        // The range is still 0-based
+        assert_eq!(parse_result.errors[0].range.start.line, 0);
+    }
+
     #[test]
     fn test_parse_result_display_is_also_correct() {
         let text = "course
@@ -176,4 +262,71 @@ oupsii
         eprintln!("{parse_result}");
         assert_eq!(format!("{parse_result}"), expected_output);
     }
+
+    #[test]
+    fn test_diff_detects_changed_goal() {
+        let text = "course Programmation 1
+code PRG1
+goal Learn C++";
+        let edited_text = "course Programmation 1
+code PRG1
+goal Learn C++ and Qt";
+        let before = parse_course(&None, text);
+        let after = parse_course(&None, edited_text);
+        let diff = before.diff(&after, |course| course.code.clone());
+        assert_eq!(diff.changed_items, vec![&after.items[0]]);
+        assert!(diff.added_items.is_empty());
+        assert!(diff.removed_items.is_empty());
+        assert!(diff.added_errors.is_empty());
+        assert!(diff.removed_errors.is_empty());
+    }
+
+    #[test]
+    fn test_parse_result_display_with_spec_includes_key_description() {
+        let text = "course PRG1
+goal Learn C++";
+        let expected_output = "Found 1 item in course.dy with 1 error.
+
+Error at course.dy:0:0
+course PRG1
+| Missing required key 'code'
+Hint: 'code' - The code of the course is a shorter name of the course, under 10 letters usually.
+";
+        let spec = ValidDYSpec::new(COURSE_SPEC).unwrap();
+        let parse_result = parse_course(&Some("course.dy".to_string()), text);
+        assert_eq!(
+            format!("{}", parse_result.display_with_spec(&spec)),
+            expected_output
+        );
+    }
+
+    #[test]
+    fn test_can_merge_two_course_blocks_into_one_course() {
+        const MERGED_COURSE_KEYSPEC: &KeySpec = &KeySpec {
+            id: "course",
+            desc: "test",
+            subkeys: &[CODE_KEYSPEC, GOAL_KEYSPEC],
+            vt: ValueType::SingleLine,
+            max_occurs: Some(1),
+            required: true,
+            merge: MergeMode::Merge,
+            ..KeySpec::DEFAULT
+        };
+        let text = "course Programmation 1
+code PRG1
+
+course
+goal Learn C++";
+        let spec = ValidDYSpec::new(&[MERGED_COURSE_KEYSPEC]).unwrap();
+        let parse_result = parse_with_spec::<DYCourse>(&spec, &None, text);
+        assert_eq!(parse_result.errors, vec![]);
+        assert_eq!(
+            parse_result.items,
+            vec![DYCourse {
+                name: "Programmation 1".to_string(),
+                code: "PRG1".to_string(),
+                goal: "Learn C++".to_string()
+            }]
+        );
+    }
 }
@@ -1,12 +1,15 @@
 use dy::{
     FromDYBlock, ParseResult,
     error::{ParseError, ParseErrorType},
+    lexer::extract_code_blocks,
     parse_with_spec,
-    parser::Block,
+    parser::{Block, LazyText},
     range_on_line_part,
     spec::{DYSpec, KeySpec, ValidDYSpec, ValueType},
 };
-use serde::Serialize;
+use lsp_types::Range;
+use serde::{Serialize, Serializer};
+use std::{sync::LazyLock, time::Duration};
 
 /// This describes the automation of an action that would normally be done manually in the terminal
 #[derive(Serialize, Debug, PartialEq)]
@@ -14,9 +17,40 @@ pub enum TermAction {
     /// Make sure there is the given string in the program stdout. It doesn't need to be exact.
     /// This string is trimed itself to avoid any missing invisible space causing check failure
     See(String),
+    /// Make sure the given lines are present in the program stdout, contiguous and in this order.
+    /// This is generated by a `see` with a value on several lines. The text is trimmed as a whole,
+    /// but the lines themselves are kept as is.
+    SeeBlock(Vec<String>),
+    /// Same as See or SeeBlock but the expected text is in the file given after `@`, like
+    /// `see @expected.txt`, relative to the exo file. It is replaced by the file content when the
+    /// exo is parsed with project::parse_exo_file(). The range of the `@` and the file is kept to
+    /// report a missing file, it is not serialized.
+    #[serde(serialize_with = "serialize_see_file")]
+    SeeFile(String, Range),
     /// Type something in the terminal, by injecting content into stdin at once,
     /// including an additionnal new line \n at the end
     Type(String),
+    /// Same as Type but without the additionnal new line at the end, for programs reading raw input
+    TypeRaw(String),
+}
+
+impl TermAction {
+    /// The key that generated this action, actions of the same kind are numbered together
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TermAction::See(_) | TermAction::SeeBlock(_) | TermAction::SeeFile(..) => "see",
+            TermAction::Type(_) | TermAction::TypeRaw(_) => "type",
+        }
+    }
+}
+
+/// A structured argument, so a runner can run a check once per value of a numeric range
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub enum ArgValue {
+    /// A plain argument, passed as is
+    Literal(String),
+    /// An argument written `N..M`, the end is excluded like in Rust, so `1..3` means 1 and 2
+    Range(i32, i32),
 }
 
 #[derive(Serialize, Default, Debug, PartialEq)]
@@ -24,164 +58,640 @@ pub struct Check {
     pub name: String,
     /// The argument to pass to exo program when executing it
     pub args: Vec<String>,
-    /// The expected exit code of the exo program
+    /// The arguments exactly as written after the `args` key (only trimmed), to display the
+    /// command faithfully
+    pub args_raw: Option<String>,
+    /// The same arguments as `args` but where the `N..M` ones are parsed as ranges
+    pub arg_values: Vec<ArgValue>,
+    /// The expected exit code of the exo program, it is 0 when no `exit` is given,
+    /// except when a `signal` is expected. It is None if the given value is invalid.
     pub exit: Option<i32>, // why i32 ? because std::process::ExitStatus::code() -> Option<i32>
+    /// The expected signal that killed the exo program, on Unix. Cannot be used with `exit`.
+    pub signal: Option<i32>, // why i32 ? because std::os::unix::process::ExitStatusExt::signal() -> Option<i32>
+    /// The maximum duration of the check, given by `check_timeout` or by the `timeout` in the
+    /// `defaults` of the exo. It is serialized in milliseconds.
+    #[serde(serialize_with = "serialize_millis")]
+    pub timeout: Option<Duration>,
     /// The test sequence containing assertions to verify the behavior of the exo program
     pub sequence: Vec<TermAction>,
 }
 
+impl Check {
+    /// Get the problems of this check as human readable messages, an empty vec means the check is
+    /// valid. This is useful for checks built incrementally, parsed checks are already validated.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.name.trim().is_empty() {
+            problems.push(ERROR_CHECK_WITHOUT_NAME.to_string());
+        }
+        if !self.sequence.iter().any(|action| {
+            matches!(
+                action,
+                TermAction::See(_) | TermAction::SeeBlock(_) | TermAction::SeeFile(..)
+            )
+        }) {
+            problems.push(ERROR_CHECK_WITHOUT_SEE.to_string());
+        }
+        if self.exit.is_some() && self.signal.is_some() {
+            problems.push(ERROR_EXIT_AND_SIGNAL_CONFLICT.to_string());
+        }
+        if self.args.iter().any(|arg| arg.is_empty()) {
+            problems.push(ERROR_EMPTY_ARG.to_string());
+        }
+        if self
+            .sequence
+            .iter()
+            .any(|action| matches!(action, TermAction::Type(text) if text.contains('\n')))
+        {
+            problems.push(ERROR_TYPE_CONTAINS_NEWLINE.to_string());
+        }
+        problems
+    }
+
+    /// Describe the action at the given index of the sequence with its 1-based number among the
+    /// actions of the same kind, like `see #2: Hello John`, so a runner can tell which assertion failed
+    pub fn describe_action(&self, index: usize) -> String {
+        let Some(action) = self.sequence.get(index) else {
+            return format!("no action at index {index}");
+        };
+        let number = self.sequence[..=index]
+            .iter()
+            .filter(|a| a.kind() == action.kind())
+            .count();
+        let text = match action {
+            TermAction::See(text) | TermAction::Type(text) => text.clone(),
+            TermAction::SeeBlock(lines) => lines.join("\n"),
+            TermAction::SeeFile(file, _) => format!("@{file}"),
+            TermAction::TypeRaw(text) => format!("{text} (without new line)"),
+        };
+        format!("{} #{number}: {text}", action.kind())
+    }
+}
+
 #[derive(Serialize, Default, Debug, PartialEq)]
-pub struct DYExo {
+pub struct DYExo<'a> {
     pub name: String,
-    pub instruction: String,
+    /// Instructions can contain big code blocks, they are only joined when accessed
+    pub instruction: LazyText<'a>,
     pub checks: Vec<Check>,
+    /// Arbitrary metadata (author, version, ...) given in the `meta` key, not used by PLX
+    pub meta: Option<String>,
+}
+
+/// The version of the JSON given by DYExo::to_test_plan_json(), incremented on every change of the
+/// schema that could break a runner
+pub const TEST_PLAN_VERSION: u32 = 1;
+
+/// The test plan of an exo, the contract between the parser and a runner executing the checks
+#[derive(Serialize)]
+struct TestPlan<'a> {
+    version: u32,
+    exo: &'a str,
+    checks: &'a [Check],
+}
+
+impl<'a> DYExo<'a> {
+    /// Get an exo that doesn't borrow the parsed content anymore, the instruction is joined now
+    pub fn into_owned(self) -> DYExo<'static> {
+        DYExo {
+            name: self.name,
+            instruction: self.instruction.into_owned(),
+            checks: self.checks,
+            meta: self.meta,
+        }
+    }
+
+    /// The code snippets of the instruction with their language, like some starter code
+    pub fn code_blocks(&self) -> Vec<(Option<String>, String)> {
+        extract_code_blocks(self.instruction.get())
+    }
+
+    /// Export the checks of this exo as a JSON test plan for a runner, without the instruction.
+    /// The schema is `{ "version": TEST_PLAN_VERSION, "exo": name, "checks": [...] }` where each
+    /// check has the fields of Check (`name`, `args`, `args_raw`, `arg_values`, `exit`, `signal`,
+    /// `timeout` in milliseconds) and its `sequence` of actions in order, like `{ "See": "Hello" }`
+    /// or `{ "Type": "John" }`. Fields can be added in the same version, a runner must ignore the
+    /// unknown ones.
+    pub fn to_test_plan_json(&self) -> String {
+        serde_json::to_string_pretty(&TestPlan {
+            version: TEST_PLAN_VERSION,
+            exo: &self.name,
+            checks: &self.checks,
+        })
+        .expect("a test plan can always be serialized")
+    }
 }
 
 const ARGS_KEYSPEC: &KeySpec = &KeySpec {
     id: "args",
-    desc: "The command line arguments passed to the exo program, the space is used to split the list of arguments. No quotes or space inside argument is supported at the moment.",
-    // TODO: support a way to have arguments with space !
+    desc: "The command line arguments passed to the exo program, the space is used to split the list of arguments. Double quotes can be used to have spaces inside an argument, like `--name=\"John Doe\"`.",
     subkeys: &[],
     vt: ValueType::SingleLine,
-    once: true,
-    required: false,
+    max_occurs: Some(1),
+    ..KeySpec::DEFAULT
 };
 const SEE_KEYSPEC: &KeySpec = &KeySpec {
     id: "see",
-    desc: "The `see` assertion asserts that the standard output of the exo program contains the given text. Values around that text are permitted.",
+    desc: "The `see` assertion asserts that the standard output of the exo program contains the given text. Values around that text are permitted. A big expected text can be stored in a file given after `@`, like `see @expected.txt`.",
     subkeys: &[],
     vt: ValueType::Multiline,
-    once: false,
     required: true,
+    ..KeySpec::DEFAULT
 };
 const TYPE_KEYSPEC: &KeySpec = &KeySpec {
     id: "type",
     desc: "The `type` action simulate typing in the terminal and hitting enter. It inject the given text in the standard input at once after appending a `\\n` at the end of the text.",
     subkeys: &[],
     vt: ValueType::SingleLine, // we can only type a single line of text. The type value can be empty, it just means we type enter without anything before.
-    once: false,
-    required: false,
+    ..KeySpec::DEFAULT
+};
+const TYPERAW_KEYSPEC: &KeySpec = &KeySpec {
+    id: "typeraw",
+    desc: "The `typeraw` action is the same as `type` except that no `\\n` is appended at the end of the text, the enter key is not hit.",
+    subkeys: &[],
+    vt: ValueType::SingleLine,
+    ..KeySpec::DEFAULT
 };
 const EXIT_KEYSPEC: &KeySpec = &KeySpec {
     desc: "Assert the value of the exit code (also named exit status). By default, this is checked to be 0, you can define another value to assert the program has failed with a specific exit code.",
     id: "exit",
     subkeys: &[],
-    vt: ValueType::SingleLine,
-    once: true,
-    required: false,
+    vt: ValueType::Integer,
+    max_occurs: Some(1),
+    default: Some("0"),
+    ..KeySpec::DEFAULT
+};
+const SIGNAL_KEYSPEC: &KeySpec = &KeySpec {
+    id: "signal",
+    desc: "Assert the program has been killed by the given signal number (Unix only), like 11 for a SIGSEGV or 6 for a SIGABRT. This cannot be used with `exit`, as a killed program doesn't have an exit code.",
+    subkeys: &[],
+    vt: ValueType::Integer,
+    max_occurs: Some(1),
+    ..KeySpec::DEFAULT
+};
+const CHECK_TIMEOUT_KEYSPEC: &KeySpec = &KeySpec {
+    id: "check_timeout",
+    desc: "The maximum duration of the check, like `500ms`, `2s` or `1m`, the program is killed after that. This overrides the `timeout` defined in the `defaults` of the exo.",
+    subkeys: &[],
+    vt: ValueType::Duration,
+    max_occurs: Some(1),
+    ..KeySpec::DEFAULT
 };
 const CHECK_KEYSPEC: &KeySpec = &KeySpec {
     id: "check",
     desc: "Describe a `check`, which is a basic automated test.",
-    subkeys: &[ARGS_KEYSPEC, SEE_KEYSPEC, TYPE_KEYSPEC, EXIT_KEYSPEC],
+    subkeys: &[
+        ARGS_KEYSPEC,
+        SEE_KEYSPEC,
+        TYPE_KEYSPEC,
+        TYPERAW_KEYSPEC,
+        EXIT_KEYSPEC,
+        SIGNAL_KEYSPEC,
+        CHECK_TIMEOUT_KEYSPEC,
+    ],
     vt: ValueType::SingleLine,
-    once: false,
+    min_occurs: 1,
     required: true,
+    ..KeySpec::DEFAULT
+};
+const TIMEOUT_KEYSPEC: &KeySpec = &KeySpec {
+    id: "timeout",
+    desc: "The maximum duration of each check, like `500ms`, `2s` or `1m`, the program is killed after that.",
+    subkeys: &[],
+    vt: ValueType::Duration,
+    max_occurs: Some(1),
+    ..KeySpec::DEFAULT
+};
+const DEFAULTS_KEYSPEC: &KeySpec = &KeySpec {
+    id: "defaults",
+    desc: "Default values applied to all the checks of the exo, unless a check defines its own value.",
+    subkeys: &[TIMEOUT_KEYSPEC],
+    vt: ValueType::SingleLine,
+    max_occurs: Some(1),
+    ..KeySpec::DEFAULT
 };
 const EXO_KEYSPEC: &KeySpec = &KeySpec {
     id: "exo",
     desc: "Define a new exercise (exo is shortcut for exercise) with a name and optionnal instruction.",
-    subkeys: &[CHECK_KEYSPEC],
+    subkeys: &[CHECK_KEYSPEC, DEFAULTS_KEYSPEC],
     vt: ValueType::Multiline,
-    once: true, // for now, only one exo per file
+    max_occurs: Some(1), // for now, only one exo per file
     required: true,
+    ..KeySpec::DEFAULT
 };
 
-pub const EXO_SPEC: &DYSpec = &[EXO_KEYSPEC];
+const META_KEYSPEC: &KeySpec = &KeySpec {
+    id: "meta",
+    desc: "Arbitrary metadata about the exo like the author or a version, it doesn't impact the exo.",
+    subkeys: &[],
+    vt: ValueType::Multiline,
+    max_occurs: Some(1),
+    ..KeySpec::DEFAULT
+};
+
+pub const EXO_SPEC: &DYSpec = &[EXO_KEYSPEC, META_KEYSPEC];
+/// The validated EXO_SPEC, it lives for the whole program so the exos can borrow the content
+static VALID_EXO_SPEC: LazyLock<ValidDYSpec> =
+    LazyLock::new(|| ValidDYSpec::new(EXO_SPEC).expect("EXO_SPEC is invalid !"));
 
 // Error texts
-const ERROR_CANNOT_PARSE_EXIT_CODE: &str =
-    "Couldn't parse the given value as the program's exit code (signed 32bits integer)";
+const ERROR_TYPE_CONTAINS_NEWLINE: &str =
+    "The value of a `type` cannot contain a new line, as a new line is already typed at the end";
+const ERROR_CHECK_WITHOUT_NAME: &str = "A check must have a name";
+const ERROR_CHECK_WITHOUT_SEE: &str =
+    "A check must contain at least one `see` assertion to verify the program output";
+const ERROR_EMPTY_ARG_RANGE: &str =
+    "This range of arguments is empty, the start must be smaller than the end (excluded)";
+const ERROR_EMPTY_ARG: &str = "A check cannot have an empty argument";
+const ERROR_UNTERMINATED_QUOTE: &str =
+    "This quote is never closed, add the same quote at the end of the argument";
+const ERROR_EXIT_AND_SIGNAL_CONFLICT: &str = "A check cannot expect both an exit code and a signal, a program killed by a signal has no exit code";
 
-impl<'a> FromDYBlock<'a> for DYExo {
-    fn from_block_with_validation(block: &Block<'a>) -> (Vec<ParseError>, DYExo) {
+impl<'a> FromDYBlock<'a> for DYExo<'a> {
+    fn from_block_with_validation(block: &Block<'a>) -> (Vec<ParseError>, DYExo<'a>) {
         let mut errors = Vec::new();
-        let mut exo = DYExo::default();
-        // The first line is the name, the following ones are the description
-        (exo.name, exo.instruction) = block.get_text_with_joined_splits_at(1);
-        for exo_subblock in block.subblocks.iter() {
-            let id = exo_subblock.key.id;
-            if id == CHECK_KEYSPEC.id {
-                let mut check = Check {
-                    name: exo_subblock.get_joined_text(),
-                    ..Default::default()
-                };
-                for check_subblock in exo_subblock.subblocks.iter() {
-                    let check_subblock_id = check_subblock.key.id;
-                    if check_subblock_id == ARGS_KEYSPEC.id {
-                        let args_text = &check_subblock.get_joined_text();
-                        if args_text.is_empty() {
-                            errors.push(ParseError {
-                                // Note: the range is pointing just after the key as it's where the value need to come
-                                range: range_on_line_part(
+        // The first non empty line is the name, the following ones are the description
+        let name_index = block.name_line_index();
+        let mut exo = DYExo {
+            name: block.get_text_before(name_index + 1),
+            instruction: block.get_lazy_text_after(name_index + 1),
+            ..Default::default()
+        };
+        // The defaults must be known before parsing the checks, invalid durations are already
+        // reported by the parser
+        let default_timeout = block
+            .find_subblock(DEFAULTS_KEYSPEC.id)
+            .and_then(|defaults| defaults.find_subblock(TIMEOUT_KEYSPEC.id))
+            .and_then(|timeout| timeout.get_duration());
+        for check_block in block.subblocks_with(CHECK_KEYSPEC.id) {
+            let mut check = Check {
+                name: check_block.get_joined_text(),
+                ..Default::default()
+            };
+            for check_subblock in check_block.subblocks.iter() {
+                let check_subblock_id = check_subblock.key.id;
+                if check_subblock_id == ARGS_KEYSPEC.id {
+                    if check_subblock.text_is_empty() {
+                        errors.push(ParseError::new(
+                            // Note: the range is pointing just after the key as it's where the value need to come
+                            range_on_line_part(
+                                check_subblock.range.start.line,
+                                ARGS_KEYSPEC.id.len() as u32,
+                                ARGS_KEYSPEC.id.len() as u32,
+                            ),
+                            ParseErrorType::MissingRequiredValue(check_subblock_id.to_string()),
+                        ));
+                    } else {
+                        let raw = check_subblock
+                            .require_single_line()
+                            .unwrap_or_else(|error| {
+                                errors.push(*error);
+                                check_subblock.get_joined_text()
+                            });
+                        check.args = split_args_string(&raw).unwrap_or_else(|quote_index| {
+                            // The quote is searched in the value as written, the transformer
+                            // could have changed its position
+                            let value = check_subblock.text.first().copied().unwrap_or("");
+                            let trimmed = value.trim_start();
+                            let quote_index =
+                                split_args_string(trimmed).err().unwrap_or(quote_index);
+                            let leading_spaces = value.len() - trimmed.len();
+                            let start = check_subblock.key_len()
+                                + 1
+                                + (leading_spaces + quote_index) as u32;
+                            errors.push(ParseError::new(
+                                range_on_line_part(
                                     check_subblock.range.start.line,
-                                    ARGS_KEYSPEC.id.len() as u32,
-                                    ARGS_KEYSPEC.id.len() as u32,
+                                    start,
+                                    check_subblock.range.end.character.max(start),
                                 ),
-                                error: ParseErrorType::MissingRequiredValue(
-                                    check_subblock_id.to_string(),
+                                ParseErrorType::ValidationError(
+                                    ERROR_UNTERMINATED_QUOTE.to_string(),
                                 ),
-                            });
-                        } else {
-                            check.args = split_args_string(args_text);
+                            ));
+                            vec![]
+                        });
+                        check.arg_values =
+                            check.args.iter().map(|arg| parse_arg_value(arg)).collect();
+                        if check.arg_values.iter().any(
+                            |value| matches!(value, ArgValue::Range(start, end) if start >= end),
+                        ) {
+                            errors.push(ParseError::new(
+                                check_subblock.range,
+                                ParseErrorType::ValidationError(ERROR_EMPTY_ARG_RANGE.to_string()),
+                            ));
                         }
+                        check.args_raw = Some(raw);
                     }
-                    if check_subblock_id == EXIT_KEYSPEC.id {
-                        check.exit = None;
-                        match check_subblock.get_joined_text().parse::<i32>() {
-                            Ok(code) => check.exit = Some(code),
-                            Err(_) => {
-                                errors.push(ParseError {
-                                    range: range_on_line_part(
-                                        check_subblock.range.start.line,
-                                        check_subblock.range.start.character
-                                            + check_subblock_id.len() as u32
-                                            + 1,
-                                        check_subblock.range.end.character,
-                                    ),
-                                    error: ParseErrorType::ValidationError(
-                                        ERROR_CANNOT_PARSE_EXIT_CODE.to_string(),
-                                    ),
-                                });
-                            }
-                        }
+                }
+                if check_subblock_id == EXIT_KEYSPEC.id {
+                    // Invalid integers are already reported by the parser
+                    check.exit = check_subblock.value_as_i32().ok();
+                }
+                if check_subblock_id == SIGNAL_KEYSPEC.id {
+                    check.signal = check_subblock.value_as_i32().ok();
+                    if check_block.find_subblock(EXIT_KEYSPEC.id).is_some() {
+                        errors.push(ParseError::new(
+                            range_on_line_part(
+                                check_subblock.range.start.line,
+                                0,
+                                check_subblock.range.end.character,
+                            ),
+                            ParseErrorType::ValidationError(
+                                ERROR_EXIT_AND_SIGNAL_CONFLICT.to_string(),
+                            ),
+                        ));
                     }
-                    if check_subblock_id == TYPE_KEYSPEC.id {
-                        check
-                            .sequence
-                            .push(TermAction::Type(check_subblock.get_joined_text()));
+                }
+                if check_subblock_id == CHECK_TIMEOUT_KEYSPEC.id {
+                    check.timeout = check_subblock.get_duration();
+                }
+                if check_subblock_id == TYPE_KEYSPEC.id {
+                    let text = check_subblock.get_joined_text();
+                    if text.contains('\n') {
+                        errors.push(ParseError::new(
+                            check_subblock.range,
+                            ParseErrorType::ValidationError(
+                                ERROR_TYPE_CONTAINS_NEWLINE.to_string(),
+                            ),
+                        ));
                     }
-                    if check_subblock_id == SEE_KEYSPEC.id {
-                        check
-                            .sequence
-                            .push(TermAction::See(check_subblock.get_joined_text()));
+                    check.sequence.push(TermAction::Type(text));
+                }
+                if check_subblock_id == TYPERAW_KEYSPEC.id {
+                    check
+                        .sequence
+                        .push(TermAction::TypeRaw(check_subblock.get_joined_text()));
+                }
+                if check_subblock_id == SEE_KEYSPEC.id {
+                    let text = check_subblock.get_joined_text();
+                    match see_file_reference(&text) {
+                        Some(file) => check.sequence.push(TermAction::SeeFile(
+                            file.to_string(),
+                            check_subblock.value_range,
+                        )),
+                        None => check.sequence.push(see_action(text)),
                     }
                 }
-                exo.checks.push(check);
             }
+            // A program killed by a signal has no exit code, otherwise the default exit code applies
+            let has_exit = check_block.find_subblock(EXIT_KEYSPEC.id).is_some();
+            if !has_exit && check.signal.is_none() {
+                check.exit = check_block
+                    .get_subvalue_or_default(EXIT_KEYSPEC)
+                    .and_then(|value| value.parse().ok());
+            }
+            if check_block
+                .find_subblock(CHECK_TIMEOUT_KEYSPEC.id)
+                .is_none()
+            {
+                check.timeout = default_timeout;
+            }
+            exo.checks.push(check);
         }
         (errors, exo)
     }
 }
 
-// For now we only break on space, that's a bit limited if we need to have args that include space
-// in them. This will be fixed in the future when needed.
-fn split_args_string(line: &str) -> Vec<String> {
-    if line.is_empty() {
-        vec![]
+// Arguments are split on spaces like in a shell, except inside double or single quotes. Quotes
+// can be anywhere in an argument and are removed, so `--name="John Doe"` gives `--name=John Doe`.
+// A backslash escapes the next char (a quote, a space or a backslash), except inside single
+// quotes where it is kept as is. Several spaces are considered as one.
+// An unterminated quote gives the char index of the opening quote as error.
+fn split_args_string(line: &str) -> Result<Vec<String>, usize> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut inside_an_arg = false;
+    let mut opened_quote: Option<(char, usize)> = None;
+    let mut chars = line.chars().enumerate();
+    while let Some((index, c)) = chars.next() {
+        match (c, opened_quote) {
+            ('\\', None | Some(('"', _))) => {
+                inside_an_arg = true;
+                match chars.next() {
+                    Some((_, escaped)) => current.push(escaped),
+                    None => current.push(c),
+                }
+            }
+            ('"' | '\'', None) => {
+                opened_quote = Some((c, index));
+                inside_an_arg = true;
+            }
+            (_, Some((quote, _))) if c == quote => opened_quote = None,
+            (' ', None) => {
+                if inside_an_arg {
+                    args.push(std::mem::take(&mut current));
+                    inside_an_arg = false;
+                }
+            }
+            _ => {
+                current.push(c);
+                inside_an_arg = true;
+            }
+        }
+    }
+    if let Some((_, index)) = opened_quote {
+        return Err(index);
+    }
+    if inside_an_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+fn serialize_millis<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    duration.map(|d| d.as_millis() as u64).serialize(serializer)
+}
+
+/// Only the file of a SeeFile is serialized, as it was before its range was kept
+fn serialize_see_file<S: Serializer>(
+    file: &str,
+    _range: &Range,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    file.serialize(serializer)
+}
+
+/// Get the file of a `see` value referencing a file like `@expected.txt`
+pub(crate) fn see_file_reference(text: &str) -> Option<&str> {
+    text.strip_prefix('@')
+        .filter(|file| !file.is_empty() && !file.contains(char::is_whitespace))
+}
+
+/// A See for a single line text, a SeeBlock for several lines
+pub(crate) fn see_action(text: String) -> TermAction {
+    if text.contains('\n') {
+        TermAction::SeeBlock(text.lines().map(|l| l.to_string()).collect())
+    } else {
+        TermAction::See(text)
+    }
+}
+
+/// Parse an argument as a range if it looks like `N..M` with two integers, as a literal otherwise
+fn parse_arg_value(arg: &str) -> ArgValue {
+    if let Some((start, end)) = arg.split_once("..")
+        && let (Ok(start), Ok(end)) = (start.parse::<i32>(), end.parse::<i32>())
+    {
+        return ArgValue::Range(start, end);
+    }
+    ArgValue::Literal(arg.to_string())
+}
+
+/// Find the `check` block of the given exo block that contains the given line, the subblocks of
+/// the check are included. Useful to map a failing check back to its source.
+pub fn check_at_line<'b, 'a>(exo_block: &'b Block<'a>, line: u32) -> Option<&'b Block<'a>> {
+    exo_block.subblocks_with(CHECK_KEYSPEC.id).find(|b| {
+        let range = b.full_range();
+        range.start.line <= line && line <= range.end.line
+    })
+}
+
+/// Merge adjacent See and SeeBlock actions into a single SeeBlock, in the same order
+fn coalesce_sees(sequence: Vec<TermAction>) -> Vec<TermAction> {
+    let mut coalesced: Vec<TermAction> = Vec::with_capacity(sequence.len());
+    for action in sequence {
+        let lines = match action {
+            TermAction::See(text) => vec![text],
+            TermAction::SeeBlock(lines) => lines,
+            TermAction::Type(_) | TermAction::TypeRaw(_) | TermAction::SeeFile(..) => {
+                coalesced.push(action);
+                continue;
+            }
+        };
+        match coalesced.pop() {
+            Some(TermAction::See(previous)) => {
+                coalesced.push(TermAction::SeeBlock([vec![previous], lines].concat()))
+            }
+            Some(TermAction::SeeBlock(previous)) => {
+                coalesced.push(TermAction::SeeBlock([previous, lines].concat()))
+            }
+            previous => {
+                coalesced.extend(previous);
+                coalesced.push(match lines.len() {
+                    1 => TermAction::See(lines.into_iter().next().unwrap_or_default()),
+                    _ => TermAction::SeeBlock(lines),
+                });
+            }
+        }
+    }
+    coalesced
+}
+
+/// A top-level block of an exo file, the meta block is given to the exo once parsed. The
+/// WeakErrorCheck warnings are only generated if WARN_WEAK_ERROR_CHECKS is true, see
+/// ExoParseOptions::warn_weak_error_checks.
+enum ExoFileBlock<'a, const WARN_WEAK_ERROR_CHECKS: bool> {
+    Exo(DYExo<'a>),
+    Meta(String),
+}
+
+impl<'a, const WARN_WEAK_ERROR_CHECKS: bool> FromDYBlock<'a>
+    for ExoFileBlock<'a, WARN_WEAK_ERROR_CHECKS>
+{
+    fn from_block_with_validation(block: &Block<'a>) -> (Vec<ParseError>, Self) {
+        if block.key.id == META_KEYSPEC.id {
+            (Vec::new(), ExoFileBlock::Meta(block.require_multiline()))
+        } else {
+            let (mut errors, exo) = DYExo::from_block_with_validation(block);
+            if !WARN_WEAK_ERROR_CHECKS {
+                return (errors, ExoFileBlock::Exo(exo));
+            }
+            for (check_block, check) in block.subblocks_with(CHECK_KEYSPEC.id).zip(&exo.checks) {
+                let has_see = check.sequence.iter().any(|action| {
+                    matches!(
+                        action,
+                        TermAction::See(_) | TermAction::SeeBlock(_) | TermAction::SeeFile(..)
+                    )
+                });
+                // A check expecting a signal or with an invalid exit code has no exit of 0 either
+                if check.exit != Some(0) && !has_see {
+                    errors.push(ParseError::new(
+                        check_block.find_subblock(EXIT_KEYSPEC.id).map_or(
+                            range_on_line_part(
+                                check_block.range.start.line,
+                                0,
+                                CHECK_KEYSPEC.id.len() as u32,
+                            ),
+                            |exit_block| exit_block.range,
+                        ),
+                        ParseErrorType::WeakErrorCheck,
+                    ));
+                }
+            }
+            (errors, ExoFileBlock::Exo(exo))
+        }
+    }
+}
+
+/// Options to change how exos are built, everything is disabled by default
+#[derive(Debug, Default, Clone)]
+pub struct ExoParseOptions {
+    /// Merge adjacent `see` actions (not separated by a `type`) of a check into a single SeeBlock
+    pub coalesce_sees: bool,
+    /// Generate WeakErrorCheck warnings on checks not expecting an exit code of 0 (including the
+    /// checks expecting a signal) whose sequence has no `see`, as only the exit code is asserted
+    /// and not the error message
+    pub warn_weak_error_checks: bool,
+}
+
+pub fn parse_exo<'a>(some_file: &Option<String>, content: &'a str) -> ParseResult<DYExo<'a>> {
+    parse_exo_with_options(some_file, content, &ExoParseOptions::default())
+}
+
+/// Same as parse_exo() but with the given options
+pub fn parse_exo_with_options<'a>(
+    some_file: &Option<String>,
+    content: &'a str,
+    options: &ExoParseOptions,
+) -> ParseResult<DYExo<'a>> {
+    if options.warn_weak_error_checks {
+        parse_exo_file::<true>(some_file, content, options)
     } else {
-        line.split(' ')
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>()
+        parse_exo_file::<false>(some_file, content, options)
     }
 }
 
-pub fn parse_exo(some_file: &Option<String>, content: &str) -> ParseResult<DYExo> {
-    parse_with_spec(
-        &ValidDYSpec::new(EXO_SPEC).expect("EXO_SPEC is invalid !"),
+fn parse_exo_file<'a, const WARN_WEAK_ERROR_CHECKS: bool>(
+    some_file: &Option<String>,
+    content: &'a str,
+    options: &ExoParseOptions,
+) -> ParseResult<DYExo<'a>> {
+    let result = parse_with_spec::<ExoFileBlock<'a, WARN_WEAK_ERROR_CHECKS>>(
+        &VALID_EXO_SPEC,
         some_file,
         content,
-    )
+    );
+    let meta = result.items.iter().find_map(|item| match item {
+        ExoFileBlock::Meta(meta) => Some(meta.clone()),
+        ExoFileBlock::Exo(_) => None,
+    });
+    ParseResult {
+        items: result
+            .items
+            .into_iter()
+            .filter_map(|item| match item {
+                ExoFileBlock::Exo(mut exo) => {
+                    if options.coalesce_sees {
+                        for check in exo.checks.iter_mut() {
+                            check.sequence = coalesce_sees(std::mem::take(&mut check.sequence));
+                        }
+                    }
+                    Some(DYExo {
+                        meta: meta.clone(),
+                        ..exo
+                    })
+                }
+                ExoFileBlock::Meta(_) => None,
+            })
+            .collect(),
+        some_file_content: result.some_file_content,
+        errors: result.errors,
+        some_file_path: result.some_file_path,
+        doc_comments: result.doc_comments,
+    }
 }
 
 #[cfg(test)]
@@ -192,13 +702,24 @@ mod tests {
         range_on_line_part,
     };
 
-    use crate::exo::{Check, DYExo, ERROR_CANNOT_PARSE_EXIT_CODE, TermAction, parse_exo};
+    use dy::{ParseOptions, generic::DyValue, parse_with_options, parse_with_spec};
+
+    use dy::{
+        FromDYBlock, lexer::tokenize_into_lines, parser::Block, parser::build_blocks_tree,
+        range_on_line_with_length, range_on_lines, spec::ValidDYSpec,
+    };
+
+    use crate::exo::{
+        ArgValue, CHECK_KEYSPEC, Check, DYExo, ERROR_CHECK_WITHOUT_SEE, ERROR_EMPTY_ARG_RANGE,
+        ERROR_EXIT_AND_SIGNAL_CONFLICT, ERROR_TYPE_CONTAINS_NEWLINE, ERROR_UNTERMINATED_QUOTE,
+        EXO_KEYSPEC, EXO_SPEC, ExoParseOptions, TEST_PLAN_VERSION, TYPE_KEYSPEC, TermAction,
+        check_at_line, parse_exo, parse_exo_with_options, split_args_string,
+    };
 
     use pretty_assertions::assert_eq;
+    use std::time::Duration;
 
-    #[test]
-    fn test_can_parse_a_simple_exo() {
-        let text = "
+    const SIMPLE_EXO_TEXT: &str = "
 // the basic just greet me exo !
 exo Just greet me
 A simple hello program that **asks your firstname and lastname and greets you**.
@@ -222,20 +743,30 @@ type John23
 see This doesn't look like a firstname...
 exit 2
 ";
+
+    #[test]
+    fn test_can_parse_a_simple_exo() {
+        let text = SIMPLE_EXO_TEXT;
         let some_file = &Some("exo.dy".to_string());
         assert_eq!(
             parse_exo(some_file, text),
             ParseResult {
                 some_file_path: some_file.clone(),
                 some_file_content: None,
+                doc_comments: vec![],
                 items: vec![DYExo {
                     name: "Just greet me".to_string(),
-                    instruction: "A simple hello program that **asks your firstname and lastname and greets you**.\nMake sure to validate firstname and lastname content. They must contain only A-Z and a-z chars. \nDo not use a regex. Try to avoid repeating the validation logic.\n\nThe goal is to train input/output with `printf` and `scanf`.".to_string(),
+                    instruction: "A simple hello program that **asks your firstname and lastname and greets you**.\nMake sure to validate firstname and lastname content. They must contain only A-Z and a-z chars. \nDo not use a regex. Try to avoid repeating the validation logic.\n\nThe goal is to train input/output with `printf` and `scanf`.".into(),
+                    meta: None,
                     checks: vec![
                         Check {
                             name: "Can enter the full name and be greeted".to_string(),
                             args: vec!["kinda".to_string(),],
+                            args_raw: Some("kinda".to_string()),
+                            arg_values: vec![ArgValue::Literal("kinda".to_string())],
                             exit: Some(0,),
+                            signal: None,
+                            timeout: None,
                             sequence: vec![
                                 TermAction::See("What is your firstname ?".to_string(),),
                                 TermAction::Type("John".to_string(),),
@@ -247,7 +778,11 @@ exit 2
                         Check {
                             name: "It validates the firstname text".to_string(),
                             args: vec![],
+                            args_raw: None,
+                            arg_values: vec![],
                             exit: Some(2,),
+                            signal: None,
+                            timeout: None,
                             sequence: vec![
                                 TermAction::See("What is your firstname ?".to_string(),),
                                 TermAction::Type("John23".to_string(),),
@@ -261,6 +796,463 @@ exit 2
         )
     }
 
+    #[test]
+    fn test_name_can_be_on_the_line_after_the_key() {
+        let text = "exo
+
+Just greet me
+The instruction
+
+check it greets
+see Hello
+";
+        let result = parse_exo(&None, text);
+        assert_eq!(result.errors, vec![]);
+        assert_eq!(result.items[0].name, "Just greet me");
+        assert_eq!(result.items[0].instruction.get(), "The instruction");
+    }
+
+    #[test]
+    fn test_can_export_a_test_plan() {
+        let result = parse_exo(&None, SIMPLE_EXO_TEXT);
+        let plan: serde_json::Value =
+            serde_json::from_str(&result.items[0].to_test_plan_json()).unwrap();
+        assert_eq!(plan["version"], TEST_PLAN_VERSION);
+        assert_eq!(plan["exo"], "Just greet me");
+        assert_eq!(plan["checks"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            plan["checks"][0]["name"],
+            "Can enter the full name and be greeted"
+        );
+        assert_eq!(plan["checks"][0]["args"], serde_json::json!(["kinda"]));
+        assert_eq!(plan["checks"][0]["exit"], 0);
+        assert_eq!(
+            plan["checks"][0]["sequence"],
+            serde_json::json!([
+                { "See": "What is your firstname ?" },
+                { "Type": "John" },
+                { "See": "Hello John, what's your lastname ?" },
+                { "Type": "Doe" },
+                { "See": "Have a nice day John Doe !" }
+            ])
+        );
+        assert_eq!(
+            plan["checks"][1]["sequence"],
+            serde_json::json!([
+                { "See": "What is your firstname ?" },
+                { "Type": "John23" },
+                { "See": "This doesn't look like a firstname..." }
+            ])
+        );
+        assert!(plan.get("instruction").is_none());
+    }
+
+    #[test]
+    fn test_instruction_is_only_joined_when_accessed() {
+        let mut text = String::from("exo Big code\n```c\n");
+        for i in 0..1000 {
+            text.push_str(&format!("int a{i} = {i};\n"));
+        }
+        text.push_str("```\ncheck it compiles\nsee done\n");
+        let result = parse_exo(&None, &text);
+        assert_eq!(result.errors, vec![]);
+        assert!(!result.items[0].instruction.is_joined());
+        let instruction = result.items[0].instruction.get();
+        assert!(result.items[0].instruction.is_joined());
+        assert!(instruction.starts_with("```c\nint a0 = 0;\nint a1 = 1;\n"));
+        assert!(instruction.ends_with("int a999 = 999;\n```"));
+
+        let options = ParseOptions {
+            transformer: Some(|text| text.replace("int", "long")),
+            ..Default::default()
+        };
+        let spec = ValidDYSpec::new(EXO_SPEC).unwrap();
+        let result: ParseResult<DYExo> = parse_with_options(&spec, &None, &text, &options);
+        let exo = &result.items[0];
+        assert!(!exo.instruction.is_joined());
+        let instruction = exo.instruction.get();
+        assert!(exo.instruction.is_joined());
+        assert!(instruction.starts_with("```c\nlong a0 = 0;\nlong a1 = 1;\n"));
+    }
+
+    #[test]
+    fn test_transformer_is_applied_to_the_name_and_the_instruction() {
+        let options = ParseOptions {
+            transformer: Some(|text| text.replace("${lang}", "C")),
+            ..Default::default()
+        };
+        let spec = ValidDYSpec::new(EXO_SPEC).unwrap();
+        let result: ParseResult<DYExo> = parse_with_options(
+            &spec,
+            &None,
+            "exo Hello in ${lang}\nWrite it in ${lang}\ncheck it works\nsee Hello",
+            &options,
+        );
+        assert_eq!(result.errors, vec![]);
+        assert_eq!(result.items[0].name, "Hello in C");
+        assert_eq!(result.items[0].instruction.get(), "Write it in C");
+    }
+
+    #[test]
+    fn test_can_find_the_check_at_a_given_line() {
+        let binding = ValidDYSpec::new(EXO_SPEC).unwrap();
+        let lines = tokenize_into_lines(&binding, SIMPLE_EXO_TEXT);
+        let (blocks, errors) = build_blocks_tree(&binding, lines);
+        assert_eq!(errors, vec![]);
+        let exo_block = &blocks[0];
+        // A line in the middle of the second check
+        let check = check_at_line(exo_block, 20).unwrap();
+        assert_eq!(check.get_joined_text(), "It validates the firstname text");
+        // The check line itself
+        let check = check_at_line(exo_block, 9).unwrap();
+        assert_eq!(
+            check.get_joined_text(),
+            "Can enter the full name and be greeted"
+        );
+        // A line in the instruction
+        assert_eq!(check_at_line(exo_block, 4), None);
+    }
+
+    #[test]
+    fn test_check_without_exit_expects_the_default_exit_code() {
+        let text = "exo test
+check test
+see hello
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(parse_result.errors, vec![]);
+        assert_eq!(parse_result.items[0].checks[0].exit, Some(0));
+    }
+
+    #[test]
+    fn test_explicit_and_implicit_exit_codes_are_the_same() {
+        let text = "exo test
+check explicit
+see hello
+exit 0
+check implicit
+see hello
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(parse_result.errors, vec![]);
+        let checks = &parse_result.items[0].checks;
+        assert_eq!(checks[0].exit, Some(0));
+        assert_eq!(checks[0].exit, checks[1].exit);
+    }
+
+    #[test]
+    fn test_default_timeout_applies_to_checks_without_their_own() {
+        let text = "exo test
+defaults
+timeout 2s
+check uses the default
+see hello
+check is slower
+see hello
+check_timeout 1m
+check is faster
+see hello
+check_timeout 500ms
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(parse_result.errors, vec![]);
+        let exo = &parse_result.items[0];
+        assert_eq!(exo.checks[0].timeout, Some(Duration::from_secs(2)));
+        assert_eq!(exo.checks[1].timeout, Some(Duration::from_secs(60)));
+        assert_eq!(exo.checks[2].timeout, Some(Duration::from_millis(500)));
+        let plan: serde_json::Value = serde_json::from_str(&exo.to_test_plan_json()).unwrap();
+        assert_eq!(plan["checks"][2]["timeout"], 500);
+
+        let parse_result = parse_exo(&None, "exo test\ncheck test\nsee hello\ncheck_timeout 2000");
+        assert_eq!(
+            parse_result.errors,
+            vec![ParseError::new(
+                range_on_line_part(3, 14, 18),
+                ParseErrorType::InvalidDuration("2000".to_string()),
+            )]
+        );
+        assert_eq!(parse_result.items[0].checks[0].timeout, None);
+    }
+
+    #[test]
+    fn test_failing_check_without_any_see_can_be_reported() {
+        let text = "exo test
+check invalid firstname
+type 123
+exit 2
+check invalid firstname with the message
+see What is your firstname ?
+type 123
+exit 2
+check killed
+type 123
+signal 9
+check valid firstname
+see What is your firstname ?
+type John
+";
+        let options = ExoParseOptions {
+            warn_weak_error_checks: true,
+            ..Default::default()
+        };
+        let weak_error_checks = |options: &ExoParseOptions| -> Vec<ParseError> {
+            parse_exo_with_options(&None, text, options)
+                .errors
+                .into_iter()
+                .filter(|error| error.error == ParseErrorType::WeakErrorCheck)
+                .collect()
+        };
+        // Off by default
+        assert_eq!(weak_error_checks(&ExoParseOptions::default()), vec![]);
+        // A check with a see is not reported, even if the see is before the failure
+        assert_eq!(
+            weak_error_checks(&options),
+            vec![
+                ParseError::new(
+                    range_on_line_with_length(3, 6),
+                    ParseErrorType::WeakErrorCheck,
+                ),
+                ParseError::new(range_on_line_part(8, 0, 5), ParseErrorType::WeakErrorCheck,),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_adjacent_sees_can_be_coalesced() {
+        let text = "exo test
+check test
+see hello
+see world
+type John
+see bye
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(
+            parse_result.items[0].checks[0].sequence,
+            vec![
+                TermAction::See("hello".to_string()),
+                TermAction::See("world".to_string()),
+                TermAction::Type("John".to_string()),
+                TermAction::See("bye".to_string()),
+            ]
+        );
+        let options = ExoParseOptions {
+            coalesce_sees: true,
+            ..Default::default()
+        };
+        let parse_result = parse_exo_with_options(&None, text, &options);
+        assert_eq!(
+            parse_result.items[0].checks[0].sequence,
+            vec![
+                TermAction::SeeBlock(vec!["hello".to_string(), "world".to_string()]),
+                TermAction::Type("John".to_string()),
+                TermAction::See("bye".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_actions_are_numbered_by_kind() {
+        let text = "exo test
+check test
+see Name
+type John
+see Hello John
+typeraw y
+see @bye.txt
+";
+        let parse_result = parse_exo(&None, text);
+        let check = &parse_result.items[0].checks[0];
+        assert_eq!(check.describe_action(0), "see #1: Name");
+        assert_eq!(check.describe_action(2), "see #2: Hello John");
+        assert_eq!(check.describe_action(3), "type #2: y (without new line)");
+        assert_eq!(check.describe_action(4), "see #3: @bye.txt");
+        assert_eq!(check.describe_action(5), "no action at index 5");
+    }
+
+    #[test]
+    fn test_typeraw_does_not_change_type() {
+        let text = "exo test
+check test
+type John
+typeraw y
+see bye
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(parse_result.errors, vec![]);
+        assert_eq!(
+            parse_result.items[0].checks[0].sequence,
+            vec![
+                TermAction::Type("John".to_string()),
+                TermAction::TypeRaw("y".to_string()),
+                TermAction::See("bye".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_args_can_contain_ranges() {
+        let text = "exo test
+check ranges
+args 1..3 foo
+see hello
+check literal
+args foo
+see hello
+check inverted
+args 10..1
+see hello
+";
+        let parse_result = parse_exo(&None, text);
+        let checks = &parse_result.items[0].checks;
+        assert_eq!(
+            checks[0].arg_values,
+            vec![ArgValue::Range(1, 3), ArgValue::Literal("foo".to_string())]
+        );
+        assert_eq!(checks[0].args, vec!["1..3".to_string(), "foo".to_string()]);
+        assert_eq!(
+            checks[1].arg_values,
+            vec![ArgValue::Literal("foo".to_string())]
+        );
+        assert_eq!(
+            parse_result.errors,
+            vec![ParseError::new(
+                range_on_line_part(8, 0, 10),
+                ParseErrorType::ValidationError(ERROR_EMPTY_ARG_RANGE.to_string()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_block_comment_can_disable_a_check() {
+        let text = "exo test
+check kept
+see hello
+/*
+check disabled
+see bye
+*/
+check also kept
+see hello
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(parse_result.errors, vec![]);
+        let names: Vec<&str> = parse_result.items[0]
+            .checks
+            .iter()
+            .map(|check| check.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["kept", "also kept"]);
+
+        let parse_result = parse_exo(&None, "exo test\ncheck kept\nsee hello\n/*\ncheck disabled");
+        assert_eq!(
+            parse_result.errors,
+            vec![ParseError::new(
+                range_on_line_part(3, 0, 2),
+                ParseErrorType::UnterminatedBlockComment,
+            )]
+        );
+    }
+
+    #[test]
+    fn test_code_blocks_are_extracted_from_instruction() {
+        let text = "exo Hello
+Write a program greeting the user, starting from
+```rust
+fn main() {
+}
+```
+A C++ version is also possible
+```cpp
+int main() {}
+```
+check test
+see hello
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(
+            parse_result.items[0].code_blocks(),
+            vec![
+                (Some("rust".to_string()), "fn main() {\n}".to_string()),
+                (Some("cpp".to_string()), "int main() {}".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exo_spec_can_be_exported_and_imported_as_json() {
+        let native = ValidDYSpec::new(EXO_SPEC).unwrap();
+        let owned_spec = ValidDYSpec::from_schema_json(&native.to_schema_json()).unwrap();
+        let imported = owned_spec.spec();
+        let text = "exo Just greet me
+Write a program that greets
+check it greets
+args John
+type Doe
+see Hello John Doe
+exit 3
+check invalid
+exit abc
+see bye
+see bye again
+meta some meta
+";
+        let native_result: ParseResult<DyValue> = parse_with_spec(&native, &None, text);
+        let imported_result: ParseResult<DyValue> = parse_with_spec(imported, &None, text);
+        assert_eq!(native_result, imported_result);
+        assert_eq!(native_result.items.len(), 2);
+    }
+
+    #[test]
+    fn test_exo_spec_can_be_exported_and_imported_as_yaml() {
+        let native = ValidDYSpec::new(EXO_SPEC).unwrap();
+        let owned_spec = ValidDYSpec::from_schema_yaml(&native.to_schema_yaml()).unwrap();
+        let imported = owned_spec.spec();
+        assert_eq!(imported.to_owned_spec(), native.to_owned_spec());
+        let text = "exo Just greet me
+Write a program that greets
+defaults
+timeout 2s
+check it greets
+args John
+type Doe
+see Hello John Doe
+exit 3
+check invalid
+exit abc
+see bye
+";
+        let native_result: ParseResult<DyValue> = parse_with_spec(&native, &None, text);
+        let imported_result: ParseResult<DyValue> = parse_with_spec(imported, &None, text);
+        assert_eq!(native_result, imported_result);
+        assert_eq!(native_result.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_check_can_be_validated_alone() {
+        let mut check = Check {
+            name: "it greets".to_string(),
+            args: vec!["John".to_string()],
+            exit: Some(0),
+            sequence: vec![
+                TermAction::Type("John".to_string()),
+                TermAction::See("Hello John".to_string()),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(check.validate(), Vec::<String>::new());
+
+        check.sequence.pop();
+        check.signal = Some(11);
+        assert_eq!(
+            check.validate(),
+            vec![
+                ERROR_CHECK_WITHOUT_SEE.to_string(),
+                ERROR_EXIT_AND_SIGNAL_CONFLICT.to_string()
+            ]
+        );
+    }
+
     #[test]
     fn test_can_error_on_invalid_exit_code() {
         let text = "exo thing
@@ -274,22 +1266,26 @@ exit blabla
             ParseResult {
                 some_file_path: some_file.clone(),
                 some_file_content: Some(text.to_string()),
+                doc_comments: vec![],
                 items: vec![DYExo {
                     name: "thing".to_string(),
-                    instruction: "".to_string(),
+                    instruction: "".into(),
+                    meta: None,
                     checks: vec![Check {
                         name: "test".to_string(),
                         args: vec![],
+                        args_raw: None,
+                        arg_values: vec![],
                         exit: None,
+                        signal: None,
+                        timeout: None,
                         sequence: vec![TermAction::See("hello".to_string())],
                     }]
                 }],
-                errors: vec![ParseError {
-                    range: range_on_line_part(3, 5, 11),
-                    error: ParseErrorType::ValidationError(
-                        ERROR_CANNOT_PARSE_EXIT_CODE.to_string()
-                    )
-                }]
+                errors: vec![ParseError::new(
+                    range_on_line_part(3, 5, 11),
+                    ParseErrorType::InvalidInteger("blabla".to_string()),
+                )]
             }
         )
     }
@@ -307,9 +1303,11 @@ see hello
             ParseResult {
                 some_file_path: some_file.clone(),
                 some_file_content: None,
+                doc_comments: vec![],
                 items: vec![DYExo {
                     name: "test".to_string(),
-                    instruction: "".to_string(),
+                    instruction: "".into(),
+                    meta: None,
                     checks: vec![Check {
                         name: "test".to_string(),
                         args: vec![
@@ -319,7 +1317,13 @@ see hello
                             "hey".to_string(),
                             "there".to_string()
                         ],
-                        exit: None,
+                        args_raw: Some("1 2 3 hey there".to_string()),
+                        arg_values: ["1", "2", "3", "hey", "there"]
+                            .map(|arg| ArgValue::Literal(arg.to_string()))
+                            .to_vec(),
+                        exit: Some(0),
+                        signal: None,
+                        timeout: None,
                         sequence: vec![TermAction::See("hello".to_string(),),],
                     },],
                 }],
@@ -328,6 +1332,101 @@ see hello
         )
     }
 
+    #[test]
+    fn test_args_keep_their_raw_form() {
+        let text = "exo test
+check test
+args  1   2
+see hello
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(parse_result.errors, vec![]);
+        let check = &parse_result.items[0].checks[0];
+        assert_eq!(check.args, vec!["1".to_string(), "2".to_string()]);
+        assert_eq!(check.args_raw, Some("1   2".to_string()));
+    }
+
+    #[test]
+    fn test_args_can_contain_quoted_values_with_equals() {
+        let text = "exo test
+check test
+args --name=\"John Doe\" -v
+see hello
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(parse_result.errors, vec![]);
+        assert_eq!(
+            parse_result.items[0].checks[0].args,
+            vec!["--name=John Doe".to_string(), "-v".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_can_split_args_with_quotes_and_escapes() {
+        assert_eq!(split_args_string(""), Ok(vec![]));
+        assert_eq!(split_args_string("   "), Ok(vec![]));
+        assert_eq!(
+            split_args_string("--name \"John Doe\" 'a b'"),
+            Ok(vec![
+                "--name".to_string(),
+                "John Doe".to_string(),
+                "a b".to_string()
+            ])
+        );
+        assert_eq!(
+            split_args_string("\"it's\" 'say \"hi\"'"),
+            Ok(vec!["it's".to_string(), "say \"hi\"".to_string()])
+        );
+        assert_eq!(
+            split_args_string("a\\ b \\\"quoted\\\" \"in \\\"double\\\"\" 'in \\ single'"),
+            Ok(vec![
+                "a b".to_string(),
+                "\"quoted\"".to_string(),
+                "in \"double\"".to_string(),
+                "in \\ single".to_string()
+            ])
+        );
+        assert_eq!(split_args_string("''"), Ok(vec!["".to_string()]));
+        assert_eq!(split_args_string("ok \"not closed"), Err(3));
+        assert_eq!(
+            split_args_string("ok 'not \\' closed"),
+            Ok(vec![
+                "ok".to_string(),
+                "not \\".to_string(),
+                "closed".to_string()
+            ])
+        );
+        assert_eq!(split_args_string("ok 'not closed\""), Err(3));
+    }
+
+    #[test]
+    fn test_unterminated_quote_in_args_gives_an_error() {
+        let text = "exo test
+check test
+args --name \"John Doe
+see hello
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(
+            parse_result.errors,
+            vec![ParseError::new(
+                range_on_line_part(2, 12, 21),
+                ParseErrorType::ValidationError(ERROR_UNTERMINATED_QUOTE.to_string()),
+            )]
+        );
+        assert_eq!(parse_result.items[0].checks[0].args, Vec::<String>::new());
+
+        // The trailing spaces are not part of the value but are in the range of the line
+        let parse_result = parse_exo(&None, "exo test\ncheck test\nargs a \"bc   \nsee hello\n");
+        assert_eq!(
+            parse_result.errors,
+            vec![ParseError::new(
+                range_on_line_part(2, 7, 13),
+                ParseErrorType::ValidationError(ERROR_UNTERMINATED_QUOTE.to_string()),
+            )]
+        );
+    }
+
     #[test]
     fn test_detect_empty_args_error_but_ignores_empty_type() {
         let text = "exo test
@@ -344,24 +1443,159 @@ type
             ParseResult {
                 some_file_path: some_file.clone(),
                 some_file_content: Some(text.to_string()),
+                doc_comments: vec![],
                 items: vec![DYExo {
                     name: "test".to_string(),
-                    instruction: "".to_string(),
+                    instruction: "".into(),
+                    meta: None,
                     checks: vec![Check {
                         name: "test".to_string(),
                         args: vec![],
-                        exit: None,
+                        args_raw: None,
+                        arg_values: vec![],
+                        exit: Some(0),
+                        signal: None,
+                        timeout: None,
                         sequence: vec![
                             TermAction::See("hello".to_string(),),
                             TermAction::Type("".to_string())
                         ],
                     },],
                 }],
-                errors: vec![ParseError {
-                    range: range_on_line_part(4, 4, 4),
-                    error: ParseErrorType::MissingRequiredValue("args".to_string()),
-                }]
+                errors: vec![ParseError::new(
+                    range_on_line_part(4, 4, 4),
+                    ParseErrorType::MissingRequiredValue("args".to_string()),
+                )]
             }
         )
     }
+
+    #[test]
+    fn test_multiline_see_generates_a_see_block() {
+        let text = "exo test
+check test
+see Hello
+  world !
+
+type yes
+see single line
+";
+        let some_file = &Some("exo.dy".to_string());
+        assert_eq!(
+            parse_exo(some_file, text).items[0].checks[0].sequence,
+            vec![
+                TermAction::SeeBlock(vec!["Hello".to_string(), "  world !".to_string()]),
+                TermAction::Type("yes".to_string()),
+                TermAction::See("single line".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_can_expect_a_signal() {
+        let text = "exo test
+check it crashes
+see hello
+signal 11
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(parse_result.errors, vec![]);
+        assert_eq!(parse_result.items[0].checks[0].signal, Some(11));
+        assert_eq!(parse_result.items[0].checks[0].exit, None);
+    }
+
+    #[test]
+    fn test_cannot_expect_both_exit_and_signal() {
+        let text = "exo test
+check it crashes
+see hello
+exit 1
+signal 6
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(
+            parse_result.errors,
+            vec![ParseError::new(
+                range_on_line_part(4, 0, 8),
+                ParseErrorType::ValidationError(ERROR_EXIT_AND_SIGNAL_CONFLICT.to_string()),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_meta_block_is_attached_to_the_exo() {
+        let text = "meta author: John
+version: 2
+
+exo test
+check test
+see hello
+";
+        let parse_result = parse_exo(&None, text);
+        assert_eq!(parse_result.errors, vec![]);
+        assert_eq!(parse_result.items.len(), 1);
+        assert_eq!(parse_result.items[0].name, "test");
+        assert_eq!(
+            parse_result.items[0].meta,
+            Some("author: John\nversion: 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_type_value_cannot_contain_newlines() {
+        // The parser never generates a multiline type as it's a single line key, so the block is built by hand
+        let type_block = |text, range| Block {
+            key: TYPE_KEYSPEC,
+            matched_key_text: "type",
+            transformer: None,
+            synthetic: false,
+            incomplete: false,
+            path_id: String::new(),
+            value_range: range,
+            raw: vec![],
+            text,
+            range,
+            subblocks: vec![],
+        };
+        let exo_block = Block {
+            key: EXO_KEYSPEC,
+            matched_key_text: "exo",
+            transformer: None,
+            synthetic: false,
+            incomplete: false,
+            path_id: String::new(),
+            value_range: range_on_line_part(0, 4, 8),
+            raw: vec![],
+            text: vec!["test"],
+            range: range_on_line_with_length(0, 8),
+            subblocks: vec![Block {
+                key: CHECK_KEYSPEC,
+                matched_key_text: "check",
+                transformer: None,
+                synthetic: false,
+                incomplete: false,
+                path_id: String::new(),
+                value_range: range_on_line_part(1, 6, 10),
+                raw: vec![],
+                text: vec!["test"],
+                range: range_on_line_with_length(1, 10),
+                subblocks: vec![
+                    type_block(vec!["John"], range_on_line_with_length(2, 9)),
+                    type_block(vec!["John", "Doe"], range_on_lines(3, 4, 3)),
+                ],
+            }],
+        };
+        let (errors, exo) = DYExo::from_block_with_validation(&exo_block);
+        assert_eq!(
+            errors,
+            vec![ParseError::new(
+                range_on_lines(3, 4, 3),
+                ParseErrorType::ValidationError(ERROR_TYPE_CONTAINS_NEWLINE.to_string()),
+            )]
+        );
+        assert_eq!(
+            exo.checks[0].sequence[0],
+            TermAction::Type("John".to_string())
+        );
+    }
 }
@@ -1,14 +1,69 @@
 pub mod course;
 pub mod exo;
+pub mod project;
 pub mod skill;
+pub mod validation;
 
 pub use dy;
+use dy::generic::{GenericParseResult, SpecRegistry};
+use dy::spec::ValidDYSpec;
 
 pub use course::parse_course;
-pub use exo::parse_exo;
+pub use exo::{parse_exo, parse_exo_with_options};
+pub use project::{parse_exo_file, parse_skills_file};
 pub use skill::parse_skills;
+pub use validation::validate_course_tree;
 
 // The PLX spec define that course file can only be described inside a `course.dy` and that skills only inside a `skills.dy`
 pub const COURSE_FILE: &str = "course.dy";
 pub const SKILLS_FILE: &str = "skills.dy";
 pub const EXO_FILE: &str = "exo.dy";
+
+/// A registry of the PLX specs associated to their file name
+pub fn plx_registry() -> SpecRegistry<'static> {
+    let mut registry = SpecRegistry::new();
+    registry
+        .register(
+            COURSE_FILE,
+            ValidDYSpec::new(course::COURSE_SPEC).expect("COURSE_SPEC is invalid !"),
+        )
+        .register(
+            SKILLS_FILE,
+            ValidDYSpec::new(skill::SKILLS_SPEC).expect("SKILLS_SPEC is invalid !"),
+        )
+        .register(
+            EXO_FILE,
+            ValidDYSpec::new(exo::EXO_SPEC).expect("EXO_SPEC is invalid !"),
+        );
+    registry
+}
+
+/// Parse any PLX file into generic values, the spec is chosen from the file name of the given path
+pub fn parse_auto(path: &str, content: &str) -> Result<GenericParseResult, String> {
+    plx_registry().parse_auto(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_auto;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_auto_picks_the_spec_from_the_file_name() {
+        let course =
+            parse_auto("prg1/course.dy", "course PRG1\ncode PRG1\ngoal Learn C++").unwrap();
+        assert_eq!(course.errors, vec![]);
+        assert_eq!(course.items[0].key, "course");
+
+        let exo = parse_auto(
+            "prg1/intro/hello/exo.dy",
+            "exo Hello\ncheck it works\nsee hello",
+        )
+        .unwrap();
+        assert_eq!(exo.errors, vec![]);
+        assert_eq!(exo.items[0].key, "exo");
+        assert_eq!(exo.items[0].children[0].key, "check");
+
+        assert!(parse_auto("prg1/readme.md", "# PRG1").is_err());
+    }
+}
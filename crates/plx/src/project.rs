@@ -0,0 +1,276 @@
+/// Parsing of PLX files from the disk, where references to other files (`include`, `see @file`) can be resolved
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use dy::{
+    ParseResult,
+    error::{ParseError, ParseErrorType},
+    range_on_line_with_length,
+};
+
+use crate::{
+    exo::{DYExo, TermAction, parse_exo, see_action},
+    skill::{DYSkill, SkillsFileBlock, parse_skills_file_blocks},
+};
+
+/// Parse the exo file at the given path, the `see @file` are loaded relative to the exo file
+/// and replaced by a See or a SeeBlock with the trimmed content. Missing files are reported as
+/// errors on the file reference and stay a SeeFile.
+pub fn parse_exo_file(path: &Path) -> std::io::Result<ParseResult<DYExo<'static>>> {
+    let content = fs::read_to_string(path)?;
+    let result = parse_exo(&Some(path.to_string_lossy().to_string()), &content);
+    // The content is dropped at the end, the exos cannot borrow it
+    let mut result = ParseResult {
+        items: result.items.into_iter().map(DYExo::into_owned).collect(),
+        errors: result.errors,
+        some_file_path: result.some_file_path,
+        some_file_content: None,
+        doc_comments: result.doc_comments,
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let see_files = result
+        .items
+        .iter_mut()
+        .flat_map(|exo| exo.checks.iter_mut())
+        .flat_map(|check| check.sequence.iter_mut());
+    for action in see_files {
+        let TermAction::SeeFile(file, range) = action else {
+            continue;
+        };
+        match fs::read_to_string(dir.join(&*file)) {
+            Ok(expected) => *action = see_action(expected.trim().to_string()),
+            Err(_) => result.errors.push(ParseError::new(
+                *range,
+                ParseErrorType::ValidationError(format!(
+                    "The expected output file '{file}' doesn't exist"
+                )),
+            )),
+        }
+    }
+    result.errors.sort();
+    if !result.errors.is_empty() {
+        result.some_file_content = Some(content);
+    }
+    Ok(result)
+}
+
+/// Parse the skills file at the given path, the skills of included files are inserted at the
+/// position of their `include`. Missing files and include cycles are reported as errors on the
+/// line of the `include`. Errors in included files keep their range, with the path of the
+/// included file in their `some_file`.
+pub fn parse_skills_file(path: &Path) -> std::io::Result<ParseResult<DYSkill>> {
+    let content = fs::read_to_string(path)?;
+    let some_file = Some(path.to_string_lossy().to_string());
+    let mut visited = vec![path.canonicalize()?];
+    let (items, mut errors, doc_comments) =
+        parse_skills_with_includes(path, &content, &mut visited);
+    errors.sort();
+    let some_file_content = if errors.is_empty() {
+        None
+    } else {
+        Some(content)
+    };
+    Ok(ParseResult {
+        items,
+        errors,
+        some_file_path: some_file,
+        some_file_content,
+        doc_comments,
+    })
+}
+
+/// The `visited` files are the chain of files including the current one, to detect cycles. The
+/// doc comments of the current file are returned, not the ones of included files.
+fn parse_skills_with_includes(
+    path: &Path,
+    content: &str,
+    visited: &mut Vec<PathBuf>,
+) -> (Vec<DYSkill>, Vec<ParseError>, Vec<String>) {
+    let result = parse_skills_file_blocks(&Some(path.to_string_lossy().to_string()), content);
+    let mut errors = result.errors;
+    let mut skills = Vec::new();
+    for item in result.items {
+        let (included_path, line) = match item {
+            SkillsFileBlock::Skill(skill) => {
+                skills.push(skill);
+                continue;
+            }
+            SkillsFileBlock::Include(included_path, line) => (included_path, line),
+        };
+        let line_length = content
+            .lines()
+            .nth(line as usize)
+            .unwrap_or_default()
+            .chars()
+            .count() as u32;
+        let mut include_error = |message: String| {
+            errors.push(ParseError::new(
+                range_on_line_with_length(line, line_length),
+                ParseErrorType::ValidationError(message),
+            ))
+        };
+
+        let full_path = path.parent().unwrap_or(Path::new("")).join(&included_path);
+        let Ok(canonical_path) = full_path.canonicalize() else {
+            include_error(format!("The included file '{included_path}' doesn't exist"));
+            continue;
+        };
+        if visited.contains(&canonical_path) {
+            include_error(format!(
+                "Include cycle detected, '{included_path}' is already including this file"
+            ));
+            continue;
+        }
+        let Ok(included_content) = fs::read_to_string(&full_path) else {
+            include_error(format!(
+                "The included file '{included_path}' cannot be read"
+            ));
+            continue;
+        };
+
+        visited.push(canonical_path);
+        let (included_skills, included_errors, _) =
+            parse_skills_with_includes(&full_path, &included_content, visited);
+        visited.pop();
+        skills.extend(included_skills);
+        // Errors of files included by the included file already have their file
+        errors.extend(included_errors.into_iter().map(|mut error| {
+            if error.some_file.is_none() {
+                error.some_file = Some(full_path.to_string_lossy().to_string());
+            }
+            error
+        }));
+    }
+    (skills, errors, result.doc_comments)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use dy::{
+        error::{ParseError, ParseErrorType},
+        range_on_line_part, range_on_line_with_length,
+    };
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        exo::TermAction,
+        project::{parse_exo_file, parse_skills_file},
+    };
+
+    #[test]
+    fn test_see_file_is_loaded_relative_to_the_exo() {
+        let dir = std::env::temp_dir().join("plx_test_see_file_is_loaded_relative_to_the_exo");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("exo.dy"),
+            // The reference in the code snippet of the instruction is not a see
+            "exo Greetings\n```\nsee @example.txt\n```\ncheck it greets\nsee @out.txt\ncheck it fails\nsee @missing.txt",
+        )
+        .unwrap();
+        fs::write(dir.join("out.txt"), "Hello John\nBye\n").unwrap();
+
+        let result = parse_exo_file(&dir.join("exo.dy")).unwrap();
+        let checks = &result.items[0].checks;
+        assert_eq!(
+            checks[0].sequence,
+            vec![TermAction::SeeBlock(vec![
+                "Hello John".to_string(),
+                "Bye".to_string()
+            ])]
+        );
+        assert_eq!(
+            checks[1].sequence,
+            vec![TermAction::SeeFile(
+                "missing.txt".to_string(),
+                range_on_line_part(7, 4, 16)
+            )]
+        );
+        // The range is not serialized
+        assert_eq!(
+            serde_json::to_string(&checks[1].sequence).unwrap(),
+            r#"[{"SeeFile":"missing.txt"}]"#
+        );
+        assert_eq!(
+            result.errors,
+            vec![ParseError::new(
+                range_on_line_part(7, 4, 16),
+                ParseErrorType::ValidationError(
+                    "The expected output file 'missing.txt' doesn't exist".to_string()
+                ),
+            )]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_included_skills_are_merged() {
+        let dir = std::env::temp_dir().join("plx_test_included_skills_are_merged");
+        fs::create_dir_all(dir.join("more")).unwrap();
+        fs::write(
+            dir.join("skills.dy"),
+            "skill Classes\ndir classes\ninclude more/ops.dy\nskill Templates\ndir templates",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("more/ops.dy"),
+            "skill Operators\ndir ops\nskill Bits\ndir bits",
+        )
+        .unwrap();
+
+        let result = parse_skills_file(&dir.join("skills.dy")).unwrap();
+        assert_eq!(result.errors, vec![]);
+        let names: Vec<&str> = result.items.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Classes", "Operators", "Bits", "Templates"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_and_cyclic_includes_are_errors() {
+        let dir = std::env::temp_dir().join("plx_test_missing_and_cyclic_includes_are_errors");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("skills.dy"),
+            "skill Classes\ndir classes\ninclude other.dy\ninclude missing.dy",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("other.dy"),
+            "skill Operators\ndir ops\ninclude skills.dy",
+        )
+        .unwrap();
+
+        let result = parse_skills_file(&dir.join("skills.dy")).unwrap();
+        let names: Vec<&str> = result.items.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Classes", "Operators"]);
+        assert_eq!(
+            result.errors,
+            vec![
+                ParseError {
+                    some_file: Some(dir.join("other.dy").to_string_lossy().to_string()),
+                    ..ParseError::new(
+                        range_on_line_with_length(2, 17),
+                        ParseErrorType::ValidationError(
+                            "Include cycle detected, 'skills.dy' is already including this file"
+                                .to_string(),
+                        ),
+                    )
+                },
+                ParseError::new(
+                    range_on_line_with_length(3, 18),
+                    ParseErrorType::ValidationError(
+                        "The included file 'missing.dy' doesn't exist".to_string()
+                    ),
+                ),
+            ]
+        );
+        // The error of the included file is shown at its position in this file
+        let other_position = format!("{}:2:0", dir.join("other.dy").to_string_lossy());
+        assert!(format!("{result}").contains(&other_position));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -12,18 +12,35 @@ use serde::Serialize;
 pub struct DYSkill {
     pub name: String,
     pub description: String,
-    /// the directory where associated exos are stored, the existance of this directory cannot be checked by the parser as it doesn't touch the file system !
-    pub directory: String,
+    /// the directories where associated exos are stored, in the order of the `dir` keys. The existance of these directories cannot be checked by the parser as it doesn't touch the file system !
+    pub directories: Vec<String>,
     /// Note: subskills are not supported by PLX at the moment, they are just ignored
     pub subskills: Vec<DYSkill>,
 }
+
+impl DYSkill {
+    /// The first directory of the skill, or an empty string if there is none
+    pub fn directory(&self) -> &str {
+        self.directories.first().map_or("", |d| d.as_str())
+    }
+}
+
+/// Whether the given directory is relative and stays inside the course, like `classes` or
+/// `ops/bits` but not `/tmp` or `../other`
+fn is_safe_relative_path(dir: &str) -> bool {
+    !dir.is_empty()
+        && !dir.starts_with('/')
+        && !dir.starts_with('\\')
+        && !dir.contains(':')
+        && dir.split(['/', '\\']).all(|component| component != "..")
+}
 const DIR_KEYSPEC: &KeySpec = &KeySpec {
     id: "dir",
-    desc: "The directory where exos of this skill are stored. This directory must be unique among listed skills.",
+    desc: "The directory where exos of this skill are stored, relative to the course. This directory must be unique among listed skills. This key can be repeated when exos are stored in several directories.",
     subkeys: &[],
     vt: ValueType::SingleLine,
-    once: true,
     required: true,
+    ..KeySpec::DEFAULT
 };
 // TODO: how to support dir also for subskill ? this is detected as a duplicated keyspec !
 // For now, PLX doesn't support subskills so we will just ignore them when converting DYSkill to Skill
@@ -32,56 +49,97 @@ const SUBSKILL_KEYSPEC: &KeySpec = &KeySpec {
     desc: "The subskill is the same as a skill but must be more specific and focused.",
     subkeys: &[],
     vt: ValueType::Multiline,
-    once: false,
-    required: false,
+    ..KeySpec::DEFAULT
 };
 const SKILL_KEYSPEC: &KeySpec = &KeySpec {
     id: "skill",
     desc: "The skill is describing what students are expected to be able to do. Subskills can be used to define more specific inner skills.\nThe first line is the skill name and following lines define the details of the skill.",
     subkeys: &[SUBSKILL_KEYSPEC, DIR_KEYSPEC],
     vt: ValueType::Multiline,
-    once: false,
     required: true,
+    ..KeySpec::DEFAULT
 };
-pub const SKILLS_SPEC: &DYSpec = &[SKILL_KEYSPEC];
+const INCLUDE_KEYSPEC: &KeySpec = &KeySpec {
+    id: "include",
+    desc: "Include the skills of another file, given by its path relative to the current file. Includes are only resolved when parsing a skills file from the disk.",
+    subkeys: &[],
+    vt: ValueType::SingleLine,
+    ..KeySpec::DEFAULT
+};
+pub const SKILLS_SPEC: &DYSpec = &[SKILL_KEYSPEC, INCLUDE_KEYSPEC];
 
 impl<'a> FromDYBlock<'a> for DYSkill {
     fn from_block_with_validation(block: &Block<'a>) -> (Vec<ParseError>, DYSkill) {
         let mut errors = Vec::new();
         let mut skill = DYSkill::default();
-        // The first line is the name, the following ones are the description
-        (skill.name, skill.description) = block.get_text_with_joined_splits_at(1);
+        // The first non empty line is the name, the following ones are the description
+        (skill.name, skill.description) =
+            block.get_text_with_joined_splits_at(block.name_line_index() + 1);
 
-        for subblock in block.subblocks.iter() {
-            let id = subblock.key.id;
-            if id == DIR_KEYSPEC.id {
-                skill.directory = subblock.get_joined_text()
-            }
-            if id == SUBSKILL_KEYSPEC.id {
-                // Make sure subskill value is not empty
-                if subblock.get_joined_text().is_empty() {
-                    errors.push(ParseError {
-                        range: range_on_line_part(
-                            subblock.range.start.line,
-                            SUBSKILL_KEYSPEC.id.len() as u32,
-                            SUBSKILL_KEYSPEC.id.len() as u32,
-                        ),
-                        error: ParseErrorType::MissingRequiredValue(
-                            SUBSKILL_KEYSPEC.id.to_string(),
-                        ),
-                    });
+        for dir_block in block.subblocks_with(DIR_KEYSPEC.id) {
+            match dir_block.require_single_line() {
+                Ok(directory) => {
+                    if !is_safe_relative_path(&directory) {
+                        errors.push(ParseError::new(
+                            dir_block.range,
+                            ParseErrorType::ValidationError(format!(
+                                "The directory '{directory}' must be a relative path inside the course"
+                            )),
+                        ));
+                    }
+                    skill.directories.push(directory)
                 }
-
-                let (suberrors, subentity) = DYSkill::from_block_with_validation(subblock);
-                skill.subskills.push(subentity);
-                errors.extend(suberrors);
+                Err(error) => errors.push(*error),
             }
         }
+        for subblock in block.subblocks_with(SUBSKILL_KEYSPEC.id) {
+            // Make sure subskill value is not empty
+            if subblock.text_is_empty() {
+                errors.push(ParseError::new(
+                    range_on_line_part(
+                        subblock.range.start.line,
+                        SUBSKILL_KEYSPEC.id.len() as u32,
+                        SUBSKILL_KEYSPEC.id.len() as u32,
+                    ),
+                    ParseErrorType::MissingRequiredValue(SUBSKILL_KEYSPEC.id.to_string()),
+                ));
+            }
+
+            let (suberrors, subentity) = DYSkill::from_block_with_validation(subblock);
+            skill.subskills.push(subentity);
+            errors.extend(suberrors);
+        }
+        // All errors are inside this skill, including the errors of the subskills
+        for error in errors.iter_mut() {
+            error.prefix_path(format!("{} '{}'", block.key.id, skill.name));
+        }
         (errors, skill)
     }
 }
 
-pub fn parse_skills(some_file: &Option<String>, content: &str) -> ParseResult<DYSkill> {
+/// A top-level block of a skills file, includes are resolved by project::parse_skills_file()
+pub(crate) enum SkillsFileBlock {
+    Skill(DYSkill),
+    /// The path to include and the line of the `include`
+    Include(String, u32),
+}
+
+impl<'a> FromDYBlock<'a> for SkillsFileBlock {
+    fn from_block_with_validation(block: &Block<'a>) -> (Vec<ParseError>, SkillsFileBlock) {
+        if block.key.id == INCLUDE_KEYSPEC.id {
+            let include = SkillsFileBlock::Include(block.get_joined_text(), block.range.start.line);
+            (Vec::new(), include)
+        } else {
+            let (errors, skill) = DYSkill::from_block_with_validation(block);
+            (errors, SkillsFileBlock::Skill(skill))
+        }
+    }
+}
+
+pub(crate) fn parse_skills_file_blocks(
+    some_file: &Option<String>,
+    content: &str,
+) -> ParseResult<SkillsFileBlock> {
     parse_with_spec(
         &ValidDYSpec::new(SKILLS_SPEC).expect("SKILLS_SPEC is invalid !"),
         some_file,
@@ -89,6 +147,25 @@ pub fn parse_skills(some_file: &Option<String>, content: &str) -> ParseResult<DY
     )
 }
 
+/// Parse the skills of the given content, `include` are ignored as they need a file system
+pub fn parse_skills(some_file: &Option<String>, content: &str) -> ParseResult<DYSkill> {
+    let result = parse_skills_file_blocks(some_file, content);
+    ParseResult {
+        items: result
+            .items
+            .into_iter()
+            .filter_map(|item| match item {
+                SkillsFileBlock::Skill(skill) => Some(skill),
+                SkillsFileBlock::Include(..) => None,
+            })
+            .collect(),
+        errors: result.errors,
+        some_file_path: result.some_file_path,
+        some_file_content: result.some_file_content,
+        doc_comments: result.doc_comments,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use dy::{
@@ -97,10 +174,53 @@ mod tests {
         range_on_line_part,
     };
 
+    use dy::{
+        FromDYBlock,
+        lexer::tokenize_into_lines,
+        parser::build_blocks_tree,
+        spec::{DYSpec, KeySpec, ValidDYSpec, ValueType},
+    };
+
     use crate::skill::{DYSkill, parse_skills};
 
     use pretty_assertions::assert_eq;
 
+    static NESTED_SUBSKILL_KEYSPEC: KeySpec = KeySpec {
+        id: "subskill",
+        desc: "test",
+        subkeys: &[&NESTED_SUBSKILL_KEYSPEC],
+        vt: ValueType::Multiline,
+        ..KeySpec::DEFAULT
+    };
+    static NESTED_SKILL_KEYSPEC: KeySpec = KeySpec {
+        id: "skill",
+        desc: "test",
+        subkeys: &[&NESTED_SUBSKILL_KEYSPEC],
+        vt: ValueType::Multiline,
+        required: true,
+        ..KeySpec::DEFAULT
+    };
+
+    #[test]
+    fn test_errors_of_nested_subskills_have_a_path() {
+        let spec: &DYSpec = &[&NESTED_SKILL_KEYSPEC];
+        let spec = ValidDYSpec::new(spec).unwrap();
+        // Each subskill is nested in the previous one as the key is its own subkey
+        let lines = tokenize_into_lines(&spec, "skill A\nsubskill B\nsubskill\n");
+        let (blocks, _) = build_blocks_tree(&spec, lines);
+        let (errors, _) = DYSkill::from_block_with_validation(&blocks[0]);
+        assert_eq!(
+            errors,
+            vec![ParseError {
+                range: range_on_line_part(2, 8, 8),
+                error: ParseErrorType::MissingRequiredValue("subskill".to_string()),
+                path: vec!["skill 'A'".to_string(), "subskill 'B'".to_string()],
+                some_file: None,
+                severity: None,
+            }]
+        );
+    }
+
     #[test]
     fn test_can_parse_simple_skills() {
         let text = "
@@ -129,45 +249,46 @@ subskill Redéfinition d'opérateurs
             ParseResult {
                 some_file_path: some_file.clone(),
                 some_file_content: None,
+                doc_comments: vec![],
         items: vec![
             DYSkill {
                 name: "Classes".to_string(),
-                directory: "classes".to_string(),
+                directories: vec!["classes".to_string()],
                 description: "".to_string(),
                 subskills: vec![],
             },
             DYSkill {
                 name: "Opérateurs".to_string(),
-                directory: "ops".to_string(),
+                directories: vec!["ops".to_string()],
                 description: "Maitriser l'usage de **tous les opérateurs utiles**, pour manipuler\ndes nombres, des bits, ou encore des flux. Redéfinir les opérateurs\nexistants pour nos classes.".to_string(),
                 subskills: vec![
                     DYSkill {
                         name: "Calculs simples".to_string(),
-                        directory: "".to_string(),
+                        directories: vec![],
                         description: "Juste des calculs de math !".to_string(),
                         subskills: vec![],
                     },
                     DYSkill {
                         name: "Calculs avancés et précision".to_string(),
-                        directory: "".to_string(),
+                        directories: vec![],
                         description: "".to_string(),
                         subskills: vec![],
                     },
                     DYSkill {
                         name: "Opérateurs de flux".to_string(),
-                        directory: "".to_string(),
+                        directories: vec![],
                         description: "".to_string(),
                         subskills: vec![],
                     },
                     DYSkill {
                         name: "Manipulation de bits".to_string(),
-                        directory: "".to_string(),
+                        directories: vec![],
                         description: "".to_string(),
                         subskills: vec![],
                     },
                     DYSkill {
                         name: "Redéfinition d'opérateurs".to_string(),
-                        directory: "".to_string(),
+                        directories: vec![],
                         description: "".to_string(),
                         subskills: vec![],
                     },
@@ -179,6 +300,35 @@ subskill Redéfinition d'opérateurs
         )
     }
 
+    #[test]
+    fn test_dir_can_be_repeated() {
+        let text = "skill Classes
+dir classes
+dir more/classes
+skill Unsafe
+dir ../outside
+";
+        let result = parse_skills(&None, text);
+        assert_eq!(
+            result.items[0].directories,
+            vec!["classes".to_string(), "more/classes".to_string()]
+        );
+        assert_eq!(result.items[0].directory(), "classes");
+        assert_eq!(
+            result.errors,
+            vec![ParseError {
+                range: range_on_line_part(4, 0, 14),
+                error: ParseErrorType::ValidationError(
+                    "The directory '../outside' must be a relative path inside the course"
+                        .to_string()
+                ),
+                path: vec!["skill 'Unsafe'".to_string()],
+                some_file: None,
+                severity: None,
+            }]
+        );
+    }
+
     #[test]
     fn test_can_detect_subskill_missing_value() {
         let text = "skill A
@@ -194,13 +344,14 @@ subskill
             ParseResult {
                 some_file_path: some_file.clone(),
                 some_file_content: Some(text.to_string()),
+                doc_comments: vec![],
                 items: vec![DYSkill {
                     name: "A".to_string(),
-                    directory: "a".to_string(),
+                    directories: vec!["a".to_string()],
                     description: "great desc".to_string(),
                     subskills: vec![DYSkill {
                         name: "".to_string(),
-                        directory: "".to_string(),
+                        directories: vec![],
                         description: "".to_string(),
                         subskills: vec![],
                     }],
@@ -208,6 +359,9 @@ subskill
                 errors: vec![ParseError {
                     range: range_on_line_part(3, 8, 8),
                     error: ParseErrorType::MissingRequiredValue("subskill".to_string()),
+                    path: vec!["skill 'A'".to_string()],
+                    some_file: None,
+                    severity: None,
                 }]
             }
         )
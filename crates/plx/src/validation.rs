@@ -0,0 +1,165 @@
+/// Validation of a whole PLX course tree once all files have been parsed. This is only domain
+/// logic, the file system is not accessed, the existence of directories is not checked.
+use std::path::Path;
+
+use dy::{
+    error::{ParseError, ParseErrorType},
+    range_on_line_with_length,
+};
+
+use crate::{course::DYCourse, exo::DYExo, skill::DYSkill};
+
+/// Check the referential integrity between the course, its skills and its exos.
+/// The exos are given with their directory relative to the course, like `classes/constructors`,
+/// that starts with the `dir` of the skill they belong to, which can be nested like
+/// `more/classes`. It makes sure that
+/// - every skill directory is used by at least one exo
+/// - every exo belongs to the directory of a declared skill
+/// - there are no two exos with the same name in the same skill
+///
+/// As these errors are not tied to a specific file, their range is at the start of the file.
+pub fn validate_course_tree(
+    course: &DYCourse,
+    skills: &[DYSkill],
+    exos: &[(String, DYExo<'_>)],
+) -> Vec<ParseError> {
+    let mut errors = Vec::new();
+    let error = |message: String| {
+        ParseError::new(
+            range_on_line_with_length(0, 0),
+            ParseErrorType::ValidationError(message),
+        )
+    };
+    // The deepest skill directory containing the exo, if any
+    let skill_dir_of = |exo_path: &str| {
+        skills
+            .iter()
+            .flat_map(|skill| skill.directories.iter())
+            .filter(|directory| Path::new(exo_path).starts_with(directory))
+            .max_by_key(|directory| directory.len())
+    };
+
+    for skill in skills {
+        for directory in skill.directories.iter() {
+            if !exos
+                .iter()
+                .any(|(path, _)| Path::new(path).starts_with(directory))
+            {
+                errors.push(error(format!(
+                    "The directory '{}' of the skill '{}' doesn't contain any exo of the course '{}'",
+                    directory, skill.name, course.code
+                )));
+            }
+        }
+    }
+
+    for (index, (path, exo)) in exos.iter().enumerate() {
+        let skill_dir = skill_dir_of(path);
+        if skill_dir.is_none() {
+            errors.push(error(format!(
+                "The exo '{}' in '{path}' doesn't belong to the directory of a declared skill",
+                exo.name
+            )));
+        }
+        if exos[..index].iter().any(|(other_path, other)| {
+            skill_dir.is_some() && skill_dir_of(other_path) == skill_dir && other.name == exo.name
+        }) {
+            errors.push(error(format!(
+                "The exo '{}' in '{path}' has the same name as another exo of the same skill",
+                exo.name
+            )));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use dy::error::{ParseError, ParseErrorType};
+    use dy::range_on_line_with_length;
+    use pretty_assertions::assert_eq;
+
+    use crate::{course::DYCourse, exo::DYExo, skill::DYSkill, validate_course_tree};
+
+    fn get_tree() -> (DYCourse, Vec<DYSkill>, Vec<(String, DYExo<'static>)>) {
+        let course = DYCourse {
+            name: "Programmation 1".to_string(),
+            code: "PRG1".to_string(),
+            goal: "Learn C++".to_string(),
+        };
+        let skills = vec![
+            DYSkill {
+                name: "Classes".to_string(),
+                directories: vec!["classes".to_string()],
+                ..Default::default()
+            },
+            DYSkill {
+                name: "Operators".to_string(),
+                directories: vec!["ops".to_string()],
+                ..Default::default()
+            },
+        ];
+        let exo = |name: &str| DYExo {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let exos = vec![
+            ("classes/constructors".to_string(), exo("Constructors")),
+            ("classes/destructors".to_string(), exo("Destructors")),
+            ("ops/bits".to_string(), exo("Bits")),
+        ];
+        (course, skills, exos)
+    }
+
+    #[test]
+    fn test_valid_course_tree_has_no_error() {
+        let (course, skills, exos) = get_tree();
+        assert_eq!(validate_course_tree(&course, &skills, &exos), vec![]);
+    }
+
+    #[test]
+    fn test_nested_skill_dir_contains_its_exos() {
+        let (course, mut skills, mut exos) = get_tree();
+        skills[1].directories = vec!["more/ops".to_string()];
+        exos[2].0 = "more/ops/bits".to_string();
+        assert_eq!(validate_course_tree(&course, &skills, &exos), vec![]);
+    }
+
+    #[test]
+    fn test_exo_name_collision_in_skill_is_detected() {
+        let (course, skills, mut exos) = get_tree();
+        exos[1].1.name = "Constructors".to_string();
+        assert_eq!(
+            validate_course_tree(&course, &skills, &exos),
+            vec![ParseError::new(
+                range_on_line_with_length(0, 0),
+                ParseErrorType::ValidationError(
+                    "The exo 'Constructors' in 'classes/destructors' has the same name as another exo of the same skill".to_string()
+                ),
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unknown_skill_dir_and_unused_skill_are_detected() {
+        let (course, skills, mut exos) = get_tree();
+        exos[2].0 = "operators/bits".to_string();
+        assert_eq!(
+            validate_course_tree(&course, &skills, &exos),
+            vec![
+                ParseError::new(
+                    range_on_line_with_length(0, 0),
+                    ParseErrorType::ValidationError(
+                        "The directory 'ops' of the skill 'Operators' doesn't contain any exo of the course 'PRG1'".to_string()
+                    ),
+                ),
+                ParseError::new(
+                    range_on_line_with_length(0, 0),
+                    ParseErrorType::ValidationError(
+                        "The exo 'Bits' in 'operators/bits' doesn't belong to the directory of a declared skill".to_string()
+                    ),
+                ),
+            ]
+        );
+    }
+}